@@ -8,25 +8,56 @@
 //!
 //! The AI manages three major responsibilities:
 //!
-//! 1. **Lifecycle control** via `start()` and `stop()`.
+//! 1. **Lifecycle control** via [`on_start`](PlanetAI::on_start) and
+//!    [`on_stop`](PlanetAI::on_stop), called by the planet runtime on
+//!    `StartPlanetAI` and `StopPlanetAI`.
 //!    - When stopped, the AI rejects all messages and produces no output.
 //! 2. **Message handling**
-//!    - [`handle_orchestrator_msg`] processes messages from the orchestrator,
-//!      including sunrays, internal state requests, and others.
-//!    - [`handle_explorer_msg`] processes queries and requests from explorers
+//!    - The `common_game` planet runtime dispatches every orchestrator
+//!      message to a dedicated handler: sunrays to
+//!      [`handle_sunray`](PlanetAI::handle_sunray), asteroids to
+//!      [`handle_asteroid`](PlanetAI::handle_asteroid) and internal state
+//!      requests to
+//!      [`handle_internal_state_req`](PlanetAI::handle_internal_state_req).
+//!      There is no catch-all orchestrator handler that could drop an
+//!      `Asteroid`: its `AsteroidAck` always carries the result of
+//!      `handle_asteroid`.
+//!    - [`handle_explorer_msg`](PlanetAI::handle_explorer_msg) processes queries and requests from explorers
 //!      related to energy, basic resources, supported combinations, and complex
 //!      combinations.
 //! 3. **Asteroid response logic**
-//!    - [`handle_asteroid`] launches an existing rocket or attempts to build
+//!    - [`handle_asteroid`](PlanetAI::handle_asteroid) launches an existing rocket or attempts to build
 //!      and launch a new one.
 //!
 //! # AI Runtime Model
 //!
-//! The AI maintains an internal `running: bool` flag.
-//! - When `running == false`, the planet is considered inactive and **all
-//!   incoming messages are ignored**.
-//! - The orchestrator controls this state via `StartPlanetAI` and
-//!   `StopPlanetAI` messages.
+//! The AI is in one of the [`PlanetMode`]s.
+//! - When [`PlanetMode::Stopped`], the planet is considered inactive and
+//!   **all incoming messages are ignored**: the `common_game` runtime
+//!   answers them with `Stopped` without calling the AI. [`Trip`](crate::Trip)
+//!   keeps the sunrays among them (up to a fixed capacity) and replays them
+//!   once the planet starts again.
+//! - When [`PlanetMode::Maintenance`], the planet answers read-only queries
+//!   but refuses to generate, combine or build rockets; sunrays are buffered
+//!   by the AI and replayed once it runs again, and asteroids are only met
+//!   with rockets already built.
+//! - The orchestrator switches between stopped and running via
+//!   `StartPlanetAI` and `StopPlanetAI` messages; maintenance is toggled on
+//!   a running planet through
+//!   [`TripHandle::set_maintenance`](crate::TripHandle::set_maintenance).
+//!   Likewise, the generation rules of a running planet are narrowed
+//!   through
+//!   [`TripHandle::set_generation_rules`](crate::TripHandle::set_generation_rules).
+//!
+//! Stopping and restarting keeps everything the AI learned: activity
+//! counters, the rocket reserve, buffered sunrays and the explorers on the
+//! planet. Explorers that disconnected in the meantime are the exception:
+//! the [`Trip`](crate::Trip) relay notices when a reply to an explorer can
+//! no longer be delivered and makes the planet let go of it as if it had
+//! left, right away if the planet runs or on the next `StartPlanetAI`
+//! otherwise, which frees its quota and rate limit state. A
+//! [`TripHandle::reset`](crate::TripHandle::reset) clears all of it at the
+//! next stop or start instead, see [`AI::reset`].
 //!
 //! The planet never blocks inside the AI; blocking occurs only in the
 //! outer planet loop that receives messages from channels.
@@ -35,19 +66,44 @@
 //!
 //! The AI supports:
 //! - **Sunray absorption and energy cell charging**
-//! - **Rocket construction via charged cells**
-//! - **Internal state reporting**
-//! - **Basic resource handling for Oxygen**
-//! - **Fallback error reporting for unsupported combinations**
+//! - **Rocket construction via charged cells**, keeping a configurable
+//!   reserve of rockets for asteroid showers; sunrays reaching a fully
+//!   charged planet are stockpiled as extra rockets
+//! - **Internal state reporting** (charged cells and rocket availability)
+//! - **Basic resource generation for every configured generation rule**,
+//!   sharing scarce charged cells fairly among explorers
+//! - **Complex resource combination for the configured recipes**,
+//!   optionally advertising them only while a charged cell can power them
+//! - **Fallback error reporting for unsupported combinations and resources**
 //! - **Asteroid-triggered rocket launching**
+//! - **Periodic housekeeping**, refilling the rocket reserve while no
+//!   message arrives, when enabled on the [`Trip`](crate::Trip)
+//! - **Start grace period**, holding explorer requests back for a while
+//!   after a start; the [`Trip`](crate::Trip) relay enforces it, as the AI
+//!   cannot leave a request unanswered and come back to it later
+//!
+//! # Combination Errors
+//!
+//! A failed `CombineResourceRequest` hands the inputs back to the explorer
+//! together with a reason:
+//!
+//! - `unsupported_combination`: the planet has no recipe for the requested
+//!   complex resource.
+//! - `no_charged_cells`: the recipe is supported but no charged cell can
+//!   power it; retrying after the next sunray may succeed.
+//! - `dry_run`: the recipe is supported but the AI runs with
+//!   [`AiConfig::dry_run`], so nothing is combined.
+//!
+//! The input resources themselves cannot be insufficient: a
+//! [`ComplexResourceRequest`] only type-checks with the right inputs.
 //!
 //! # Unsupported Features (as of current version)
 //!
-//! The following message types are acknowledged but **not implemented** and
-//! return `None` (or panic if explicitly marked with `todo!()` in the code):
+//! The following are acknowledged by the planet runtime without the AI having
+//! a say:
 //!
-//! - Incoming and outgoing explorer routing requests
-//! - Complex resource generation beyond the Oxygen path
+//! - Rejecting incoming and outgoing explorer routing requests: the planet
+//!   runtime always acknowledges them, the AI only tracks the explorers
 //! - Planet kill event (currently ignored; real implementation should finalize
 //!   the planet's lifecycle)
 //!
@@ -56,8 +112,8 @@
 //! - The AI mutates [`PlanetState`] extensively (charging cells, building and
 //!   launching rockets, creating resources).
 //! - Logging is performed using the `log` crate.
-//! - No global state is modified, and the struct is `Send` + `Sync` via its
-//!   field structure.
+//! - No global state is modified, and the struct is `Send` via its field
+//!   structure; a rocket launch callback must be `Send` for that reason.
 //!
 //! # Protocol Guarantees
 //!
@@ -76,15 +132,441 @@
 
 use common_game::components::energy_cell::EnergyCell;
 use common_game::components::planet::DummyPlanetState;
-use common_game::components::planet::{PlanetAI, PlanetState};
+use common_game::components::planet::{PlanetAI, PlanetState, PlanetType};
 use common_game::components::resource::ComplexResourceRequest;
 use common_game::components::resource::{
-    BasicResource, BasicResourceType, Combinator, ComplexResource, Generator, GenericResource,
+    BasicResource, BasicResourceType, Combinator, ComplexResource, ComplexResourceType, Generator,
+    GenericResource,
 };
 use common_game::components::rocket::Rocket;
 use common_game::components::sunray::Sunray;
 use common_game::protocols::planet_explorer::{ExplorerToPlanet, PlanetToExplorer};
+use crossbeam_channel::Sender;
 use log::{debug, error, info, warn};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::resource_names;
+use crate::{AuditEvent, ComplexYields, ResourceYields};
+
+/// Activity mode of the [`AI`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum PlanetMode {
+    /// Every message is ignored, sunrays are buffered.
+    #[default]
+    Stopped,
+    /// Every message is handled.
+    Running,
+    /// Read-only queries are answered, requests that would change the
+    /// planet state are refused.
+    Maintenance,
+}
+
+/// Shared state of an [`AI`], kept by [`Trip`](crate::Trip) to watch and
+/// steer the AI while the planet runs on another thread.
+#[derive(Clone)]
+pub(crate) struct AiHandle {
+    pub(crate) stats: Arc<Mutex<AiStats>>,
+    /// Whether a running AI should switch to [`PlanetMode::Maintenance`].
+    pub(crate) maintenance: Arc<AtomicBool>,
+    /// Generation rules the AI should narrow the planet's own to.
+    pub(crate) generation_rules: Arc<Mutex<RuleOverride>>,
+    /// Resets requested over the planet's lifetime, see [`AI::reset`].
+    pub(crate) resets: Arc<AtomicU64>,
+    /// Whether a housekeeping round is due, see [`AI::housekeep`].
+    pub(crate) housekeeping: Arc<AtomicBool>,
+}
+
+/// Generation rules set through
+/// [`TripHandle::set_generation_rules`](crate::TripHandle::set_generation_rules).
+#[derive(Debug, Default)]
+pub(crate) struct RuleOverride {
+    /// Basic resources the planet may generate, among those of its own
+    /// rules; `None` for all of them.
+    pub(crate) rules: Option<HashSet<BasicResourceType>>,
+    /// Whether `rules` changed since the AI last applied them.
+    pub(crate) changed: bool,
+}
+
+/// Per-explorer state kept by the [`AI`] while an explorer is on the planet.
+///
+/// The explorer's `Sender<PlanetToExplorer>` is owned by the planet runtime,
+/// which delivers every response; the AI only receives explorer ids through
+/// [`PlanetAI::on_explorer_arrival`] and [`PlanetAI::on_explorer_departure`].
+#[derive(Default)]
+struct Explorer {
+    requests_served: u64,
+    /// Charged cells spent on this explorer's generation requests since the
+    /// last sunray.
+    cells_consumed: u32,
+    /// When the explorer last got a generated resource, as a value of
+    /// [`AI::serve_tick`]; `0` if it never did.
+    last_served: u64,
+    /// Sunrays left before the explorer's claim to a charged cell lapses,
+    /// see [`AI::defers_to_waiting`].
+    waiting: u32,
+    /// Requests the explorer may still send right away, see
+    /// [`Explorer::admit`].
+    tokens: f64,
+    /// When `tokens` was last brought up to date; `None` while the bucket
+    /// was never drawn from, i.e. is full.
+    refilled: Option<Instant>,
+}
+
+impl Explorer {
+    /// Takes one request's worth out of the explorer's token bucket.
+    ///
+    /// The bucket holds up to `limit.burst` tokens and regains
+    /// `limit.per_second` of them every second.
+    ///
+    /// # Returns
+    /// `false` if the bucket is empty and the request must be dropped.
+    fn admit(&mut self, limit: RateLimit, now: Instant) -> bool {
+        let burst = f64::from(limit.burst);
+        let tokens = self.refilled.map_or(burst, |last| {
+            let regained = now.duration_since(last).as_secs_f64() * f64::from(limit.per_second);
+            (self.tokens + regained).min(burst)
+        });
+        self.refilled = Some(now);
+        let admitted = tokens >= 1.0;
+        self.tokens = if admitted { tokens - 1.0 } else { tokens };
+        admitted
+    }
+}
+
+/// Recipe sets of the planet, enumerated on first use.
+///
+/// A planet's generation and combination rules are fixed when it is created,
+/// and an [`AI`] serves a single planet, so the sets only need invalidating
+/// when the generation rules are narrowed, see [`RecipeCache::restrict`].
+#[derive(Default)]
+struct RecipeCache {
+    resources: Option<HashSet<BasicResourceType>>,
+    combinations: Option<HashSet<ComplexResourceType>>,
+    /// Basic resources the generation rules are narrowed to, if any.
+    allowed: Option<HashSet<BasicResourceType>>,
+    /// Number of times a recipe set was enumerated.
+    #[cfg(test)]
+    enumerations: usize,
+}
+
+impl RecipeCache {
+    fn resources(&mut self, generator: &Generator) -> &HashSet<BasicResourceType> {
+        self.resources.get_or_insert_with(|| {
+            #[cfg(test)]
+            {
+                self.enumerations += 1;
+            }
+            let mut resources = generator.all_available_recipes();
+            if let Some(allowed) = &self.allowed {
+                resources.retain(|resource| allowed.contains(resource));
+            }
+            resources
+        })
+    }
+
+    /// Narrows the generation rules to `allowed`, or restores them with
+    /// `None`, dropping the cached basic resources.
+    fn restrict(&mut self, allowed: Option<HashSet<BasicResourceType>>) {
+        self.allowed = allowed;
+        self.resources = None;
+    }
+
+    fn combinations(&mut self, comb: &Combinator) -> &HashSet<ComplexResourceType> {
+        self.combinations.get_or_insert_with(|| {
+            #[cfg(test)]
+            {
+                self.enumerations += 1;
+            }
+            comb.all_available_recipes()
+        })
+    }
+}
+
+/// Policy used by the [`AI`] to pick which energy cell to use.
+///
+/// The policy applies both when charging a cell with a sunray (among the
+/// uncharged cells) and when discharging one to generate or combine resources
+/// or to build a rocket (among the charged cells).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum CellSelection {
+    /// Always pick the lowest-indexed eligible cell.
+    #[default]
+    FirstAvailable,
+    /// Pick the first eligible cell after the previously chosen one,
+    /// wrapping around, so wear is spread evenly across cells.
+    RoundRobin,
+    /// Pick the eligible cell whose last charge is the oldest.
+    LeastRecentlyCharged,
+}
+
+/// Rate at which a single explorer may send requests, see
+/// [`AiConfig::explorer_rate_limit`].
+///
+/// Each explorer gets a token bucket: a request spends a token, tokens come
+/// back at a steady rate, and a request finding the bucket empty is dropped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+    /// Requests an explorer may send at once, after a quiet period.
+    pub burst: u32,
+    /// Requests an explorer may keep sending every second.
+    pub per_second: u32,
+}
+
+/// Activity counters maintained by the [`AI`].
+///
+/// The counters are shared behind an `Arc<Mutex<_>>` so they can be read
+/// while the planet runs on another thread; see [`AI::stats`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct AiStats {
+    pub(crate) sunrays_received: u64,
+    pub(crate) rockets_built: u64,
+    pub(crate) rockets_launched: u64,
+    pub(crate) resources_generated: u64,
+    /// Resources generated by type, over the planet's lifetime.
+    pub(crate) yields: ResourceYields,
+    pub(crate) complex_resources_produced: u64,
+    /// Complex resources combined by recipe, over the planet's lifetime.
+    pub(crate) complex_yields: ComplexYields,
+    /// Explorer requests handled while running.
+    pub(crate) explorers_served: u64,
+    /// Rocket builds that failed on every cell tried, see
+    /// [`AI::try_build_rocket`].
+    pub(crate) rocket_build_failures: u64,
+    /// Rockets ready for launch, in the planet state and in the reserve, as
+    /// of the last `InternalStateRequest`.
+    pub(crate) stockpiled_rockets: usize,
+    /// Rockets in the AI's reserve, kept current unlike
+    /// `stockpiled_rockets`.
+    pub(crate) reserve_rockets: usize,
+    /// Charged energy cells as of the last `InternalStateRequest` or
+    /// asteroid.
+    pub(crate) charged_cells: usize,
+    /// Explorers currently on the planet.
+    pub(crate) explorers: usize,
+    /// Health left, see [`AI::take_hit`].
+    pub(crate) health: u32,
+    /// Explorer requests dropped by [`AiConfig::explorer_rate_limit`].
+    pub(crate) rate_limited: u64,
+}
+
+/// Callback run on the planet's thread for every rocket launched, see
+/// [`TripBuilder::on_rocket_launched`](crate::TripBuilder::on_rocket_launched).
+pub(crate) type RocketHook = Box<dyn Fn(&Rocket) + Send>;
+
+/// How a planet of a given type weathers an asteroid, see
+/// [`asteroid_defense`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct AsteroidDefense {
+    /// Share of [`AiConfig::asteroid_damage`] taken from an asteroid that
+    /// is not stopped, in percent.
+    pub(crate) damage_percent: u32,
+}
+
+/// Returns how a planet of `planet_type` weathers an asteroid.
+///
+/// Whether the planet survives is not decided here: the orchestrator
+/// destroys a planet whose `AsteroidAck` carries no rocket, so every type
+/// launches a single rocket whenever it has one. Planet types only differ
+/// in the health the AI tracks for an asteroid that is not stopped:
+/// - [`PlanetType::A`] takes every impact in full;
+/// - [`PlanetType::B`] cannot hold rockets, but its hull absorbs half of
+///   every impact;
+/// - [`PlanetType::C`] has a single energy cell and a brittle crust that
+///   takes half again as much from every impact;
+/// - [`PlanetType::D`] cannot hold rockets and takes every impact in full.
+pub(crate) const fn asteroid_defense(planet_type: PlanetType) -> AsteroidDefense {
+    match planet_type {
+        PlanetType::A | PlanetType::D => AsteroidDefense {
+            damage_percent: 100,
+        },
+        PlanetType::B => AsteroidDefense { damage_percent: 50 },
+        PlanetType::C => AsteroidDefense {
+            damage_percent: 150,
+        },
+    }
+}
+
+/// Policies followed by the [`AI`], fixed for the lifetime of a planet.
+///
+/// The same configuration can be cloned and handed to several planets, see
+/// [`TripBuilder::ai_config`](crate::TripBuilder::ai_config).
+///
+/// The AI never relies on randomness, and the [`Default`] configuration
+/// leaves out every policy that depends on when messages arrive: the same
+/// message sequence always leads to the same planet state. Tests that need
+/// this should keep [`cell_selection`](AiConfig::cell_selection) at its
+/// default and [`explorer_rate_limit`](AiConfig::explorer_rate_limit) and
+/// [`start_grace`](AiConfig::start_grace) off.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AiConfig {
+    /// Number of rockets to keep ready for asteroid defense.
+    pub rocket_reserve: usize,
+    /// Whether sunrays build rockets until the reserve is full. When
+    /// disabled, rockets are only built when an asteroid hits.
+    pub auto_build: bool,
+    /// Most rockets kept ready at once, counting the reserve and the extra
+    /// rockets stockpiled from overflowing sunrays. Past it, sunrays leave
+    /// the cells charged for resource generation.
+    pub max_rockets: usize,
+    /// Charged cells needed before auto-build spends one on a rocket, so
+    /// the first charges stay available for resource generation. `1`
+    /// builds as soon as a cell is charged.
+    pub build_threshold: usize,
+    /// Number of sunrays buffered while the planet is stopped or in
+    /// maintenance, each charging a cell once it runs again; when the buffer
+    /// is full further sunrays are lost. Sunrays reaching a stopped planet
+    /// are buffered by [`Trip`](crate::Trip), which the planet runtime
+    /// answers without calling the AI.
+    pub sunray_buffer_capacity: usize,
+    /// Policy used to pick which energy cell to charge or discharge.
+    pub cell_selection: CellSelection,
+    /// Rate limit applied to each explorer separately, or `None` for no
+    /// limit. Requests over the limit are dropped without an answer, so a
+    /// flooding explorer cannot keep the planet busy.
+    pub explorer_rate_limit: Option<RateLimit>,
+    /// Charged cells a single explorer may consume for generation between
+    /// two sunrays, or `None` for no limit.
+    pub generation_quota: Option<u32>,
+    /// Health the planet starts with.
+    pub max_health: u32,
+    /// Health lost to every asteroid met without a rocket. The default
+    /// follows the game: a single undefended asteroid destroys the planet.
+    pub asteroid_damage: u32,
+    /// Whether the AI only reports what it would do with sunrays and
+    /// generation or combination requests, leaving the planet state as it
+    /// is. Generation and combination requests are still answered, without
+    /// a resource, so explorers do not wait on them. Read-only queries are
+    /// answered as usual and asteroids are still met with the rockets
+    /// already built.
+    pub dry_run: bool,
+    /// Whether a `SupportedCombinationResponse` only lists the recipes the
+    /// planet can combine right now rather than all of them. Every recipe
+    /// needs a charged cell, so the list is empty until a sunray charges
+    /// one.
+    pub actionable_combinations: bool,
+    /// Time after the planet starts during which explorer requests wait in
+    /// their channel, answered in order once it passes, so the
+    /// orchestrator can finish wiring its explorers first. Orchestrator
+    /// messages are handled right away. Zero, the default, means no wait.
+    pub start_grace: Duration,
+}
+
+impl Default for AiConfig {
+    fn default() -> Self {
+        Self {
+            rocket_reserve: 1,
+            auto_build: true,
+            max_rockets: 3,
+            build_threshold: 1,
+            sunray_buffer_capacity: 8,
+            cell_selection: CellSelection::default(),
+            explorer_rate_limit: None,
+            generation_quota: None,
+            max_health: 100,
+            asteroid_damage: 100,
+            dry_run: false,
+            actionable_combinations: false,
+            start_grace: Duration::ZERO,
+        }
+    }
+}
+
+impl AiConfig {
+    /// Builds a configuration from `TRIP_*` environment variables, so a
+    /// deployment can tune its planets without recompiling.
+    ///
+    /// | Variable | Field |
+    /// |---|---|
+    /// | `TRIP_ROCKET_RESERVE` | [`rocket_reserve`](AiConfig::rocket_reserve) |
+    /// | `TRIP_AUTO_BUILD` | [`auto_build`](AiConfig::auto_build), `true` or `false` |
+    /// | `TRIP_MAX_ROCKETS` | [`max_rockets`](AiConfig::max_rockets) |
+    /// | `TRIP_BUILD_THRESHOLD` | [`build_threshold`](AiConfig::build_threshold) |
+    /// | `TRIP_SUNRAY_BUFFER_CAPACITY` | [`sunray_buffer_capacity`](AiConfig::sunray_buffer_capacity) |
+    /// | `TRIP_CELL_SELECTION` | [`cell_selection`](AiConfig::cell_selection): `first_available`, `round_robin` or `least_recently_charged` |
+    /// | `TRIP_RATE_LIMIT_BURST`, `TRIP_RATE_LIMIT_PER_SECOND` | [`explorer_rate_limit`](AiConfig::explorer_rate_limit), both or neither |
+    /// | `TRIP_GENERATION_QUOTA` | [`generation_quota`](AiConfig::generation_quota) |
+    /// | `TRIP_MAX_HEALTH` | [`max_health`](AiConfig::max_health) |
+    /// | `TRIP_ASTEROID_DAMAGE` | [`asteroid_damage`](AiConfig::asteroid_damage) |
+    /// | `TRIP_DRY_RUN` | [`dry_run`](AiConfig::dry_run), `true` or `false` |
+    /// | `TRIP_ACTIONABLE_COMBINATIONS` | [`actionable_combinations`](AiConfig::actionable_combinations), `true` or `false` |
+    /// | `TRIP_START_GRACE_MS` | [`start_grace`](AiConfig::start_grace), in milliseconds |
+    ///
+    /// Fields whose variable is unset keep their [`Default`] value, and so do
+    /// fields whose variable cannot be parsed, with a warning.
+    #[must_use]
+    pub fn from_env() -> Self {
+        Self::from_lookup(|name| std::env::var(name).ok())
+    }
+
+    /// Builds a configuration from the variables `lookup` returns, see
+    /// [`AiConfig::from_env`].
+    pub(crate) fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Self {
+        let defaults = Self::default();
+        let cell_selection = lookup("TRIP_CELL_SELECTION").and_then(|raw| match raw.trim() {
+            "first_available" => Some(CellSelection::FirstAvailable),
+            "round_robin" => Some(CellSelection::RoundRobin),
+            "least_recently_charged" => Some(CellSelection::LeastRecentlyCharged),
+            _ => {
+                warn!("TRIP_CELL_SELECTION={raw:?} is not a policy, keeping the default");
+                None
+            }
+        });
+        let explorer_rate_limit = match (
+            lookup("TRIP_RATE_LIMIT_BURST").is_some(),
+            lookup("TRIP_RATE_LIMIT_PER_SECOND").is_some(),
+        ) {
+            (false, false) => defaults.explorer_rate_limit,
+            (true, true) => env_value(&lookup, "TRIP_RATE_LIMIT_BURST")
+                .zip(env_value(&lookup, "TRIP_RATE_LIMIT_PER_SECOND"))
+                .map_or(defaults.explorer_rate_limit, |(burst, per_second)| {
+                    Some(RateLimit { burst, per_second })
+                }),
+            _ => {
+                warn!(
+                    "TRIP_RATE_LIMIT_BURST and TRIP_RATE_LIMIT_PER_SECOND go together, keeping the default"
+                );
+                defaults.explorer_rate_limit
+            }
+        };
+        Self {
+            rocket_reserve: env_value(&lookup, "TRIP_ROCKET_RESERVE")
+                .unwrap_or(defaults.rocket_reserve),
+            auto_build: env_value(&lookup, "TRIP_AUTO_BUILD").unwrap_or(defaults.auto_build),
+            max_rockets: env_value(&lookup, "TRIP_MAX_ROCKETS").unwrap_or(defaults.max_rockets),
+            build_threshold: env_value(&lookup, "TRIP_BUILD_THRESHOLD")
+                .unwrap_or(defaults.build_threshold),
+            sunray_buffer_capacity: env_value(&lookup, "TRIP_SUNRAY_BUFFER_CAPACITY")
+                .unwrap_or(defaults.sunray_buffer_capacity),
+            cell_selection: cell_selection.unwrap_or(defaults.cell_selection),
+            explorer_rate_limit,
+            generation_quota: env_value(&lookup, "TRIP_GENERATION_QUOTA")
+                .or(defaults.generation_quota),
+            max_health: env_value(&lookup, "TRIP_MAX_HEALTH").unwrap_or(defaults.max_health),
+            asteroid_damage: env_value(&lookup, "TRIP_ASTEROID_DAMAGE")
+                .unwrap_or(defaults.asteroid_damage),
+            dry_run: env_value(&lookup, "TRIP_DRY_RUN").unwrap_or(defaults.dry_run),
+            actionable_combinations: env_value(&lookup, "TRIP_ACTIONABLE_COMBINATIONS")
+                .unwrap_or(defaults.actionable_combinations),
+            start_grace: env_value(&lookup, "TRIP_START_GRACE_MS")
+                .map_or(defaults.start_grace, Duration::from_millis),
+        }
+    }
+}
+
+/// Parses the variable `name` returned by `lookup`, warning if it is set but
+/// invalid, see [`AiConfig::from_env`].
+fn env_value<T: FromStr>(lookup: &impl Fn(&str) -> Option<String>, name: &str) -> Option<T> {
+    let raw = lookup(name)?;
+    let parsed = raw.trim().parse().ok();
+    if parsed.is_none() {
+        warn!("{name}={raw:?} is not valid, keeping the default");
+    }
+    parsed
+}
 
 /// AI implementation for our planet.
 ///
@@ -93,35 +575,322 @@ use log::{debug, error, info, warn};
 ///
 /// See the module-level documentation for full details.
 pub(crate) struct AI {
-    running: bool,
+    config: AiConfig,
+    /// Type of the planet the AI runs, see [`asteroid_defense`].
+    planet_type: PlanetType,
+    mode: PlanetMode,
+    maintenance: Arc<AtomicBool>,
+    generation_rules: Arc<Mutex<RuleOverride>>,
+    /// Resets requested through the [`AiHandle`], and how many were applied.
+    resets: Arc<AtomicU64>,
+    applied_resets: u64,
+    /// Set by the [`Trip`](crate::Trip) relay when a housekeeping round is
+    /// due, cleared by the handler call that runs it.
+    housekeeping: Arc<AtomicBool>,
+    explorers: HashMap<u32, Explorer>,
+    rockets: Vec<Rocket>,
+    /// Called for every rocket launched against an asteroid.
+    on_rocket_launched: Option<RocketHook>,
+    /// Collector of the steps taken, if any.
+    audit: Option<Sender<AuditEvent>>,
+    stats: Arc<Mutex<AiStats>>,
+    pending_sunrays: VecDeque<Sunray>,
+    charge_cursor: usize,
+    discharge_cursor: usize,
+    charge_ticks: Vec<u64>,
+    tick: u64,
+    /// Incremented every time a generation request is served.
+    serve_tick: u64,
+    recipes: RecipeCache,
+}
+
+impl std::fmt::Debug for AI {
+    /// Shows the configuration and a summary of the runtime state; explorers
+    /// are listed by id only.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut explorers: Vec<_> = self.explorers.keys().collect();
+        explorers.sort_unstable();
+        f.debug_struct("AI")
+            .field("config", &self.config)
+            .field("planet_type", &self.planet_type)
+            .field("mode", &self.mode)
+            .field("explorers", &explorers)
+            .field("reserve_rockets", &self.rockets.len())
+            .field("pending_sunrays", &self.pending_sunrays.len())
+            .field("on_rocket_launched", &self.on_rocket_launched.is_some())
+            .field("audit", &self.audit.is_some())
+            .finish_non_exhaustive()
+    }
 }
 
 impl AI {
-    /// Creates a new, inactive [`AI`] instance.
+    /// Creates a new, inactive [`AI`] instance with the default
+    /// [`AiConfig`].
     ///
-    /// The AI begins in the `running = false` state, meaning no incoming
+    /// The AI begins in the [`PlanetMode::Stopped`] mode, meaning no incoming
     /// messages will be processed until [`start`](PlanetAI::start) is called.
+    #[cfg(test)]
     pub(crate) fn new() -> Self {
-        Self { running: false }
+        Self::with_config(AiConfig::default())
+    }
+
+    /// Creates a new, inactive [`AI`] following the policies in `config`.
+    pub(crate) fn with_config(config: AiConfig) -> Self {
+        Self {
+            planet_type: PlanetType::A,
+            mode: PlanetMode::Stopped,
+            maintenance: Arc::default(),
+            generation_rules: Arc::default(),
+            resets: Arc::default(),
+            applied_resets: 0,
+            housekeeping: Arc::default(),
+            explorers: HashMap::new(),
+            rockets: Vec::new(),
+            on_rocket_launched: None,
+            audit: None,
+            stats: Arc::new(Mutex::new(AiStats {
+                health: config.max_health,
+                ..AiStats::default()
+            })),
+            pending_sunrays: VecDeque::with_capacity(config.sunray_buffer_capacity),
+            charge_cursor: 0,
+            discharge_cursor: 0,
+            charge_ticks: Vec::new(),
+            tick: 0,
+            serve_tick: 0,
+            recipes: RecipeCache::default(),
+            config,
+        }
+    }
+
+    /// Sets the type of the planet the AI runs, [`PlanetType::A`] by
+    /// default, which decides how it weathers asteroids.
+    pub(crate) fn for_planet_type(mut self, planet_type: PlanetType) -> Self {
+        self.planet_type = planet_type;
+        self
+    }
+
+    /// Sets the callback run for every rocket launched, if any.
+    pub(crate) fn on_rocket_launched(mut self, hook: Option<RocketHook>) -> Self {
+        self.on_rocket_launched = hook;
+        self
+    }
+
+    /// Sets the collector of the steps taken, if any.
+    pub(crate) fn audit_events(mut self, events: Option<Sender<AuditEvent>>) -> Self {
+        self.audit = events;
+        self
+    }
+
+    /// Hands `event` to the audit collector, if any; a collector that went
+    /// away only misses the event.
+    fn emit(&self, event: AuditEvent) {
+        if let Some(audit) = &self.audit
+            && audit.send(event).is_err()
+        {
+            debug!("audit collector gone, {event:?} not reported");
+        }
+    }
+
+    /// Creates a new, inactive [`AI`] that picks energy cells according to
+    /// `selection`.
+    #[cfg(test)]
+    pub(crate) fn with_cell_selection(selection: CellSelection) -> Self {
+        Self::with_config(AiConfig {
+            cell_selection: selection,
+            ..AiConfig::default()
+        })
+    }
+
+    /// Creates a new, inactive [`AI`] with a custom rocket policy, see
+    /// [`AiConfig::rocket_reserve`] and [`AiConfig::auto_build`]. Callers
+    /// set the same policy through
+    /// [`TripBuilder::ai_config`](crate::TripBuilder::ai_config).
+    #[cfg(test)]
+    pub(crate) fn with_reserve(reserve: usize, auto_build: bool) -> Self {
+        Self::with_config(AiConfig {
+            rocket_reserve: reserve,
+            auto_build,
+            ..AiConfig::default()
+        })
+    }
+
+    /// Creates a new, inactive [`AI`] that buffers at most `capacity`
+    /// sunrays received in maintenance.
+    #[cfg(test)]
+    pub(crate) fn with_sunray_buffer(capacity: usize) -> Self {
+        Self::with_config(AiConfig {
+            sunray_buffer_capacity: capacity,
+            ..AiConfig::default()
+        })
+    }
+
+    /// Returns a handle to the AI's activity counters.
+    pub(crate) fn stats(&self) -> Arc<Mutex<AiStats>> {
+        Arc::clone(&self.stats)
+    }
+
+    /// Returns the state shared with the [`Trip`](crate::Trip) running the
+    /// AI.
+    pub(crate) fn handle(&self) -> AiHandle {
+        AiHandle {
+            stats: self.stats(),
+            maintenance: Arc::clone(&self.maintenance),
+            generation_rules: Arc::clone(&self.generation_rules),
+            resets: Arc::clone(&self.resets),
+            housekeeping: Arc::clone(&self.housekeeping),
+        }
     }
 
-    /// Returns `true` if the AI is currently active, otherwise logs that the
-    /// AI ignored a message due to being stopped and returns `false`.
+    /// Clears the runtime state for a new simulation epoch, keeping the
+    /// configuration.
     ///
-    /// # Parameters
-    /// - `planet_id`: The ID of the planet for contextual logging.
+    /// Counters are zeroed, except for the lifetime yields, and
+    /// health restored, the rocket reserve and the
+    /// buffered sunrays are discarded, and the AI returns to
+    /// [`PlanetMode::Stopped`]. The energy cells and the rocket standing on
+    /// the planet belong to the planet's state and are left as they are.
+    /// Explorers are left to the
+    /// [`Trip`](crate::Trip) relay, which makes the planet let go of all of
+    /// them on its next start, so they still leave through
+    /// [`on_explorer_departure`](PlanetAI::on_explorer_departure).
+    pub(crate) fn reset(&mut self, planet_id: u32) {
+        self.mode = PlanetMode::Stopped;
+        self.rockets.clear();
+        self.pending_sunrays.clear();
+        self.charge_cursor = 0;
+        self.discharge_cursor = 0;
+        self.charge_ticks.clear();
+        self.tick = 0;
+        self.serve_tick = 0;
+        let explorers = self.explorers.len();
+        self.record(|s| {
+            *s = AiStats {
+                explorers,
+                health: self.config.max_health,
+                yields: s.yields,
+                complex_yields: s.complex_yields,
+                ..AiStats::default()
+            };
+        });
+        info!("planet_id={planet_id} ai_reset");
+    }
+
+    /// Applies a reset requested through the [`AiHandle`] since the last
+    /// start or stop.
+    fn apply_reset(&mut self, planet_id: u32) {
+        let resets = self.resets.load(Ordering::Acquire);
+        if resets != self.applied_resets {
+            self.applied_resets = resets;
+            self.reset(planet_id);
+        }
+    }
+
+    /// Updates the activity counters, recovering them if the lock was
+    /// poisoned by a panicking reader.
+    fn record(&self, update: impl FnOnce(&mut AiStats)) {
+        let mut stats = self
+            .stats
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        update(&mut stats);
+    }
+
+    /// Picks the cell to charge (`charged == false`) or to discharge
+    /// (`charged == true`) according to the configured [`CellSelection`].
     ///
     /// # Returns
-    /// `true` if `running == true`, `false` otherwise.
+    /// The index of the chosen cell, or `None` if no cell is eligible.
+    fn select_cell(&mut self, state: &PlanetState, charged: bool) -> Option<usize> {
+        let count = state.cells_count();
+        self.charge_ticks.resize(count, 0);
+        let eligible = |i: &usize| state.cell(*i).is_charged() == charged;
+        let cursor = if charged {
+            &mut self.discharge_cursor
+        } else {
+            &mut self.charge_cursor
+        };
+        let index = match self.config.cell_selection {
+            CellSelection::FirstAvailable => (0..count).find(eligible),
+            CellSelection::RoundRobin => (0..count).map(|i| (*cursor + i) % count).find(eligible),
+            CellSelection::LeastRecentlyCharged => (0..count)
+                .filter(eligible)
+                .min_by_key(|&i| self.charge_ticks[i]),
+        }?;
+        *cursor = (index + 1) % count;
+        Some(index)
+    }
+
+    /// Charges the cell at `index` and remembers when it was charged.
+    fn charge(&mut self, state: &mut PlanetState, index: usize, s: Sunray) {
+        state.cell_mut(index).charge(s);
+        self.tick += 1;
+        self.charge_ticks[index] = self.tick;
+        self.emit(AuditEvent::SunrayCharged {
+            planet_id: state.id(),
+            cell: index,
+        });
+    }
+
+    /// Queues a sunray received in maintenance, dropping the oldest
+    /// buffered sunray if the buffer is full.
+    fn buffer_sunray(&mut self, planet_id: u32, s: Sunray) {
+        if self.config.sunray_buffer_capacity == 0 {
+            debug!("planet_id={planet_id} sunray_dropped: buffer_disabled");
+            return;
+        }
+        if self.pending_sunrays.len() == self.config.sunray_buffer_capacity {
+            self.pending_sunrays.pop_front();
+            warn!("planet_id={planet_id} sunray_buffer_full: oldest_dropped");
+        }
+        self.pending_sunrays.push_back(s);
+        debug!(
+            "planet_id={planet_id} sunray_buffered: pending={}",
+            self.pending_sunrays.len()
+        );
+    }
+
+    /// Replays the sunrays buffered while the AI was in maintenance.
+    ///
+    /// `on_start` only receives a shared [`PlanetState`], so the replay
+    /// happens on the first handler call with mutable access after a restart.
+    fn replay_sunrays(&mut self, state: &mut PlanetState) {
+        if self.pending_sunrays.is_empty() {
+            return;
+        }
+        info!(
+            "planet_id={} replaying_buffered_sunrays: count={}",
+            state.id(),
+            self.pending_sunrays.len()
+        );
+        while let Some(s) = self.pending_sunrays.pop_front() {
+            self.charge_cell(state, s);
+        }
+    }
+
+    /// Returns the current [`PlanetMode`], first applying a maintenance
+    /// switch requested since the last message.
     ///
     /// # Side Effects
-    /// - Writes a debug log message when inactive.
-    fn is_running(&self, planet_id: u32) -> bool {
-        if !self.running {
+    /// - Logs mode transitions, and a debug message when stopped.
+    fn mode(&mut self, planet_id: u32) -> PlanetMode {
+        if self.mode == PlanetMode::Stopped {
             debug!("planet_id={planet_id} msg_ignored: ai_stopped");
-            return false;
+            return self.mode;
         }
-        true
+        let mode = if self.maintenance.load(Ordering::Acquire) {
+            PlanetMode::Maintenance
+        } else {
+            PlanetMode::Running
+        };
+        if mode != self.mode {
+            info!(
+                "planet_id={planet_id} mode_changed: {:?} -> {mode:?}",
+                self.mode
+            );
+            self.mode = mode;
+        }
+        mode
     }
 
     /// Transforms a [`ComplexResourceRequest`] into a pair of [`GenericResource`]
@@ -162,342 +931,2717 @@ impl AI {
         }
     }
 
-    /// Handles a [`Sunray`] by charging the first uncharged energy cell and
-    /// attempting to build a rocket on that cell.
-    ///
-    /// This method encapsulates the sunray-handling logic used by
-    /// [`handle_orchestrator_msg`](PlanetAI::handle_orchestrator_msg).
-    ///
-    /// # Behavior
-    /// - Charges the first available uncharged cell.
-    /// - Attempts to build a rocket on that cell; logs success or failure.
-    /// - Logs relevant diagnostic information.
+    /// Applies generation rules set through the
+    /// [`TripHandle`](crate::TripHandle) since the last explorer request.
     ///
     /// # Side Effects
-    /// - Mutates the [`PlanetState`] (cell charge, rocket construction).
-    /// - Emits debug, info, or error logs.
-    fn handle_sunray(state: &mut PlanetState, s: Sunray) {
-        debug!("planet_id={} incoming_sunray", state.id());
-        if let Some(index) = state.cells_iter().position(|cell| !cell.is_charged()) {
-            let cell = state.cell_mut(index);
-            cell.charge(s);
-            debug!("planet_id={} sunray: charging cell", state.id());
-            match state.build_rocket(index) {
-                Ok(()) => info!("planet_id={} rocket_built", state.id()),
-                Err(e) => warn!("planet_id={} rocket_build_failed: {}", state.id(), e),
+    /// - Logs the new rules, and a warning for every resource outside the
+    ///   planet's own rules, which `common_game` does not let us add.
+    fn apply_generation_rules(&mut self, planet_id: u32, generator: &Generator) {
+        let mut update = self
+            .generation_rules
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        if !update.changed {
+            return;
+        }
+        update.changed = false;
+        if let Some(rules) = &update.rules {
+            let own = generator.all_available_recipes();
+            for &resource in rules.difference(&own) {
+                warn!(
+                    "planet_id={planet_id} generation_rule_ignored: {} is not in the planet's rules",
+                    resource_names::name(resource)
+                );
             }
-        } else {
-            warn!("planet_id={} sunray: no_uncharged_cells", state.id());
         }
-        debug!("planet_id={} outgoing_sunray_ack", state.id());
-    }
-}
-
-impl PlanetAI for AI {
-    /// Activates the AI and enables message processing.
-    ///
-    /// Called by the planet runtime when initialization completes.
-    /// After this call, incoming messages to the AI will be processed normally.
-    ///
-    /// # Side Effects
-    /// - Sets `running = true`
-    /// - Logs an informational `ai_started` message
-    fn on_start(&mut self, state: &PlanetState, _: &Generator, _: &Combinator) {
-        self.running = true;
-        info!("planet_id={} ai_started", state.id());
+        info!(
+            "planet_id={planet_id} generation_rules_changed: {:?}",
+            update.rules
+        );
+        self.recipes.restrict(update.rules.clone());
     }
 
-    /// Deactivates the AI and stops all message processing.
-    ///
-    /// All message handlers will return `None` until the AI is restarted.
+    /// Returns the basic resources this planet is able to generate.
     ///
-    /// # Side Effects
-    /// - Sets `running = false`
-    /// - Logs an informational `ai_stopped` message
-    fn on_stop(&mut self, state: &PlanetState, _: &Generator, _: &Combinator) {
-        self.running = false;
-        info!("planet_id={} ai_stopped", state.id());
+    /// This is the single source of truth for both `SupportedResourceRequest`
+    /// and `GenerateResourceRequest`, so explorers are never told about a
+    /// resource that a generation request would not honor.
+    fn supported_resources(&mut self, generator: &Generator) -> &HashSet<BasicResourceType> {
+        self.recipes.resources(generator)
     }
 
-    /// Handles a sunray by delegating to the internal charging logic.
+    /// Generates one unit of `resource` by discharging `cell`, using the
+    /// matching [`Generator`] method.
     ///
-    /// # Behavior
-    /// - Consumes the incoming sunray to charge the first available energy cell.
-    /// - Attempts to build a rocket immediately after charging.
-    /// - This is a wrapper around the static [`AI::handle_sunray`] method.
-    fn handle_sunray(&mut self, state: &mut PlanetState, _: &Generator, _: &Combinator, s: Sunray) {
-        if self.is_running(state.id()) {
-            AI::handle_sunray(state, s);
+    /// # Errors
+    /// Returns the generator's error if the recipe is missing or the cell is
+    /// not charged.
+    fn generate(
+        generator: &Generator,
+        resource: BasicResourceType,
+        cell: &mut EnergyCell,
+    ) -> Result<BasicResource, String> {
+        match resource {
+            BasicResourceType::Oxygen => generator.make_oxygen(cell).map(BasicResource::Oxygen),
+            BasicResourceType::Hydrogen => {
+                generator.make_hydrogen(cell).map(BasicResource::Hydrogen)
+            }
+            BasicResourceType::Carbon => generator.make_carbon(cell).map(BasicResource::Carbon),
+            BasicResourceType::Silicon => generator.make_silicon(cell).map(BasicResource::Silicon),
         }
     }
 
-    /// Provides a `DummyPlanetState` object representing the current planet state.
-    ///
-    /// # Behavior
-    /// - Converts the current `PlanetState` into a `DummyPlanetState`.
-    ///
-    /// # Returns
-    /// A `DummyPlanetState` representing the current state of the planet.
-    fn handle_internal_state_req(
-        &mut self,
-        state: &mut PlanetState,
-        _: &Generator,
-        _: &Combinator,
-    ) -> DummyPlanetState {
-        state.to_dummy()
-    }
-
-    /// Handles messages from an explorer interacting with this planet.
-    ///
-    /// The AI responds to explorer queries about:
-    /// - Supported basic resources
-    /// - Supported combination rules
-    /// - Energy availability
-    /// - Requests to generate Oxygen
-    ///
-    /// Unsupported combinations or unsupported resource requests result in
-    /// `None` or an appropriate error response.
+    /// Handles a `GenerateResourceRequest` by discharging a charged cell,
+    /// chosen by the [`CellSelection`] policy, into one unit of `resource`.
     ///
-    /// # Behavior
+    /// Every generated unit consumes a charged cell. With a generation quota
+    /// configured, an explorer that already consumed its quota since the last
+    /// sunray is throttled, so a greedy explorer cannot starve the others.
     ///
-    /// - If the AI is stopped, returns `None`.
-    /// - Basic resource generation is supported only for Oxygen.
-    /// - Combination attempts always produce an `Err` payload indicating
-    ///   unsupported functionality.
+    /// A request yields at most one unit. `common_game`'s
+    /// `GenerateResourceRequest` has no quantity, and its
+    /// `GenerateResourceResponse` carries a single optional resource, so
+    /// there is no way to ask for several units or to report a partial
+    /// batch: an explorer sends one request per unit, and the requests past
+    /// the charged cells go unanswered.
     ///
     /// # Returns
-    /// - `Some(response)` if a valid response exists.
-    /// - `None` if the AI is stopped or if the request cannot be fulfilled.    
-    fn handle_explorer_msg(
+    /// - `Some(GenerateResourceResponse)` carrying the generated resource.
+    /// - `Some(GenerateResourceResponse)` carrying no resource if `resource`
+    ///   is not supported, or the AI runs with [`AiConfig::dry_run`], so the
+    ///   explorer gets a definitive answer.
+    /// - `None` if the explorer exhausted its quota or no charged cell is
+    ///   available, which may change after the next sunray.
+    fn generate_resource(
         &mut self,
         state: &mut PlanetState,
         generator: &Generator,
-        comb: &Combinator,
-        msg: ExplorerToPlanet,
+        explorer_id: u32,
+        resource: BasicResourceType,
     ) -> Option<PlanetToExplorer> {
-        if !self.is_running(state.id()) {
+        if !self.supported_resources(generator).contains(&resource) {
+            debug!(
+                "planet_id={} explorer_id={} generate_resource: unsupported {}",
+                state.id(),
+                explorer_id,
+                resource_names::name(resource)
+            );
+            return Some(PlanetToExplorer::GenerateResourceResponse { resource: None });
+        }
+        if self.config.dry_run {
+            info!(
+                "planet_id={} explorer_id={} dry_run: generate_resource {} would_succeed={}",
+                state.id(),
+                explorer_id,
+                resource_names::name(resource),
+                state.cells_iter().any(EnergyCell::is_charged)
+            );
+            return Some(PlanetToExplorer::GenerateResourceResponse { resource: None });
+        }
+        let consumed = self
+            .explorers
+            .get(&explorer_id)
+            .map_or(0, |e| e.cells_consumed);
+        if let Some(quota) = self.config.generation_quota
+            && consumed >= quota
+        {
+            warn!(
+                "planet_id={} explorer_id={} generate_resource: quota of {} cells exhausted",
+                state.id(),
+                explorer_id,
+                quota
+            );
             return None;
         }
-        match msg {
-            ExplorerToPlanet::SupportedResourceRequest { explorer_id } => {
+        if self.defers_to_waiting(state, explorer_id) {
+            debug!(
+                "planet_id={} explorer_id={} generate_resource: deferred to explorers waiting longer",
+                state.id(),
+                explorer_id
+            );
+            self.mark_waiting(explorer_id);
+            return None;
+        }
+        self.select_cell(state, true)
+            .and_then(|index| AI::generate(generator, resource, state.cell_mut(index)).ok())
+            .map(|r| {
                 debug!(
-                    "planet_id={} explorer_id={} outgoing_supported_resource_response",
+                    "planet_id={} explorer_id={} generate_resource: success {}",
                     state.id(),
-                    explorer_id
+                    explorer_id,
+                    resource_names::name(resource)
                 );
-                Some(PlanetToExplorer::SupportedResourceResponse {
-                    resource_list: generator.all_available_recipes(),
-                })
-            }
-            ExplorerToPlanet::GenerateResourceRequest {
-                explorer_id,
-                resource: BasicResourceType::Oxygen,
-            } => state
-                .cells_iter()
-                .position(EnergyCell::is_charged)
-                .and_then(|index| generator.make_oxygen(state.cell_mut(index)).ok())
-                .map(|r| {
-                    debug!(
-                        "planet_id={} explorer_id={} generate_oxygen: success",
-                        state.id(),
-                        explorer_id
-                    );
-                    PlanetToExplorer::GenerateResourceResponse {
-                        resource: Some(common_game::components::resource::BasicResource::Oxygen(r)),
-                    }
-                })
-                .or_else(|| {
-                    warn!(
-                        "planet_id={} explorer_id={} generate_oxygen: failed",
-                        state.id(),
-                        explorer_id
-                    );
-                    None
-                }),
-            ExplorerToPlanet::GenerateResourceRequest { explorer_id, .. } => {
-                debug!(
-                    "planet_id={} explorer_id={} generate_resource: unsupported",
+                self.record(|s| {
+                    s.resources_generated += 1;
+                    s.yields.record(resource);
+                });
+                self.emit(AuditEvent::ResourceGenerated {
+                    planet_id: state.id(),
+                    explorer_id,
+                    resource,
+                });
+                self.serve_tick += 1;
+                if let Some(explorer) = self.explorers.get_mut(&explorer_id) {
+                    explorer.cells_consumed += 1;
+                    explorer.last_served = self.serve_tick;
+                    explorer.waiting = 0;
+                }
+                PlanetToExplorer::GenerateResourceResponse { resource: Some(r) }
+            })
+            .or_else(|| {
+                warn!(
+                    "planet_id={} explorer_id={} generate_resource: failed {}",
                     state.id(),
-                    explorer_id
+                    explorer_id,
+                    resource_names::name(resource)
                 );
+                self.mark_waiting(explorer_id);
                 None
-            }
-            ExplorerToPlanet::SupportedCombinationRequest { explorer_id, .. } => {
+            })
+    }
+
+    /// Sunrays an unserved explorer's claim to a charged cell survives.
+    const WAITING_SUNRAYS: u32 = 2;
+
+    /// Tells whether `explorer_id` must leave the remaining charged cells to
+    /// explorers that were turned away before and were served less recently.
+    ///
+    /// Requests are answered in arrival order, so without this a chatty
+    /// explorer would take every cell as soon as it is charged. A claim
+    /// lapses after [`AI::WAITING_SUNRAYS`] sunrays, so an explorer that gave
+    /// up cannot hold cells back for long.
+    fn defers_to_waiting(&self, state: &PlanetState, explorer_id: u32) -> bool {
+        let last_served = self
+            .explorers
+            .get(&explorer_id)
+            .map_or(0, |e| e.last_served);
+        let ahead = self
+            .explorers
+            .iter()
+            .filter(|&(&id, e)| id != explorer_id && e.waiting > 0 && e.last_served < last_served)
+            .count();
+        ahead > 0 && state.cells_iter().filter(|cell| cell.is_charged()).count() <= ahead
+    }
+
+    /// Records that `explorer_id` was turned away, giving it a claim to the
+    /// next charged cells.
+    fn mark_waiting(&mut self, explorer_id: u32) {
+        if let Some(explorer) = self.explorers.get_mut(&explorer_id) {
+            explorer.waiting = Self::WAITING_SUNRAYS;
+        }
+    }
+
+    /// Returns the [`ComplexResourceType`] produced by a [`ComplexResourceRequest`].
+    fn get_complex_type(msg: &ComplexResourceRequest) -> ComplexResourceType {
+        match msg {
+            ComplexResourceRequest::Water(..) => ComplexResourceType::Water,
+            ComplexResourceRequest::Diamond(..) => ComplexResourceType::Diamond,
+            ComplexResourceRequest::Life(..) => ComplexResourceType::Life,
+            ComplexResourceRequest::Robot(..) => ComplexResourceType::Robot,
+            ComplexResourceRequest::Dolphin(..) => ComplexResourceType::Dolphin,
+            ComplexResourceRequest::AIPartner(..) => ComplexResourceType::AIPartner,
+        }
+    }
+
+    /// Reason given back when the planet has no recipe for a requested
+    /// complex resource.
+    const UNSUPPORTED_COMBINATION: &str = "unsupported_combination";
+
+    /// Reason given back when no charged cell can power a combination.
+    const NO_CHARGED_CELLS: &str = "no_charged_cells";
+
+    /// Reason given back for a supported combination in a dry run.
+    const DRY_RUN: &str = "dry_run";
+
+    /// Answers an explorer's `CombineResourceRequest`, see [`AI::combine`].
+    fn combine_resource(
+        &mut self,
+        state: &mut PlanetState,
+        comb: &Combinator,
+        explorer_id: u32,
+        msg: ComplexResourceRequest,
+    ) -> PlanetToExplorer {
+        debug!(
+            "planet_id={} explorer_id={} incoming_combine_request: {:?}",
+            state.id(),
+            explorer_id,
+            msg
+        );
+        let complex_response = self.combine(state, comb, msg);
+        match &complex_response {
+            Ok(resource) => {
                 debug!(
-                    "planet_id={} explorer_id={} outgoing_supported_combination_response",
+                    "planet_id={} explorer_id={} outgoing_combine_response: {:?}",
                     state.id(),
-                    explorer_id
+                    explorer_id,
+                    resource.get_type()
                 );
-                Some(PlanetToExplorer::SupportedCombinationResponse {
-                    combination_list: comb.all_available_recipes(),
-                })
-            }
-            ExplorerToPlanet::CombineResourceRequest { explorer_id, msg } => {
-                debug!(
-                    "planet_id={} explorer_id={} incoming_combine_request: {:?}",
-                    state.id(),
-                    explorer_id,
-                    msg
-                );
-                let (left, right) = AI::get_generic_resources(msg);
-                debug!(
-                    "planet_id={} explorer_id={} outgoing_combine_response=unsupported_combination",
-                    state.id(),
-                    explorer_id
-                );
-                Some(PlanetToExplorer::CombineResourceResponse {
-                    complex_response: Err(("unsupported_combination".to_string(), left, right)),
-                })
-            }
-            ExplorerToPlanet::AvailableEnergyCellRequest { explorer_id } => {
-                let tmp = state.cells_iter().filter(|&cell| cell.is_charged()).count();
-                let count = tmp.try_into().unwrap_or_default();
-                debug!(
-                    "planet_id={} explorer_id={} outgoing_energy_cell_count={}",
-                    state.id(),
-                    explorer_id,
-                    count
-                );
-                Some(PlanetToExplorer::AvailableEnergyCellResponse {
-                    available_cells: count,
-                })
+                self.record(|s| {
+                    s.complex_resources_produced += 1;
+                    s.complex_yields.record(resource.get_type());
+                });
             }
+            Err((reason, ..)) => warn!(
+                "planet_id={} explorer_id={} outgoing_combine_response: {}",
+                state.id(),
+                explorer_id,
+                reason
+            ),
         }
+        PlanetToExplorer::CombineResourceResponse { complex_response }
     }
 
-    /// Handles an asteroid impact event.
+    /// Attempts to combine the resources carried by a [`ComplexResourceRequest`].
     ///
     /// # Behavior
-    ///
-    /// - If a rocket already exists in the state, it is launched immediately.
-    /// - Otherwise, the AI searches for the first charged energy cell and
-    ///   attempts to build a rocket on it.
-    /// - If rocket construction succeeds, the rocket is launched.
-    /// - If construction fails or no charged cell exists, `None` is returned.
-    ///
-    /// # Side Effects
-    /// - Mutates the planet state by consuming energy cells and creating rockets.
-    /// - Logs informational or warning messages depending on outcome.
+    /// - Validates the requested recipe against
+    ///   [`Combinator::all_available_recipes`].
+    /// - Consumes a charged energy cell, chosen by the [`CellSelection`]
+    ///   policy, to power the combination. Every `common_game` recipe needs
+    ///   one, exactly as generation does, so the check applies to all of
+    ///   them.
     ///
     /// # Returns
-    /// `Some(Rocket)` if a rocket is launched, otherwise `None`.    
-    fn handle_asteroid(
+    /// - `Ok(ComplexResource)` if the combination succeeds.
+    /// - `Err((reason, left, right))` otherwise, handing the input resources
+    ///   back to the explorer; `reason` is [`AI::UNSUPPORTED_COMBINATION`],
+    ///   [`AI::NO_CHARGED_CELLS`], [`AI::DRY_RUN`] or the combinator's own
+    ///   error.
+    fn combine(
         &mut self,
         state: &mut PlanetState,
-        _: &Generator,
-        _: &Combinator,
-    ) -> Option<Rocket> {
-        if !self.is_running(state.id()) {
-            return None;
+        comb: &Combinator,
+        msg: ComplexResourceRequest,
+    ) -> Result<ComplexResource, (String, GenericResource, GenericResource)> {
+        let requested = AI::get_complex_type(&msg);
+        if !self.recipes.combinations(comb).contains(&requested) {
+            let (left, right) = AI::get_generic_resources(msg);
+            return Err((Self::UNSUPPORTED_COMBINATION.to_string(), left, right));
         }
-        if state.has_rocket() {
+        if self.config.dry_run {
             info!(
-                "planet_id={} asteroid_event: existing_rocket_launched",
-                state.id()
+                "planet_id={} dry_run: combine {:?} would_succeed={}",
+                state.id(),
+                requested,
+                state.cells_iter().any(EnergyCell::is_charged)
+            );
+            let (left, right) = AI::get_generic_resources(msg);
+            return Err((Self::DRY_RUN.to_string(), left, right));
+        }
+        let Some(index) = self.select_cell(state, true) else {
+            let (left, right) = AI::get_generic_resources(msg);
+            return Err((Self::NO_CHARGED_CELLS.to_string(), left, right));
+        };
+        debug!(
+            "planet_id={} combine: using cell {} for {:?}",
+            state.id(),
+            index,
+            requested
+        );
+        comb.try_make(msg, state.cell_mut(index))
+    }
+
+    /// Registers an explorer that has arrived on the planet.
+    ///
+    /// Registering an already known explorer is allowed: the planet runtime
+    /// replaces its stale sender with the new one.
+    fn attach_explorer(&mut self, planet_id: u32, explorer_id: u32) {
+        if self
+            .explorers
+            .insert(explorer_id, Explorer::default())
+            .is_none()
+        {
+            debug!("planet_id={planet_id} explorer_id={explorer_id} explorer_attached");
+        } else {
+            info!("planet_id={planet_id} explorer_id={explorer_id} explorer_reattached");
+        }
+        let explorers = self.explorers.len();
+        self.record(|s| s.explorers = explorers);
+    }
+
+    /// Detaches an explorer leaving the planet, dropping its per-explorer state.
+    ///
+    /// # Errors
+    /// Returns `Err` if the explorer was never registered on this planet.
+    fn detach_explorer(&mut self, planet_id: u32, explorer_id: u32) -> Result<(), String> {
+        match self.explorers.remove(&explorer_id) {
+            Some(explorer) => {
+                debug!(
+                    "planet_id={planet_id} explorer_id={explorer_id} explorer_detached: served={}",
+                    explorer.requests_served
+                );
+                let explorers = self.explorers.len();
+                self.record(|s| s.explorers = explorers);
+                Ok(())
+            }
+            None => Err(format!(
+                "explorer {explorer_id} is not on planet {planet_id}"
+            )),
+        }
+    }
+
+    /// Converts a number of energy cells to the width used by the protocol,
+    /// saturating rather than wrapping or collapsing to zero, so an explorer
+    /// at least sees "many" cells.
+    pub(crate) fn cell_count(planet_id: u32, count: usize) -> u32 {
+        u32::try_from(count).unwrap_or_else(|_| {
+            warn!(
+                "planet_id={planet_id} cell_count: {count} cells reported as {}",
+                u32::MAX
             );
-            return state.take_rocket();
+            u32::MAX
+        })
+    }
+
+    /// Returns the number of rockets available for asteroid defense: the one
+    /// held by the [`PlanetState`] plus the AI's reserve.
+    fn rocket_count(&self, state: &PlanetState) -> usize {
+        usize::from(state.has_rocket()) + self.rockets.len()
+    }
+
+    /// Builds a rocket on the charged cell at `index`.
+    ///
+    /// The [`PlanetState`] can only hold one rocket, so an already built
+    /// rocket is moved into the AI's reserve first.
+    fn build_rocket(&mut self, state: &mut PlanetState, index: usize) -> Result<(), String> {
+        if let Some(rocket) = state.take_rocket() {
+            self.rockets.push(rocket);
+            let reserve = self.rockets.len();
+            self.record(|s| s.reserve_rockets = reserve);
         }
-        if let Some(index) = state.cells_iter().position(EnergyCell::is_charged) {
-            match state.build_rocket(index) {
+        state.build_rocket(index)?;
+        self.record(|s| s.rockets_built += 1);
+        self.emit(AuditEvent::RocketBuilt {
+            planet_id: state.id(),
+        });
+        Ok(())
+    }
+
+    /// Maximum number of charged cells tried when building a rocket fails.
+    const MAX_BUILD_ATTEMPTS: usize = 2;
+
+    /// Builds a rocket on the charged cell at `preferred`, falling back to
+    /// other charged cells if the build fails.
+    ///
+    /// At most [`AI::MAX_BUILD_ATTEMPTS`] cells are tried, so a planet that
+    /// cannot build rockets does not scan every cell on each call. A failed
+    /// build leaves the cell charged.
+    ///
+    /// # Returns
+    /// The index of the cell the rocket was built on, or `None` if every
+    /// attempt failed.
+    fn try_build_rocket(&mut self, state: &mut PlanetState, preferred: usize) -> Option<usize> {
+        let fallbacks: Vec<usize> = state
+            .cells_iter()
+            .enumerate()
+            .filter(|&(i, cell)| i != preferred && cell.is_charged())
+            .map(|(i, _)| i)
+            .collect();
+        for index in std::iter::once(preferred)
+            .chain(fallbacks)
+            .take(Self::MAX_BUILD_ATTEMPTS)
+        {
+            match self.build_rocket(state, index) {
                 Ok(()) => {
                     info!(
-                        "planet_id={} asteroid_event: rocket_built_and_launched",
-                        state.id()
+                        "planet_id={} rocket_built cell={} rockets={}",
+                        state.id(),
+                        index,
+                        self.rocket_count(state)
                     );
-                    return state.take_rocket();
+                    return Some(index);
                 }
-                Err(e) => error!(
-                    "planet_id={} asteroid_event: rocket_build_failed {}",
+                Err(e) => warn!(
+                    "planet_id={} rocket_build_failed cell={}: {}",
                     state.id(),
+                    index,
                     e
                 ),
             }
-        } else {
+        }
+        self.record(|s| s.rocket_build_failures += 1);
+        None
+    }
+
+    /// Launches an already built rocket, without consuming any cell.
+    ///
+    /// The rocket held by the [`PlanetState`] goes first, then the AI's
+    /// stockpile.
+    fn launch_from_reserve(&mut self, state: &mut PlanetState) -> Option<Rocket> {
+        let rocket = state.take_rocket().or_else(|| self.rockets.pop())?;
+        info!(
+            "planet_id={} asteroid_event: existing_rocket_launched rocket={:?} remaining={}",
+            state.id(),
+            rocket,
+            self.rocket_count(state)
+        );
+        let reserve = self.rockets.len();
+        self.record(|s| {
+            s.rockets_launched += 1;
+            s.reserve_rockets = reserve;
+        });
+        Some(rocket)
+    }
+
+    /// Launches a rocket against an asteroid, to be handed back in the
+    /// `AsteroidAck`.
+    ///
+    /// The reserve goes first; a new rocket is only built on a charged cell
+    /// if `build` allows it.
+    fn defend(&mut self, state: &mut PlanetState, build: bool) -> Option<Rocket> {
+        if !state.can_have_rocket() {
+            debug!(
+                "planet_id={} asteroid_event: {:?} cannot hold rockets",
+                state.id(),
+                self.planet_type
+            );
+            return None;
+        }
+        let rocket = self.launch_from_reserve(state).or_else(|| {
+            if build {
+                self.launch_new_rocket(state)
+            } else {
+                None
+            }
+        })?;
+        if let Some(hook) = &self.on_rocket_launched {
+            hook(&rocket);
+        }
+        self.emit(AuditEvent::RocketLaunched {
+            planet_id: state.id(),
+        });
+        Some(rocket)
+    }
+
+    /// Builds a rocket on a charged cell and launches it right away.
+    fn launch_new_rocket(&mut self, state: &mut PlanetState) -> Option<Rocket> {
+        let Some(first) = self.select_cell(state, true) else {
             warn!(
                 "planet_id={} asteroid_event: no_charged_cells_available",
                 state.id()
             );
+            return None;
+        };
+        if let Some(index) = self.try_build_rocket(state, first) {
+            let rocket = state.take_rocket()?;
+            info!(
+                "planet_id={} asteroid_event: rocket_built_and_launched cell={} rocket={:?}",
+                state.id(),
+                index,
+                rocket
+            );
+            self.record(|s| s.rockets_launched += 1);
+            return Some(rocket);
         }
+        error!(
+            "planet_id={} asteroid_event: rocket_build_failed",
+            state.id()
+        );
         None
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    //use common_game::components::planet::PlanetState;
-    //use common_game::components::resource::{Combinator, Generator};
-    //use common_game::components::sunray::Sunray;
-    //use common_game::protocols::messages::{ExplorerToPlanet, OrchestratorToPlanet};
+    /// Tells whether a request of `explorer_id` exceeds
+    /// [`AiConfig::explorer_rate_limit`], counting and logging it if so.
+    ///
+    /// Unregistered explorers are left to the caller.
+    fn over_rate_limit(&mut self, planet_id: u32, explorer_id: u32) -> bool {
+        let Some(limit) = self.config.explorer_rate_limit else {
+            return false;
+        };
+        let Some(explorer) = self.explorers.get_mut(&explorer_id) else {
+            return false;
+        };
+        if explorer.admit(limit, Instant::now()) {
+            return false;
+        }
+        warn!("planet_id={planet_id} explorer_id={explorer_id} rate_limited: message dropped");
+        self.record(|s| s.rate_limited += 1);
+        true
+    }
 
-    #[test]
-    fn test_ai_initial_state() {
-        let ai = AI::new();
-        assert!(!ai.running, "AI should start in stopped state");
+    /// Takes the planet type's share of [`AiConfig::asteroid_damage`] off
+    /// the planet's health after an undefended asteroid, down to zero, which
+    /// is game over.
+    fn take_hit(&self, planet_id: u32) {
+        let percent = asteroid_defense(self.planet_type).damage_percent;
+        let damage = self.config.asteroid_damage.saturating_mul(percent) / 100;
+        let health = {
+            let mut stats = self
+                .stats
+                .lock()
+                .unwrap_or_else(std::sync::PoisonError::into_inner);
+            stats.health = stats.health.saturating_sub(damage);
+            stats.health
+        };
+        if health == 0 {
+            error!("planet_id={planet_id} asteroid_event: game_over");
+        } else {
+            warn!("planet_id={planet_id} asteroid_event: hit damage={damage} health={health}");
+        }
     }
 
-    // Waiting for PlanetState to implement Default trait
-    /*#[test]
-    fn test_start_sets_running() {
-        let mut ai = AI::new();
-        let state = PlanetState::default();
-        ai.start(&state);
-        assert!(!ai.running, "AI should be running after start()");
+    /// Performs maintenance that does not need an incoming message, if the
+    /// [`Trip`](crate::Trip) relay flagged a round as due.
+    ///
+    /// If auto-build is enabled, builds rockets from charged cells until the
+    /// reserve target is met, so a reserve spent on an asteroid is refilled
+    /// during quiet periods instead of on the next sunray.
+    ///
+    /// `PlanetAI` has no timer hook: the relay sets the flag through the
+    /// [`AiHandle`] and wakes the planet with an `InternalStateRequest`
+    /// whose reply it keeps to itself.
+    fn housekeep(&mut self, state: &mut PlanetState) {
+        if !self.housekeeping.swap(false, Ordering::AcqRel)
+            || !self.config.auto_build
+            || self.config.dry_run
+        {
+            return;
+        }
+        while self.rocket_count(state) < self.reserve_target() && self.above_build_threshold(state)
+        {
+            let Some(index) = self.select_cell(state, true) else {
+                break;
+            };
+            if self.try_build_rocket(state, index).is_none() {
+                break;
+            }
+        }
+        debug!(
+            "planet_id={} housekeeping: rockets={}",
+            state.id(),
+            self.rocket_count(state)
+        );
     }
 
-    #[test]
-    fn test_stop_sets_stopped() {
-        let mut ai = AI::new();
-        let state = PlanetState::default();
+    /// Tells whether enough cells are charged for auto-build to spend one,
+    /// see [`AiConfig::build_threshold`].
+    fn above_build_threshold(&self, state: &PlanetState) -> bool {
+        state.cells_iter().filter(|cell| cell.is_charged()).count() >= self.config.build_threshold
+    }
 
-        ai.start(&state); // Start first
-        assert!(!ai.running);
+    /// Returns the number of rockets auto-build aims for: the configured
+    /// reserve, capped by [`AiConfig::max_rockets`].
+    fn reserve_target(&self) -> usize {
+        self.config.rocket_reserve.min(self.config.max_rockets)
+    }
 
-        ai.stop(&state);
-        assert!(ai.running, "AI should be stopped after stop()");
+    /// Spends a charged cell on an extra rocket when every cell is charged.
+    ///
+    /// # Returns
+    /// The index of the now discharged cell, or `None` if auto-build is
+    /// disabled, the planet cannot have rockets, already holds
+    /// [`AiConfig::max_rockets`] rockets, or the build failed.
+    fn stockpile_overflow(&mut self, state: &mut PlanetState) -> Option<usize> {
+        if !self.config.auto_build
+            || !state.can_have_rocket()
+            || self.rocket_count(state) >= self.config.max_rockets
+        {
+            return None;
+        }
+        let index = self.select_cell(state, true)?;
+        self.try_build_rocket(state, index)
     }
 
-    #[test]
-    fn test_handle_orchestrator_msg_returns_none() {
-        let mut ai = AI::new();
-        let state = &mut PlanetState::default();
-        let generator = &Generator::default();
-        let combinator = &Combinator::default();
-        let msg = OrchestratorToPlanet::Sunray(Sunray::default()); // Adjust based on actual enum
+    /// Handles a [`Sunray`] by charging an uncharged energy cell and
+    /// building a rocket on that cell while the reserve is below target.
+    ///
+    /// This method encapsulates the sunray-handling logic used by
+    /// [`handle_sunray`](PlanetAI::handle_sunray).
+    ///
+    /// # Behavior
+    /// - Charges an uncharged cell chosen by the [`CellSelection`] policy.
+    /// - If auto-build is enabled, fewer than `rocket_reserve` rockets are
+    ///   available and at least `build_threshold` cells are charged, builds
+    ///   a rocket on that cell, falling back to another charged cell on
+    ///   failure; logs success or failure.
+    /// - If every cell is already charged, the sunray would be lost: with
+    ///   auto-build enabled on a planet that can have rockets and holds fewer
+    ///   than `max_rockets`, a charged cell is spent on an extra stockpiled
+    ///   rocket and recharged with the sunray. Otherwise a warning reports
+    ///   that the capacity was exceeded.
+    /// - Logs relevant diagnostic information.
+    ///
+    /// # Side Effects
+    /// - Mutates the [`PlanetState`] (cell charge, rocket construction).
+    /// - Emits debug, info, or error logs.
+    fn charge_cell(&mut self, state: &mut PlanetState, s: Sunray) {
+        debug!("planet_id={} incoming_sunray", state.id());
+        if let Some(index) = self.select_cell(state, false) {
+            self.charge(state, index, s);
+            debug!("planet_id={} sunray: charging cell={}", state.id(), index);
+            if self.config.auto_build
+                && self.rocket_count(state) < self.reserve_target()
+                && self.above_build_threshold(state)
+            {
+                self.try_build_rocket(state, index);
+            }
+        } else if let Some(index) = self.stockpile_overflow(state) {
+            self.charge(state, index, s);
+            info!(
+                "planet_id={} sunray_overflow: rocket stockpiled, recharged cell={} rockets={}",
+                state.id(),
+                index,
+                self.rocket_count(state)
+            );
+        } else {
+            warn!(
+                "planet_id={} sunray_overflow: capacity exceeded, sunray discarded",
+                state.id()
+            );
+        }
+        debug!("planet_id={} outgoing_sunray_ack", state.id());
+    }
+}
 
-        let result = ai.handle_orchestrator_msg(state, generator, combinator, msg);
-        assert!(
-            !result.is_some(),
-            "Expected no response from orchestrator message handler"
-        );
+impl PlanetAI for AI {
+    /// Activates the AI and enables message processing.
+    ///
+    /// Called by the planet runtime when initialization completes.
+    /// After this call, incoming messages to the AI will be processed normally.
+    /// Calling it on an already running AI is a no-op.
+    ///
+    /// # Side Effects
+    /// - Applies a [`reset`](AI::reset) requested while stopped
+    /// - Leaves [`PlanetMode::Stopped`]; the maintenance switch decides
+    ///   between running and maintenance on the next message
+    /// - Logs an informational `ai_started` message on an actual transition,
+    ///   or a debug `ai_already_running` message otherwise
+    fn on_start(&mut self, state: &PlanetState, _: &Generator, _: &Combinator) {
+        self.apply_reset(state.id());
+        if self.mode != PlanetMode::Stopped {
+            debug!("planet_id={} ai_already_running", state.id());
+            return;
+        }
+        self.mode = PlanetMode::Running;
+        info!("planet_id={} ai_started", state.id());
+        self.emit(AuditEvent::Started {
+            planet_id: state.id(),
+        });
     }
 
-    #[test]
-    fn test_handle_explorer_msg_returns_none() {
-        let mut ai = AI::new();
-        let state = &mut PlanetState::default();
-        let generator = &Generator::default();
-        let combinator = &Combinator::default();
-        let msg = ExplorerToPlanet::SupportedResourceRequest { explorer_id: 0 }; // Adjust based on actual enum
+    /// Deactivates the AI and stops all message processing.
+    ///
+    /// All message handlers will return `None` until the AI is restarted.
+    /// Calling it on an already stopped AI is a no-op.
+    ///
+    /// # Side Effects
+    /// - Switches to [`PlanetMode::Stopped`]
+    /// - Logs an informational `ai_stopped` message on an actual transition,
+    ///   or a debug `ai_already_stopped` message otherwise
+    /// - Applies a [`reset`](AI::reset) requested while running
+    fn on_stop(&mut self, state: &PlanetState, _: &Generator, _: &Combinator) {
+        if self.mode == PlanetMode::Stopped {
+            debug!("planet_id={} ai_already_stopped", state.id());
+            return;
+        }
+        self.mode = PlanetMode::Stopped;
+        info!("planet_id={} ai_stopped", state.id());
+        self.emit(AuditEvent::Stopped {
+            planet_id: state.id(),
+        });
+        self.apply_reset(state.id());
+    }
 
-        let result = ai.handle_explorer_msg(state, generator, combinator, msg);
-        assert!(
-            !result.is_some(),
-            "Expected no response from explorer message handler"
-        );
+    /// Registers an explorer announced by an `IncomingExplorerRequest`.
+    ///
+    /// The planet runtime stores the explorer's sender, replacing any stale
+    /// sender for the same id, and answers the orchestrator with an
+    /// `IncomingExplorerResponse`.
+    fn on_explorer_arrival(
+        &mut self,
+        state: &mut PlanetState,
+        _: &Generator,
+        _: &Combinator,
+        explorer_id: u32,
+    ) {
+        self.attach_explorer(state.id(), explorer_id);
     }
 
-    #[test]
-    fn test_handle_asteroid_returns_none() {
-        let mut ai = AI::new();
-        let state = &mut PlanetState::default();
-        let generator = &Generator::default();
-        let combinator = &Combinator::default();
+    /// Detaches an explorer announced by an `OutgoingExplorerRequest`.
+    ///
+    /// The planet runtime always answers with `res: Ok(())`, so an unknown
+    /// explorer id can only be reported through a warning log.
+    fn on_explorer_departure(
+        &mut self,
+        state: &mut PlanetState,
+        _: &Generator,
+        _: &Combinator,
+        explorer_id: u32,
+    ) {
+        if let Err(e) = self.detach_explorer(state.id(), explorer_id) {
+            warn!("planet_id={} explorer_departure: {}", state.id(), e);
+        }
+    }
 
-        let result = ai.handle_asteroid(state, generator, combinator);
-        assert!(
-            !result.is_some(),
-            "Expected no rocket launched on asteroid event"
+    /// Handles a sunray by delegating to the internal charging logic.
+    ///
+    /// # Behavior
+    /// - Consumes the incoming sunray to charge the first available energy cell.
+    /// - Attempts to build a rocket immediately after charging, until the
+    ///   rocket reserve is full.
+    /// - This is a wrapper around the [`AI::charge_cell`] method.
+    /// - Sunrays received in maintenance are buffered and replayed once the
+    ///   AI is running again.
+    ///
+    /// The `common_game` planet runtime answers `Stopped` itself while the
+    /// AI is stopped, so such sunrays never get here: [`Trip`](crate::Trip)
+    /// buffers and replays them instead.
+    ///
+    /// A sunray always charges a single cell. `common_game`'s [`Sunray`]
+    /// carries no intensity or other payload, and an [`EnergyCell`] is
+    /// either charged or not, so there is nothing to scale the charge by
+    /// and no fractional charge to hand out.
+    ///
+    /// Sunrays are not deduplicated either: without an identifier, a sunray
+    /// the orchestrator sends twice cannot be told from two sunrays, so each
+    /// is acknowledged and charges its own cell. Avoiding retransmissions is
+    /// up to the orchestrator.
+    fn handle_sunray(&mut self, state: &mut PlanetState, _: &Generator, _: &Combinator, s: Sunray) {
+        self.record(|s| s.sunrays_received += 1);
+        if self.mode(state.id()) == PlanetMode::Running {
+            self.replay_sunrays(state);
+            for explorer in self.explorers.values_mut() {
+                explorer.cells_consumed = 0;
+                explorer.waiting = explorer.waiting.saturating_sub(1);
+            }
+            if self.config.dry_run {
+                info!(
+                    "planet_id={} dry_run: sunray would_charge={}",
+                    state.id(),
+                    state.cells_iter().any(|cell| !cell.is_charged())
+                );
+                return;
+            }
+            self.charge_cell(state, s);
+        } else {
+            self.buffer_sunray(state.id(), s);
+        }
+    }
+
+    /// Builds the [`DummyPlanetState`] reported in an `InternalStateResponse`.
+    ///
+    /// # Behavior
+    /// - Reports the charge of every energy cell and how many are charged.
+    /// - Reports whether the planet or the AI's reserve holds a rocket.
+    /// - Refreshes the `stockpiled_rockets` count in the AI's [`AiStats`].
+    /// - Runs a due [`housekeep`](AI::housekeep) round first while running.
+    ///
+    /// The `planet_id` of the response is filled in by the planet runtime
+    /// from [`PlanetState::id`]. [`DummyPlanetState`] cannot carry the AI's
+    /// activity counters nor the number of stockpiled rockets, so they are
+    /// kept alongside it in [`AiStats`] and reported in an info log line.
+    ///
+    /// # Returns
+    /// A `DummyPlanetState` representing the current state of the planet.
+    fn handle_internal_state_req(
+        &mut self,
+        state: &mut PlanetState,
+        _: &Generator,
+        _: &Combinator,
+    ) -> DummyPlanetState {
+        if self.mode(state.id()) == PlanetMode::Running {
+            self.housekeep(state);
+        }
+        let energy_cells: Vec<bool> = state.cells_iter().map(EnergyCell::is_charged).collect();
+        let charged_cells_count = state.cells_iter().filter(|c| c.is_charged()).count();
+        let stockpiled_rockets = self.rocket_count(state);
+        let has_rocket = stockpiled_rockets > 0;
+        self.record(|s| {
+            s.stockpiled_rockets = stockpiled_rockets;
+            s.charged_cells = charged_cells_count;
+        });
+        let counters = *self
+            .stats
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        info!(
+            "planet_id={} internal_state: sunrays={} rockets_built={} rockets_launched={} resources_generated={} stockpiled_rockets={} health={}",
+            state.id(),
+            counters.sunrays_received,
+            counters.rockets_built,
+            counters.rockets_launched,
+            counters.resources_generated,
+            counters.stockpiled_rockets,
+            counters.health
         );
-    }*/
+        debug!(
+            "planet_id={} outgoing_internal_state: charged_cells={} has_rocket={}",
+            state.id(),
+            charged_cells_count,
+            has_rocket
+        );
+        DummyPlanetState {
+            energy_cells,
+            charged_cells_count,
+            has_rocket,
+        }
+    }
+
+    /// Handles messages from an explorer interacting with this planet.
+    ///
+    /// The AI responds to explorer queries about:
+    /// - Supported basic resources
+    /// - Supported combination rules
+    /// - Energy availability
+    /// - Requests to generate basic resources
+    ///
+    /// Unsupported combinations or unsupported resource requests result in
+    /// an empty or error response.
+    ///
+    /// # Behavior
+    ///
+    /// - If the AI is stopped, returns `None`.
+    /// - Messages carrying the id of an explorer that is not on the planet
+    ///   are ignored with a warning, so one explorer cannot speak for
+    ///   another that already left or never arrived.
+    /// - Basic resource generation is supported for the planet's generation
+    ///   rules only, as narrowed through
+    ///   [`TripHandle::set_generation_rules`](crate::TripHandle::set_generation_rules);
+    ///   other resources yield `None`.
+    /// - Combination attempts are validated against the combinator recipes and
+    ///   consume one charged energy cell; failures hand the inputs back in an
+    ///   `Err` payload.
+    ///
+    /// # Returns
+    /// - `Some(response)` if a valid response exists.
+    /// - `None` if the AI is stopped, the explorer is unknown or the request
+    ///   cannot be fulfilled, or in maintenance for generation and
+    ///   combination requests.
+    fn handle_explorer_msg(
+        &mut self,
+        state: &mut PlanetState,
+        generator: &Generator,
+        comb: &Combinator,
+        msg: ExplorerToPlanet,
+    ) -> Option<PlanetToExplorer> {
+        let mode = self.mode(state.id());
+        if mode == PlanetMode::Stopped {
+            return None;
+        }
+        if mode == PlanetMode::Running {
+            self.replay_sunrays(state);
+        }
+        self.apply_generation_rules(state.id(), generator);
+        if self.over_rate_limit(state.id(), msg.explorer_id()) {
+            return None;
+        }
+        let Some(explorer) = self.explorers.get_mut(&msg.explorer_id()) else {
+            warn!(
+                "planet_id={} explorer_id={} unregistered_explorer: message ignored",
+                state.id(),
+                msg.explorer_id()
+            );
+            return None;
+        };
+        if mode == PlanetMode::Maintenance
+            && matches!(
+                msg,
+                ExplorerToPlanet::GenerateResourceRequest { .. }
+                    | ExplorerToPlanet::CombineResourceRequest { .. }
+            )
+        {
+            debug!(
+                "planet_id={} explorer_id={} msg_refused: maintenance",
+                state.id(),
+                msg.explorer_id()
+            );
+            return None;
+        }
+        explorer.requests_served += 1;
+        self.record(|s| s.explorers_served += 1);
+        match msg {
+            ExplorerToPlanet::SupportedResourceRequest { explorer_id } => {
+                debug!(
+                    "planet_id={} explorer_id={} outgoing_supported_resource_response",
+                    state.id(),
+                    explorer_id
+                );
+                Some(PlanetToExplorer::SupportedResourceResponse {
+                    resource_list: self.supported_resources(generator).clone(),
+                })
+            }
+            ExplorerToPlanet::GenerateResourceRequest {
+                explorer_id,
+                resource,
+            } => self.generate_resource(state, generator, explorer_id, resource),
+            ExplorerToPlanet::SupportedCombinationRequest { explorer_id, .. } => {
+                debug!(
+                    "planet_id={} explorer_id={} outgoing_supported_combination_response",
+                    state.id(),
+                    explorer_id
+                );
+                let powered = !self.config.actionable_combinations
+                    || state.cells_iter().any(EnergyCell::is_charged);
+                Some(PlanetToExplorer::SupportedCombinationResponse {
+                    combination_list: if powered {
+                        self.recipes.combinations(comb).clone()
+                    } else {
+                        HashSet::new()
+                    },
+                })
+            }
+            ExplorerToPlanet::CombineResourceRequest { explorer_id, msg } => {
+                Some(self.combine_resource(state, comb, explorer_id, msg))
+            }
+            ExplorerToPlanet::AvailableEnergyCellRequest { explorer_id } => {
+                let charged = state.cells_iter().filter(|&cell| cell.is_charged()).count();
+                let count = AI::cell_count(state.id(), charged);
+                // The response only carries the charged cells, see
+                // `Trip::planet_info` for the total and the rockets.
+                debug!(
+                    "planet_id={} explorer_id={} outgoing_energy_cell_count={} total={}",
+                    state.id(),
+                    explorer_id,
+                    count,
+                    state.cells_count()
+                );
+                Some(PlanetToExplorer::AvailableEnergyCellResponse {
+                    available_cells: count,
+                })
+            }
+        }
+    }
+
+    /// Handles an asteroid impact event.
+    ///
+    /// # Behavior
+    ///
+    /// The reserve is always drained before any cell is touched, so charged
+    /// cells stay available for resource generation:
+    ///
+    /// 1. [`launch_from_reserve`](AI::launch_from_reserve) launches a rocket
+    ///    that already exists in the state or in the AI's stockpile.
+    /// 2. Only if there is none, [`launch_new_rocket`](AI::launch_new_rocket)
+    ///    picks a charged energy cell and attempts to build a rocket on it,
+    ///    retrying on another charged cell if the build fails.
+    /// - If construction fails or no charged cell exists, `None` is returned.
+    ///
+    /// Planet types that cannot hold rockets always get `None`. The
+    /// orchestrator decides whether the planet survives from whether a
+    /// rocket comes back, see [`asteroid_defense`].
+    ///
+    /// # Side Effects
+    /// - Mutates the planet state by consuming energy cells and creating rockets.
+    /// - Logs informational or warning messages depending on outcome.
+    ///
+    /// - Costs the planet [`AiConfig::asteroid_damage`] health when no
+    ///   rocket is launched, see [`take_hit`](AI::take_hit).
+    ///
+    /// # Returns
+    /// `Some(Rocket)` if a rocket is launched, otherwise `None`.
+    fn handle_asteroid(
+        &mut self,
+        state: &mut PlanetState,
+        _: &Generator,
+        _: &Combinator,
+    ) -> Option<Rocket> {
+        let rocket = match self.mode(state.id()) {
+            PlanetMode::Stopped => None,
+            PlanetMode::Maintenance => self.defend(state, false),
+            PlanetMode::Running => {
+                self.replay_sunrays(state);
+                self.defend(state, true)
+            }
+        };
+        if rocket.is_none() {
+            self.take_hit(state.id());
+        }
+        // A rocket built to answer the asteroid uses up a cell, keep the
+        // count current so the orchestrator can tell when to send sunrays.
+        let charged_cells = state.cells_iter().filter(|c| c.is_charged()).count();
+        self.record(|s| s.charged_cells = charged_cells);
+        rocket
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use common_game::components::asteroid::Asteroid;
+    use common_game::components::planet::{Planet, PlanetType};
+    use common_game::protocols::orchestrator_planet::{OrchestratorToPlanet, PlanetToOrchestrator};
+    use crossbeam_channel::{Receiver, Sender};
+    use std::time::Duration;
+
+    /// Channel ends of a running planet driven by our [`AI`], with a single
+    /// explorer (id `0`) already registered.
+    struct TestPlanet {
+        orch_tx: Sender<OrchestratorToPlanet>,
+        planet_rx: Receiver<PlanetToOrchestrator>,
+        expl_tx: Sender<ExplorerToPlanet>,
+        expl_rx: Receiver<PlanetToExplorer>,
+    }
+
+    impl TestPlanet {
+        fn spawn(
+            id: u32,
+            planet_type: PlanetType,
+            gen_rules: Vec<BasicResourceType>,
+            comb_rules: Vec<ComplexResourceType>,
+        ) -> Self {
+            Self::spawn_with_ai(id, planet_type, AI::new(), gen_rules, comb_rules)
+        }
+
+        fn spawn_with_ai(
+            id: u32,
+            planet_type: PlanetType,
+            ai: AI,
+            gen_rules: Vec<BasicResourceType>,
+            comb_rules: Vec<ComplexResourceType>,
+        ) -> Self {
+            let (orch_tx, orch_rx) = crossbeam_channel::unbounded();
+            let (planet_tx, planet_rx) = crossbeam_channel::unbounded();
+            let (expl_tx, expl_rx) = crossbeam_channel::unbounded();
+            let (to_expl_tx, to_expl_rx) = crossbeam_channel::unbounded();
+
+            let mut planet = Planet::new(
+                id,
+                planet_type,
+                Box::new(ai.for_planet_type(planet_type)),
+                gen_rules,
+                comb_rules,
+                (orch_rx, planet_tx),
+                expl_rx,
+            )
+            .expect("Failed to create planet");
+            std::thread::spawn(move || planet.run());
+
+            let planet = Self {
+                orch_tx,
+                planet_rx,
+                expl_tx,
+                expl_rx: to_expl_rx,
+            };
+            planet.send_orch(OrchestratorToPlanet::StartPlanetAI);
+            planet.send_orch(OrchestratorToPlanet::IncomingExplorerRequest {
+                explorer_id: 0,
+                new_sender: to_expl_tx,
+            });
+            planet
+        }
+
+        fn send_orch(&self, msg: OrchestratorToPlanet) -> PlanetToOrchestrator {
+            self.orch_tx.send(msg).expect("Failed to send message");
+            self.planet_rx
+                .recv_timeout(Duration::from_millis(500))
+                .expect("No message received")
+        }
+
+        fn send_expl(&self, msg: ExplorerToPlanet) -> PlanetToExplorer {
+            self.expl_tx.send(msg).expect("Failed to send message");
+            self.expl_rx
+                .recv_timeout(Duration::from_millis(500))
+                .expect("No message received")
+        }
+
+        fn sunray(&self) {
+            self.send_orch(OrchestratorToPlanet::Sunray(Sunray::default()));
+        }
+
+        fn asteroid(&self) -> Option<Rocket> {
+            match self.send_orch(OrchestratorToPlanet::Asteroid(Asteroid::default())) {
+                PlanetToOrchestrator::AsteroidAck { rocket, .. } => rocket,
+                _other => panic!("Wrong response received"),
+            }
+        }
+
+        fn generate(&self, resource: BasicResourceType) -> Option<BasicResource> {
+            match self.send_expl(ExplorerToPlanet::GenerateResourceRequest {
+                explorer_id: 0,
+                resource,
+            }) {
+                PlanetToExplorer::GenerateResourceResponse { resource } => resource,
+                _other => panic!("Wrong response received"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_ai_initial_state() {
+        let ai = AI::new();
+        assert_eq!(
+            ai.mode,
+            PlanetMode::Stopped,
+            "AI should start in stopped state"
+        );
+    }
+
+    #[test]
+    fn test_config_debug_lists_policies() {
+        let config = AiConfig {
+            rocket_reserve: 3,
+            cell_selection: CellSelection::RoundRobin,
+            generation_quota: Some(2),
+            ..AiConfig::default()
+        };
+        let formatted = format!("{config:?}");
+        assert!(formatted.contains("rocket_reserve: 3"));
+        assert!(formatted.contains("auto_build: true"));
+        assert!(formatted.contains("cell_selection: RoundRobin"));
+        assert!(formatted.contains("generation_quota: Some(2)"));
+
+        let mut ai = AI::with_config(config.clone());
+        assert_eq!(ai.config, config);
+        ai.explorers.insert(7, Explorer::default());
+        let formatted = format!("{ai:?}");
+        assert!(formatted.contains("rocket_reserve: 3"));
+        assert!(formatted.contains("explorers: [7]"));
+        assert!(formatted.contains("mode: Stopped"));
+    }
+
+    /// [`PlanetAI`] that runs a closure against the planet state on the
+    /// first sunray and sends back its result.
+    struct Probe<F, R> {
+        f: Option<F>,
+        result_tx: Sender<R>,
+    }
+
+    impl<F, R> PlanetAI for Probe<F, R>
+    where
+        F: FnOnce(&mut PlanetState, &Generator, &Combinator) -> R + Send,
+        R: Send,
+    {
+        fn handle_sunray(
+            &mut self,
+            state: &mut PlanetState,
+            generator: &Generator,
+            comb: &Combinator,
+            _: Sunray,
+        ) {
+            if let Some(f) = self.f.take() {
+                let _ = self.result_tx.send(f(state, generator, comb));
+            }
+        }
+
+        fn handle_asteroid(
+            &mut self,
+            _: &mut PlanetState,
+            _: &Generator,
+            _: &Combinator,
+        ) -> Option<Rocket> {
+            None
+        }
+
+        fn handle_internal_state_req(
+            &mut self,
+            state: &mut PlanetState,
+            _: &Generator,
+            _: &Combinator,
+        ) -> DummyPlanetState {
+            state.to_dummy()
+        }
+
+        fn handle_explorer_msg(
+            &mut self,
+            _: &mut PlanetState,
+            _: &Generator,
+            _: &Combinator,
+            _: ExplorerToPlanet,
+        ) -> Option<PlanetToExplorer> {
+            None
+        }
+    }
+
+    /// Runs `f` against the state of a freshly constructed type A planet
+    /// generating Oxygen.
+    ///
+    /// `PlanetState` has no public constructor and `common_game` only lends
+    /// it mutably to the AI of a running planet, so `f` runs inside the
+    /// sunray handler of a throwaway planet.
+    fn with_planet_state<F, R>(f: F) -> R
+    where
+        F: FnOnce(&mut PlanetState, &Generator, &Combinator) -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        let (orch_tx, orch_rx) = crossbeam_channel::unbounded();
+        let (planet_tx, _planet_rx) = crossbeam_channel::unbounded();
+        let (_expl_tx, expl_rx) = crossbeam_channel::unbounded();
+        let (result_tx, result_rx) = crossbeam_channel::bounded(1);
+        let mut planet = Planet::new(
+            0,
+            PlanetType::A,
+            Box::new(Probe {
+                f: Some(f),
+                result_tx,
+            }),
+            vec![BasicResourceType::Oxygen],
+            vec![],
+            (orch_rx, planet_tx),
+            expl_rx,
+        )
+        .expect("Failed to create planet");
+        let handle = std::thread::spawn(move || planet.run());
+
+        orch_tx
+            .send(OrchestratorToPlanet::StartPlanetAI)
+            .expect("Failed to send message");
+        orch_tx
+            .send(OrchestratorToPlanet::Sunray(Sunray::default()))
+            .expect("Failed to send message");
+        let result = result_rx
+            .recv_timeout(Duration::from_millis(500))
+            .expect("Probe did not run");
+        drop(orch_tx);
+        let _ = handle.join();
+        result
+    }
+
+    /// Builds a planet that is never run, only used to borrow its state.
+    fn idle_planet() -> Planet {
+        let (_orch_tx, orch_rx) = crossbeam_channel::unbounded();
+        let (planet_tx, _planet_rx) = crossbeam_channel::unbounded();
+        let (_expl_tx, expl_rx) = crossbeam_channel::unbounded();
+        Planet::new(
+            0,
+            PlanetType::A,
+            Box::new(AI::new()),
+            vec![BasicResourceType::Oxygen],
+            vec![],
+            (orch_rx, planet_tx),
+            expl_rx,
+        )
+        .expect("Failed to create planet")
+    }
+
+    #[test]
+    fn test_detach_known_and_unknown_explorer() {
+        let mut ai = AI::new();
+        ai.attach_explorer(0, 1);
+        assert!(ai.explorers.contains_key(&1));
+
+        assert!(ai.detach_explorer(0, 1).is_ok());
+        assert!(
+            !ai.explorers.contains_key(&1),
+            "Explorer state must be dropped"
+        );
+        assert!(ai.detach_explorer(0, 1).is_err(), "Explorer already left");
+        assert!(ai.detach_explorer(0, 7).is_err(), "Explorer never arrived");
+    }
+
+    #[test]
+    fn test_unregistered_explorer_is_ignored() {
+        let planet = TestPlanet::spawn(0, PlanetType::A, vec![BasicResourceType::Oxygen], vec![]);
+        planet
+            .expl_tx
+            .send(ExplorerToPlanet::AvailableEnergyCellRequest { explorer_id: 9 })
+            .expect("Failed to send message");
+        assert!(
+            planet
+                .expl_rx
+                .recv_timeout(Duration::from_millis(100))
+                .is_err(),
+            "Unregistered explorer must not be answered"
+        );
+
+        match planet.send_expl(ExplorerToPlanet::AvailableEnergyCellRequest { explorer_id: 0 }) {
+            PlanetToExplorer::AvailableEnergyCellResponse { available_cells } => {
+                assert_eq!(available_cells, 0);
+            }
+            _other => panic!("Wrong response received"),
+        }
+    }
+
+    #[test]
+    fn test_start_stop_are_idempotent() {
+        let planet = idle_planet();
+        let (state, generator, comb) = (planet.state(), planet.generator(), planet.combinator());
+        let mut ai = AI::new();
+
+        ai.on_start(state, generator, comb);
+        ai.on_start(state, generator, comb);
+        assert_eq!(
+            ai.mode,
+            PlanetMode::Running,
+            "AI should be running after start()"
+        );
+
+        ai.on_stop(state, generator, comb);
+        ai.on_stop(state, generator, comb);
+        assert_eq!(
+            ai.mode,
+            PlanetMode::Stopped,
+            "AI should be stopped after stop()"
+        );
+
+        ai.on_start(state, generator, comb);
+        assert_eq!(
+            ai.mode,
+            PlanetMode::Running,
+            "AI should restart after stop()"
+        );
+    }
+
+    #[test]
+    fn test_maintenance_sunrays_are_buffered_and_capped() {
+        let mut ai = AI::with_sunray_buffer(2);
+        for _ in 0..3 {
+            ai.buffer_sunray(0, Sunray::default());
+        }
+        assert_eq!(ai.pending_sunrays.len(), 2);
+
+        let mut ai = AI::with_sunray_buffer(0);
+        ai.buffer_sunray(0, Sunray::default());
+        assert!(ai.pending_sunrays.is_empty());
+    }
+
+    #[test]
+    fn test_rocket_reserve_survives_asteroid_shower() {
+        let planet = TestPlanet::spawn_with_ai(
+            0,
+            PlanetType::A,
+            AI::with_reserve(2, true),
+            vec![BasicResourceType::Oxygen],
+            vec![],
+        );
+        planet.sunray();
+        planet.sunray();
+
+        assert!(
+            planet.asteroid().is_some(),
+            "First asteroid must be survived"
+        );
+        assert!(
+            planet.asteroid().is_some(),
+            "Second asteroid must be survived"
+        );
+        assert!(planet.asteroid().is_none(), "No rocket should be left");
+    }
+
+    #[test]
+    fn test_asteroid_drains_reserve_before_cells() {
+        let ai = AI::with_reserve(2, true);
+        let stats = ai.stats();
+        let planet = TestPlanet::spawn_with_ai(
+            0,
+            PlanetType::A,
+            ai,
+            vec![BasicResourceType::Oxygen],
+            vec![],
+        );
+        // Two sunrays fill the reserve, the next three only charge cells.
+        for _ in 0..5 {
+            planet.sunray();
+        }
+
+        assert!(planet.asteroid().is_some());
+        match planet.send_orch(OrchestratorToPlanet::InternalStateRequest) {
+            PlanetToOrchestrator::InternalStateResponse { planet_state, .. } => {
+                assert_eq!(planet_state.charged_cells_count, 3, "No cell is consumed");
+                assert!(planet_state.has_rocket, "One reserve rocket is left");
+            }
+            _other => panic!("Wrong response received"),
+        }
+        assert_eq!(stats.lock().unwrap().rockets_built, 2);
+    }
+
+    #[test]
+    fn test_configured_reserve_is_respected() {
+        let planet = TestPlanet::spawn_with_ai(
+            0,
+            PlanetType::A,
+            AI::with_reserve(3, true),
+            vec![BasicResourceType::Oxygen],
+            vec![],
+        );
+        for _ in 0..4 {
+            planet.sunray();
+        }
+
+        match planet.send_orch(OrchestratorToPlanet::InternalStateRequest) {
+            PlanetToOrchestrator::InternalStateResponse { planet_state, .. } => {
+                assert_eq!(planet_state.charged_cells_count, 1);
+            }
+            _other => panic!("Wrong response received"),
+        }
+        // Three reserved rockets, then one built from the charged cell.
+        for _ in 0..4 {
+            assert!(planet.asteroid().is_some());
+        }
+        assert!(planet.asteroid().is_none());
+    }
+
+    #[test]
+    fn test_auto_build_can_be_disabled() {
+        let planet = TestPlanet::spawn_with_ai(
+            0,
+            PlanetType::A,
+            AI::with_reserve(1, false),
+            vec![BasicResourceType::Oxygen],
+            vec![],
+        );
+        planet.sunray();
+
+        match planet.send_orch(OrchestratorToPlanet::InternalStateRequest) {
+            PlanetToOrchestrator::InternalStateResponse { planet_state, .. } => {
+                assert_eq!(planet_state.charged_cells_count, 1);
+                assert!(!planet_state.has_rocket, "No rocket must be built");
+            }
+            _other => panic!("Wrong response received"),
+        }
+    }
+
+    #[test]
+    fn test_asteroid_ack_carries_rocket_built_on_hit() {
+        let planet = TestPlanet::spawn_with_ai(
+            0,
+            PlanetType::A,
+            AI::with_reserve(1, false),
+            vec![BasicResourceType::Oxygen],
+            vec![],
+        );
+        planet.sunray();
+
+        match planet.send_orch(OrchestratorToPlanet::Asteroid(Asteroid::default())) {
+            PlanetToOrchestrator::AsteroidAck {
+                planet_id: 0,
+                rocket: Some(_),
+            } => {}
+            _other => panic!("Asteroid must be answered with a launched rocket"),
+        }
+    }
+
+    #[test]
+    fn test_activity_counters() {
+        let ai = AI::new();
+        let stats = ai.stats();
+        let planet = TestPlanet::spawn_with_ai(
+            0,
+            PlanetType::A,
+            ai,
+            vec![BasicResourceType::Oxygen],
+            vec![],
+        );
+        for _ in 0..3 {
+            planet.sunray();
+        }
+        assert!(planet.generate(BasicResourceType::Oxygen).is_some());
+        assert!(planet.asteroid().is_some());
+        planet.send_orch(OrchestratorToPlanet::InternalStateRequest);
+
+        assert_eq!(
+            *stats.lock().unwrap(),
+            AiStats {
+                sunrays_received: 3,
+                rockets_built: 1,
+                rockets_launched: 1,
+                resources_generated: 1,
+                yields: ResourceYields {
+                    oxygen: 1,
+                    ..ResourceYields::default()
+                },
+                complex_resources_produced: 0,
+                complex_yields: ComplexYields::default(),
+                explorers_served: 1,
+                rocket_build_failures: 0,
+                stockpiled_rockets: 0,
+                reserve_rockets: 0,
+                charged_cells: 1,
+                explorers: 1,
+                health: 100,
+                rate_limited: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn test_internal_state_reports_stockpiled_rockets() {
+        let ai = AI::with_reserve(3, true);
+        let stats = ai.stats();
+        let planet = TestPlanet::spawn_with_ai(
+            0,
+            PlanetType::A,
+            ai,
+            vec![BasicResourceType::Oxygen],
+            vec![],
+        );
+        for _ in 0..4 {
+            planet.sunray();
+        }
+
+        match planet.send_orch(OrchestratorToPlanet::InternalStateRequest) {
+            PlanetToOrchestrator::InternalStateResponse { planet_state, .. } => {
+                assert!(planet_state.has_rocket);
+            }
+            _other => panic!("Wrong response received"),
+        }
+        assert_eq!(stats.lock().unwrap().stockpiled_rockets, 3);
+
+        assert!(planet.asteroid().is_some());
+        planet.send_orch(OrchestratorToPlanet::InternalStateRequest);
+        assert_eq!(stats.lock().unwrap().stockpiled_rockets, 2);
+    }
+
+    #[test]
+    fn test_asteroid_reports_charged_cells_left() {
+        let ai = AI::with_reserve(0, false);
+        let stats = ai.stats();
+        let planet = TestPlanet::spawn_with_ai(
+            0,
+            PlanetType::A,
+            ai,
+            vec![BasicResourceType::Oxygen],
+            vec![],
+        );
+        planet.sunray();
+        planet.sunray();
+
+        assert!(planet.asteroid().is_some());
+        assert_eq!(stats.lock().unwrap().charged_cells, 1);
+    }
+
+    #[test]
+    fn test_overflowing_sunray_is_stockpiled_as_rocket() {
+        let ai = AI::new();
+        let stats = ai.stats();
+        let planet = TestPlanet::spawn_with_ai(
+            0,
+            PlanetType::A,
+            ai,
+            vec![BasicResourceType::Oxygen],
+            vec![],
+        );
+        // The first sunray becomes the reserve rocket, the next five saturate
+        // the cells and the last one overflows.
+        for _ in 0..7 {
+            planet.sunray();
+        }
+
+        match planet.send_orch(OrchestratorToPlanet::InternalStateRequest) {
+            PlanetToOrchestrator::InternalStateResponse { planet_state, .. } => {
+                assert_eq!(planet_state.charged_cells_count, 5);
+            }
+            _other => panic!("Wrong response received"),
+        }
+        assert_eq!(stats.lock().unwrap().rockets_built, 2);
+        assert!(planet.asteroid().is_some());
+        assert!(planet.asteroid().is_some());
+    }
+
+    #[test]
+    fn test_undefended_asteroids_cost_health() {
+        let ai = AI::with_config(AiConfig {
+            auto_build: false,
+            max_health: 100,
+            asteroid_damage: 30,
+            ..AiConfig::default()
+        });
+        let stats = ai.stats();
+        let planet = TestPlanet::spawn_with_ai(
+            0,
+            PlanetType::A,
+            ai,
+            vec![BasicResourceType::Oxygen],
+            vec![],
+        );
+        let health = || stats.lock().unwrap().health;
+        assert_eq!(health(), 100);
+
+        assert!(planet.asteroid().is_none());
+        assert!(planet.asteroid().is_none());
+        assert_eq!(health(), 40);
+
+        planet.sunray();
+        assert!(planet.asteroid().is_some());
+        assert_eq!(health(), 40, "A defended asteroid must not cost health");
+
+        assert!(planet.asteroid().is_none());
+        assert!(planet.asteroid().is_none());
+        assert_eq!(health(), 0, "Health must stop at game over");
+    }
+
+    #[test]
+    fn test_brittle_planet_launches_a_single_rocket_per_asteroid() {
+        let spawn = |planet_type| {
+            let ai = AI::with_config(AiConfig {
+                max_health: 100,
+                asteroid_damage: 60,
+                ..AiConfig::default()
+            });
+            let stats = ai.stats();
+            let planet = TestPlanet::spawn_with_ai(
+                0,
+                planet_type,
+                ai,
+                vec![BasicResourceType::Oxygen],
+                vec![],
+            );
+            (planet, stats)
+        };
+        let (a, a_stats) = spawn(PlanetType::A);
+        let (c, c_stats) = spawn(PlanetType::C);
+
+        for planet in [&a, &c] {
+            assert!(planet.asteroid().is_none(), "No rocket can be built");
+        }
+        assert_eq!(a_stats.lock().unwrap().health, 40);
+        assert_eq!(
+            c_stats.lock().unwrap().health,
+            10,
+            "The crust of C takes more from an impact"
+        );
+
+        for planet in [&a, &c] {
+            planet.sunray();
+            assert!(
+                planet.asteroid().is_some(),
+                "One rocket must be launched whenever one can be built"
+            );
+        }
+        assert_eq!(a_stats.lock().unwrap().health, 40);
+        assert_eq!(c_stats.lock().unwrap().health, 10);
+        assert_eq!(c_stats.lock().unwrap().rockets_launched, 1);
+
+        // A rocket in reserve and a charged cell last two asteroids.
+        let c = TestPlanet::spawn_with_ai(
+            0,
+            PlanetType::C,
+            AI::with_reserve(1, true),
+            vec![BasicResourceType::Oxygen],
+            vec![],
+        );
+        c.sunray();
+        c.sunray();
+        assert!(c.asteroid().is_some());
+        assert!(
+            c.asteroid().is_some(),
+            "The second rocket must be kept for the next asteroid"
+        );
+    }
+
+    #[test]
+    fn test_hull_of_b_outlasts_d() {
+        let spawn = |planet_type, comb_rules| {
+            let ai = AI::with_config(AiConfig {
+                max_health: 100,
+                asteroid_damage: 50,
+                ..AiConfig::default()
+            });
+            let stats = ai.stats();
+            let planet = TestPlanet::spawn_with_ai(
+                0,
+                planet_type,
+                ai,
+                vec![BasicResourceType::Oxygen],
+                comb_rules,
+            );
+            (planet, stats)
+        };
+        let (b, b_stats) = spawn(PlanetType::B, vec![ComplexResourceType::Water]);
+        let (d, d_stats) = spawn(PlanetType::D, vec![]);
+
+        for planet in [&b, &d] {
+            planet.sunray();
+            assert!(planet.asteroid().is_none(), "No rocket can be held");
+            assert!(planet.asteroid().is_none(), "No rocket can be held");
+        }
+        assert_eq!(
+            b_stats.lock().unwrap().health,
+            50,
+            "B keeps half its health after two hits"
+        );
+        assert_eq!(
+            d_stats.lock().unwrap().health,
+            0,
+            "D has no health left after two hits"
+        );
+    }
+
+    #[test]
+    fn test_explorer_burst_beyond_rate_limit_is_dropped() {
+        let ai = AI::with_config(AiConfig {
+            explorer_rate_limit: Some(RateLimit {
+                burst: 3,
+                per_second: 1,
+            }),
+            ..AiConfig::default()
+        });
+        let stats = ai.stats();
+        let planet = TestPlanet::spawn_with_ai(
+            0,
+            PlanetType::A,
+            ai,
+            vec![BasicResourceType::Oxygen],
+            vec![],
+        );
+
+        for _ in 0..6 {
+            planet
+                .expl_tx
+                .send(ExplorerToPlanet::SupportedResourceRequest { explorer_id: 0 })
+                .expect("Failed to send message");
+        }
+        let answered =
+            std::iter::from_fn(|| planet.expl_rx.recv_timeout(Duration::from_millis(100)).ok())
+                .count();
+        assert_eq!(answered, 3, "Only the burst must be answered");
+        let stats = *stats.lock().unwrap();
+        assert_eq!(stats.rate_limited, 3);
+        assert_eq!(stats.explorers_served, 3);
+    }
+
+    #[test]
+    fn test_token_bucket_refills_over_time() {
+        let limit = RateLimit {
+            burst: 2,
+            per_second: 4,
+        };
+        let start = Instant::now();
+        let mut explorer = Explorer::default();
+        assert!(explorer.admit(limit, start));
+        assert!(explorer.admit(limit, start));
+        assert!(!explorer.admit(limit, start));
+        assert!(explorer.admit(limit, start + Duration::from_millis(250)));
+        assert!(!explorer.admit(limit, start + Duration::from_millis(250)));
+        // Tokens never pile up past the burst.
+        let later = start + Duration::from_secs(10);
+        assert!(explorer.admit(limit, later));
+        assert!(explorer.admit(limit, later));
+        assert!(!explorer.admit(limit, later));
+    }
+
+    #[test]
+    fn test_generation_yields_one_unit_per_charged_cell() {
+        let produced = with_planet_state(|state, generator, comb| {
+            let mut ai = AI::with_config(AiConfig {
+                auto_build: false,
+                ..AiConfig::default()
+            });
+            ai.on_start(state, generator, comb);
+            ai.on_explorer_arrival(state, generator, comb, 1);
+            for _ in 0..2 {
+                ai.handle_sunray(state, generator, comb, Sunray::default());
+            }
+            // Three units asked for, one request each, with two charged cells.
+            (0..3)
+                .filter(|_| {
+                    let msg = ExplorerToPlanet::GenerateResourceRequest {
+                        explorer_id: 1,
+                        resource: BasicResourceType::Oxygen,
+                    };
+                    matches!(
+                        ai.handle_explorer_msg(state, generator, comb, msg),
+                        Some(PlanetToExplorer::GenerateResourceResponse { resource: Some(_) })
+                    )
+                })
+                .count()
+        });
+        assert_eq!(produced, 2);
+    }
+
+    #[test]
+    fn test_sunray_charges_exactly_one_cell() {
+        let charged = with_planet_state(|state, generator, comb| {
+            let mut ai = AI::with_config(AiConfig {
+                auto_build: false,
+                ..AiConfig::default()
+            });
+            ai.on_start(state, generator, comb);
+            let mut charged = Vec::new();
+            for _ in 0..2 {
+                ai.handle_sunray(state, generator, comb, Sunray::default());
+                charged.push(state.cells_iter().filter(|c| c.is_charged()).count());
+            }
+            charged
+        });
+        assert_eq!(charged, vec![1, 2]);
+    }
+
+    #[test]
+    fn test_repeated_sunrays_are_not_deduplicated() {
+        let planet = TestPlanet::spawn_with_ai(
+            0,
+            PlanetType::A,
+            AI::with_config(AiConfig {
+                auto_build: false,
+                ..AiConfig::default()
+            }),
+            vec![BasicResourceType::Oxygen],
+            vec![],
+        );
+        let sunray = || planet.send_orch(OrchestratorToPlanet::Sunray(Sunray::default()));
+        assert!(matches!(
+            sunray(),
+            PlanetToOrchestrator::SunrayAck { planet_id: 0 }
+        ));
+        assert!(matches!(
+            sunray(),
+            PlanetToOrchestrator::SunrayAck { planet_id: 0 }
+        ));
+
+        match planet.send_expl(ExplorerToPlanet::AvailableEnergyCellRequest { explorer_id: 0 }) {
+            PlanetToExplorer::AvailableEnergyCellResponse { available_cells } => {
+                assert_eq!(available_cells, 2);
+            }
+            _other => panic!("Wrong response received"),
+        }
+    }
+
+    #[test]
+    fn test_auto_build_waits_for_threshold() {
+        let ai = AI::with_config(AiConfig {
+            build_threshold: 3,
+            ..AiConfig::default()
+        });
+        let stats = ai.stats();
+        let planet = TestPlanet::spawn_with_ai(
+            0,
+            PlanetType::A,
+            ai,
+            vec![BasicResourceType::Oxygen],
+            vec![],
+        );
+        let charged_cells = || match planet.send_orch(OrchestratorToPlanet::InternalStateRequest) {
+            PlanetToOrchestrator::InternalStateResponse { planet_state, .. } => {
+                planet_state.charged_cells_count
+            }
+            _other => panic!("Wrong response received"),
+        };
+
+        planet.sunray();
+        planet.sunray();
+        assert_eq!(charged_cells(), 2);
+        assert_eq!(stats.lock().unwrap().rockets_built, 0);
+
+        planet.sunray();
+        assert_eq!(charged_cells(), 2);
+        assert_eq!(stats.lock().unwrap().rockets_built, 1);
+    }
+
+    #[test]
+    fn test_reset_clears_runtime_state_but_keeps_config() {
+        let ai = AI::with_config(AiConfig {
+            rocket_reserve: 3,
+            max_health: 50,
+            asteroid_damage: 10,
+            ..AiConfig::default()
+        });
+        let handle = ai.handle();
+        let planet = TestPlanet::spawn_with_ai(
+            0,
+            PlanetType::A,
+            ai,
+            vec![BasicResourceType::Oxygen],
+            vec![],
+        );
+        let stats = || *handle.stats.lock().unwrap();
+        let stockpiled = || {
+            planet.send_orch(OrchestratorToPlanet::InternalStateRequest);
+            stats().stockpiled_rockets
+        };
+
+        assert!(planet.asteroid().is_none());
+        for _ in 0..3 {
+            planet.sunray();
+        }
+        assert_eq!(stockpiled(), 3);
+        assert_eq!(stats().health, 40);
+
+        handle.resets.fetch_add(1, Ordering::AcqRel);
+        planet.sunray();
+        assert_eq!(stats().sunrays_received, 4, "A running epoch is left alone");
+        planet.send_orch(OrchestratorToPlanet::StopPlanetAI);
+        planet.send_orch(OrchestratorToPlanet::StartPlanetAI);
+
+        // Only the rocket standing on the planet is left of the reserve.
+        assert_eq!(stockpiled(), 1);
+        let after = stats();
+        assert_eq!(after.sunrays_received, 0);
+        assert_eq!(after.rockets_built, 0);
+        assert_eq!(after.explorers_served, 0);
+        assert_eq!(after.health, 50);
+        assert_eq!(after.explorers, 1);
+
+        // The reserve target is still three rockets, not the default one.
+        planet.sunray();
+        assert!(stats().rockets_built > 0);
+    }
+
+    #[test]
+    fn test_yields_count_each_resource_for_the_lifetime() {
+        let ai = AI::with_config(AiConfig {
+            auto_build: false,
+            ..AiConfig::default()
+        });
+        let handle = ai.handle();
+        let planet = TestPlanet::spawn_with_ai(
+            0,
+            PlanetType::D,
+            ai,
+            vec![BasicResourceType::Oxygen, BasicResourceType::Hydrogen],
+            vec![],
+        );
+        for _ in 0..4 {
+            planet.sunray();
+        }
+        for _ in 0..3 {
+            assert!(planet.generate(BasicResourceType::Oxygen).is_some());
+        }
+        assert!(planet.generate(BasicResourceType::Hydrogen).is_some());
+
+        let expected = ResourceYields {
+            oxygen: 3,
+            hydrogen: 1,
+            ..ResourceYields::default()
+        };
+        let stats = *handle.stats.lock().unwrap();
+        assert_eq!(stats.yields, expected);
+        assert_eq!(stats.yields.get(BasicResourceType::Oxygen), 3);
+        assert_eq!(stats.resources_generated, 4);
+
+        handle.resets.fetch_add(1, Ordering::AcqRel);
+        planet.send_orch(OrchestratorToPlanet::StopPlanetAI);
+        let stats = *handle.stats.lock().unwrap();
+        assert_eq!(stats.resources_generated, 0);
+        assert_eq!(stats.yields, expected, "Yields outlive a reset");
+    }
+
+    #[test]
+    fn test_stockpile_plateaus_at_max_rockets() {
+        let ai = AI::with_config(AiConfig {
+            max_rockets: 2,
+            ..AiConfig::default()
+        });
+        let stats = ai.stats();
+        let planet = TestPlanet::spawn_with_ai(
+            0,
+            PlanetType::A,
+            ai,
+            vec![BasicResourceType::Oxygen],
+            vec![],
+        );
+        for _ in 0..20 {
+            planet.sunray();
+        }
+
+        match planet.send_orch(OrchestratorToPlanet::InternalStateRequest) {
+            PlanetToOrchestrator::InternalStateResponse { planet_state, .. } => {
+                assert_eq!(planet_state.charged_cells_count, 5);
+            }
+            _other => panic!("Wrong response received"),
+        }
+        let stats = *stats.lock().unwrap();
+        assert_eq!(stats.rockets_built, 2);
+        assert_eq!(stats.stockpiled_rockets, 2);
+        assert!(planet.generate(BasicResourceType::Oxygen).is_some());
+    }
+
+    #[test]
+    fn test_overflowing_sunray_without_rockets_is_discarded() {
+        let planet = TestPlanet::spawn(0, PlanetType::D, vec![BasicResourceType::Oxygen], vec![]);
+        for _ in 0..6 {
+            planet.sunray();
+        }
+
+        match planet.send_orch(OrchestratorToPlanet::InternalStateRequest) {
+            PlanetToOrchestrator::InternalStateResponse { planet_state, .. } => {
+                assert_eq!(planet_state.charged_cells_count, 5);
+                assert!(!planet_state.has_rocket);
+            }
+            _other => panic!("Wrong response received"),
+        }
+    }
+
+    #[test]
+    fn test_failed_rocket_build_keeps_charge() {
+        // Type D planets cannot have rockets, so every build fails.
+        let planet = TestPlanet::spawn(0, PlanetType::D, vec![BasicResourceType::Oxygen], vec![]);
+        planet.sunray();
+        planet.sunray();
+        assert!(planet.asteroid().is_none());
+
+        match planet.send_orch(OrchestratorToPlanet::InternalStateRequest) {
+            PlanetToOrchestrator::InternalStateResponse { planet_state, .. } => {
+                assert_eq!(planet_state.charged_cells_count, 2);
+                assert!(!planet_state.has_rocket);
+            }
+            _other => panic!("Wrong response received"),
+        }
+    }
+
+    #[test]
+    fn test_failed_rocket_builds_are_counted() {
+        let ai = AI::new();
+        let stats = ai.stats();
+        // Type D planets cannot have rockets, so every build fails.
+        let planet = TestPlanet::spawn_with_ai(
+            0,
+            PlanetType::D,
+            ai,
+            vec![BasicResourceType::Oxygen],
+            vec![],
+        );
+        planet.sunray();
+        planet.sunray();
+        assert!(planet.asteroid().is_none());
+
+        let stats = *stats.lock().unwrap();
+        // One failure per sunray trying to fill the reserve; the asteroid
+        // tries no build, as type D planets are known to hold no rockets.
+        assert_eq!(stats.rocket_build_failures, 2);
+        assert_eq!(stats.rockets_built, 0);
+    }
+
+    #[test]
+    fn test_generation_quota_throttles_explorer() {
+        let mut ai = AI::new();
+        ai.config.generation_quota = Some(2);
+        ai.config.auto_build = false;
+        let planet = TestPlanet::spawn_with_ai(
+            0,
+            PlanetType::A,
+            ai,
+            vec![BasicResourceType::Oxygen],
+            vec![],
+        );
+        for _ in 0..4 {
+            planet.sunray();
+        }
+
+        assert!(planet.generate(BasicResourceType::Oxygen).is_some());
+        assert!(planet.generate(BasicResourceType::Oxygen).is_some());
+        planet
+            .expl_tx
+            .send(ExplorerToPlanet::GenerateResourceRequest {
+                explorer_id: 0,
+                resource: BasicResourceType::Oxygen,
+            })
+            .expect("Failed to send message");
+        assert!(
+            planet
+                .expl_rx
+                .recv_timeout(Duration::from_millis(100))
+                .is_err(),
+            "Third request must be throttled"
+        );
+
+        // The next sunray resets the quota.
+        planet.sunray();
+        assert!(planet.generate(BasicResourceType::Oxygen).is_some());
+    }
+
+    #[test]
+    fn test_scarce_cells_alternate_between_explorers() {
+        let mut ai = AI::new();
+        ai.config.auto_build = false;
+        let planet = TestPlanet::spawn_with_ai(
+            0,
+            PlanetType::A,
+            ai,
+            vec![BasicResourceType::Oxygen],
+            vec![],
+        );
+        let (to_second_tx, to_second_rx) = crossbeam_channel::unbounded();
+        planet.send_orch(OrchestratorToPlanet::IncomingExplorerRequest {
+            explorer_id: 1,
+            new_sender: to_second_tx,
+        });
+        let generate = |explorer_id, rx: &Receiver<PlanetToExplorer>| {
+            planet
+                .expl_tx
+                .send(ExplorerToPlanet::GenerateResourceRequest {
+                    explorer_id,
+                    resource: BasicResourceType::Oxygen,
+                })
+                .expect("Failed to send message");
+            rx.recv_timeout(Duration::from_millis(100)).is_ok()
+        };
+
+        // Both explorers ask for the single cell charged by every sunray.
+        let mut served = Vec::new();
+        for _ in 0..4 {
+            planet.sunray();
+            if generate(0, &planet.expl_rx) {
+                served.push(0);
+            }
+            if generate(1, &to_second_rx) {
+                served.push(1);
+            }
+        }
+        assert_eq!(served, vec![0, 1, 0, 1]);
+    }
+
+    #[test]
+    fn test_round_robin_rotates_cells() {
+        let mut ai = AI::with_cell_selection(CellSelection::RoundRobin);
+        ai.config.auto_build = false;
+        let planet = TestPlanet::spawn_with_ai(
+            0,
+            PlanetType::A,
+            ai,
+            vec![BasicResourceType::Oxygen],
+            vec![],
+        );
+
+        for expected in 0..3 {
+            planet.sunray();
+            match planet.send_orch(OrchestratorToPlanet::InternalStateRequest) {
+                PlanetToOrchestrator::InternalStateResponse { planet_state, .. } => {
+                    assert_eq!(planet_state.charged_cells_count, 1);
+                    assert!(
+                        planet_state.energy_cells[expected],
+                        "Cell {expected} must be charged"
+                    );
+                }
+                _other => panic!("Wrong response received"),
+            }
+            assert!(planet.generate(BasicResourceType::Oxygen).is_some());
+        }
+    }
+
+    #[test]
+    fn test_internal_state_reports_cells_and_rocket() {
+        let planet = TestPlanet::spawn(0, PlanetType::A, vec![BasicResourceType::Oxygen], vec![]);
+
+        // The first sunray is spent building a rocket, the others charge cells.
+        for _ in 0..3 {
+            planet.sunray();
+        }
+
+        match planet.send_orch(OrchestratorToPlanet::InternalStateRequest) {
+            PlanetToOrchestrator::InternalStateResponse {
+                planet_id: 0,
+                planet_state,
+            } => {
+                assert_eq!(planet_state.charged_cells_count, 2);
+                assert_eq!(planet_state.energy_cells.iter().filter(|&&c| c).count(), 2);
+                assert!(planet_state.has_rocket, "Planet must have rocket");
+            }
+            _other => panic!("Wrong response received"),
+        }
+    }
+
+    #[test]
+    fn test_generate_each_supported_resource() {
+        let rules = vec![BasicResourceType::Carbon, BasicResourceType::Silicon];
+        let planet = TestPlanet::spawn(0, PlanetType::D, rules.clone(), vec![]);
+
+        match planet.send_expl(ExplorerToPlanet::SupportedResourceRequest { explorer_id: 0 }) {
+            PlanetToExplorer::SupportedResourceResponse { resource_list } => {
+                assert_eq!(resource_list, rules.iter().copied().collect());
+            }
+            _other => panic!("Wrong response received"),
+        }
+
+        for resource in rules {
+            planet.sunray();
+            let generated = planet
+                .generate(resource)
+                .expect("Resource must be generated");
+            assert_eq!(generated.get_type(), resource);
+        }
+
+        planet.sunray();
+        assert!(
+            planet.generate(BasicResourceType::Oxygen).is_none(),
+            "Unsupported resource must be rejected"
+        );
+    }
+
+    /// Generates the inputs of a water combination on a throwaway planet.
+    fn water_inputs() -> ComplexResourceRequest {
+        let supplier = TestPlanet::spawn(
+            0,
+            PlanetType::D,
+            vec![BasicResourceType::Oxygen, BasicResourceType::Hydrogen],
+            vec![],
+        );
+        supplier.sunray();
+        supplier.sunray();
+        let oxygen = supplier
+            .generate(BasicResourceType::Oxygen)
+            .expect("Oxygen must be generated")
+            .to_oxygen()
+            .unwrap();
+        let hydrogen = supplier
+            .generate(BasicResourceType::Hydrogen)
+            .expect("Hydrogen must be generated")
+            .to_hydrogen()
+            .unwrap();
+        ComplexResourceRequest::Water(hydrogen, oxygen)
+    }
+
+    #[test]
+    fn test_combine_resource_request() {
+        let water_planet = TestPlanet::spawn(
+            1,
+            PlanetType::C,
+            vec![BasicResourceType::Oxygen],
+            vec![ComplexResourceType::Water],
+        );
+
+        // The first sunray is spent building a rocket.
+        water_planet.sunray();
+        water_planet.sunray();
+
+        match water_planet.send_expl(ExplorerToPlanet::CombineResourceRequest {
+            explorer_id: 0,
+            msg: water_inputs(),
+        }) {
+            PlanetToExplorer::CombineResourceResponse {
+                complex_response: Ok(ComplexResource::Water(_)),
+            } => {}
+            _other => panic!("Wrong response received"),
+        }
+    }
+
+    #[test]
+    fn test_combinations_are_counted_by_recipe() {
+        let ai = AI::new();
+        let stats = ai.stats();
+        let water_planet = TestPlanet::spawn_with_ai(
+            1,
+            PlanetType::C,
+            ai,
+            vec![BasicResourceType::Oxygen],
+            vec![ComplexResourceType::Water],
+        );
+        water_planet.sunray();
+        water_planet.sunray();
+        water_planet.send_expl(ExplorerToPlanet::CombineResourceRequest {
+            explorer_id: 0,
+            msg: water_inputs(),
+        });
+        // Without a charged cell, nothing is produced.
+        water_planet.send_expl(ExplorerToPlanet::CombineResourceRequest {
+            explorer_id: 0,
+            msg: water_inputs(),
+        });
+
+        let stats = *stats.lock().unwrap();
+        assert_eq!(stats.complex_resources_produced, 1);
+        assert_eq!(
+            stats.complex_yields,
+            ComplexYields {
+                water: 1,
+                ..ComplexYields::default()
+            }
+        );
+        assert_eq!(stats.complex_yields.get(ComplexResourceType::Water), 1);
+        assert_eq!(stats.complex_yields.get(ComplexResourceType::Diamond), 0);
+    }
+
+    #[test]
+    fn test_combine_unsupported_recipe_returns_inputs() {
+        let planet = TestPlanet::spawn(1, PlanetType::C, vec![BasicResourceType::Oxygen], vec![]);
+        planet.sunray();
+        planet.sunray();
+
+        match planet.send_expl(ExplorerToPlanet::CombineResourceRequest {
+            explorer_id: 0,
+            msg: water_inputs(),
+        }) {
+            PlanetToExplorer::CombineResourceResponse {
+                complex_response: Err((reason, left, right)),
+            } => {
+                assert_eq!(reason, AI::UNSUPPORTED_COMBINATION);
+                assert!(matches!(
+                    left,
+                    GenericResource::BasicResources(BasicResource::Hydrogen(_))
+                ));
+                assert!(matches!(
+                    right,
+                    GenericResource::BasicResources(BasicResource::Oxygen(_))
+                ));
+            }
+            _other => panic!("Wrong response received"),
+        }
+    }
+
+    #[test]
+    fn test_combine_without_energy_returns_inputs() {
+        let planet = TestPlanet::spawn(
+            1,
+            PlanetType::C,
+            vec![BasicResourceType::Oxygen],
+            vec![ComplexResourceType::Water],
+        );
+
+        match planet.send_expl(ExplorerToPlanet::CombineResourceRequest {
+            explorer_id: 0,
+            msg: water_inputs(),
+        }) {
+            PlanetToExplorer::CombineResourceResponse {
+                complex_response: Err((reason, left, right)),
+            } => {
+                assert_eq!(reason, AI::NO_CHARGED_CELLS);
+                assert!(matches!(
+                    left,
+                    GenericResource::BasicResources(BasicResource::Hydrogen(_))
+                ));
+                assert!(matches!(
+                    right,
+                    GenericResource::BasicResources(BasicResource::Oxygen(_))
+                ));
+            }
+            _other => panic!("Wrong response received"),
+        }
+    }
+
+    #[test]
+    fn test_actionable_combinations_follow_charged_cells() {
+        let ai = AI::with_config(AiConfig {
+            auto_build: false,
+            actionable_combinations: true,
+            ..AiConfig::default()
+        });
+        let planet = TestPlanet::spawn_with_ai(
+            1,
+            PlanetType::C,
+            ai,
+            vec![BasicResourceType::Oxygen],
+            vec![ComplexResourceType::Water, ComplexResourceType::Diamond],
+        );
+        let combinations = || match planet
+            .send_expl(ExplorerToPlanet::SupportedCombinationRequest { explorer_id: 0 })
+        {
+            PlanetToExplorer::SupportedCombinationResponse { combination_list } => combination_list,
+            _other => panic!("Wrong response received"),
+        };
+
+        assert!(combinations().is_empty(), "No cell can power a recipe");
+        planet.sunray();
+        assert_eq!(
+            combinations(),
+            HashSet::from([ComplexResourceType::Water, ComplexResourceType::Diamond])
+        );
+    }
+
+    #[test]
+    fn test_combine_succeeds_once_a_sunray_charges_a_cell() {
+        let ai = AI::with_config(AiConfig {
+            auto_build: false,
+            ..AiConfig::default()
+        });
+        let planet = TestPlanet::spawn_with_ai(
+            1,
+            PlanetType::C,
+            ai,
+            vec![BasicResourceType::Oxygen],
+            vec![ComplexResourceType::Water],
+        );
+        let combine = |msg| match planet.send_expl(ExplorerToPlanet::CombineResourceRequest {
+            explorer_id: 0,
+            msg,
+        }) {
+            PlanetToExplorer::CombineResourceResponse { complex_response } => complex_response,
+            _other => panic!("Wrong response received"),
+        };
+        let charged_cells = || match planet.send_orch(OrchestratorToPlanet::InternalStateRequest) {
+            PlanetToOrchestrator::InternalStateResponse { planet_state, .. } => {
+                planet_state.charged_cells_count
+            }
+            _other => panic!("Wrong response received"),
+        };
+
+        assert!(matches!(
+            combine(water_inputs()),
+            Err((reason, _, _)) if reason == AI::NO_CHARGED_CELLS
+        ));
+
+        planet.sunray();
+        assert_eq!(charged_cells(), 1);
+        assert!(matches!(
+            combine(water_inputs()),
+            Ok(ComplexResource::Water(_))
+        ));
+        assert_eq!(charged_cells(), 0, "The combination must use up the cell");
+    }
+
+    #[test]
+    fn test_start_sets_running() {
+        let planet = idle_planet();
+        let mut ai = AI::new();
+        ai.on_start(planet.state(), planet.generator(), planet.combinator());
+        assert_eq!(
+            ai.mode,
+            PlanetMode::Running,
+            "AI should be running after start()"
+        );
+    }
+
+    #[test]
+    fn test_stop_sets_stopped() {
+        let planet = idle_planet();
+        let (state, generator, comb) = (planet.state(), planet.generator(), planet.combinator());
+        let mut ai = AI::new();
+
+        ai.on_start(state, generator, comb);
+        assert_eq!(ai.mode, PlanetMode::Running);
+
+        ai.on_stop(state, generator, comb);
+        assert_eq!(
+            ai.mode,
+            PlanetMode::Stopped,
+            "AI should be stopped after stop()"
+        );
+    }
+
+    #[test]
+    fn test_handle_sunray_charges_and_builds() {
+        let has_rocket = with_planet_state(|state, generator, comb| {
+            let mut ai = AI::new();
+            ai.on_start(state, generator, comb);
+            ai.handle_sunray(state, generator, comb, Sunray::default());
+            state.has_rocket()
+        });
+        assert!(has_rocket, "First sunray must build the reserve rocket");
+    }
+
+    #[test]
+    fn test_handle_explorer_msg_returns_none_when_stopped() {
+        let answered = with_planet_state(|state, generator, comb| {
+            let mut ai = AI::new();
+            ai.attach_explorer(state.id(), 0);
+            let msg = ExplorerToPlanet::SupportedResourceRequest { explorer_id: 0 };
+            ai.handle_explorer_msg(state, generator, comb, msg)
+                .is_some()
+        });
+        assert!(!answered, "Expected no response from a stopped AI");
+    }
+
+    #[test]
+    fn test_recipe_sets_are_enumerated_once() {
+        let (enumerations, resources, combinations) =
+            with_planet_state(|state, generator, comb| {
+                let mut ai = AI::new();
+                ai.on_start(state, generator, comb);
+                ai.attach_explorer(state.id(), 0);
+                let mut resources = Vec::new();
+                let mut combinations = Vec::new();
+                for _ in 0..10 {
+                    let msg = ExplorerToPlanet::SupportedResourceRequest { explorer_id: 0 };
+                    resources.push(ai.handle_explorer_msg(state, generator, comb, msg));
+                    let msg = ExplorerToPlanet::SupportedCombinationRequest { explorer_id: 0 };
+                    combinations.push(ai.handle_explorer_msg(state, generator, comb, msg));
+                }
+                (ai.recipes.enumerations, resources, combinations)
+            });
+        assert_eq!(enumerations, 2, "Each recipe set must be enumerated once");
+        for response in resources {
+            match response {
+                Some(PlanetToExplorer::SupportedResourceResponse { resource_list }) => {
+                    assert_eq!(resource_list, HashSet::from([BasicResourceType::Oxygen]));
+                }
+                _ => panic!("Expected SupportedResourceResponse"),
+            }
+        }
+        for response in combinations {
+            match response {
+                Some(PlanetToExplorer::SupportedCombinationResponse { combination_list }) => {
+                    assert!(combination_list.is_empty());
+                }
+                _ => panic!("Expected SupportedCombinationResponse"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_cell_count_saturates() {
+        assert_eq!(AI::cell_count(0, 5), 5);
+        assert_eq!(AI::cell_count(0, u32::MAX as usize), u32::MAX);
+        if let Some(oversized) = (u32::MAX as usize).checked_add(1) {
+            assert_eq!(AI::cell_count(0, oversized), u32::MAX);
+        }
+        assert_eq!(AI::cell_count(0, usize::MAX), u32::MAX);
+    }
+
+    #[test]
+    fn test_maintenance_answers_queries_only() {
+        let ai = AI::new();
+        let handle = ai.handle();
+        handle.maintenance.store(true, Ordering::Release);
+        let planet = TestPlanet::spawn_with_ai(
+            0,
+            PlanetType::A,
+            ai,
+            vec![BasicResourceType::Oxygen],
+            vec![],
+        );
+        planet.sunray();
+        planet.sunray();
+
+        match planet.send_expl(ExplorerToPlanet::SupportedResourceRequest { explorer_id: 0 }) {
+            PlanetToExplorer::SupportedResourceResponse { resource_list } => {
+                assert!(resource_list.contains(&BasicResourceType::Oxygen));
+            }
+            _other => panic!("Wrong response received"),
+        }
+        planet
+            .expl_tx
+            .send(ExplorerToPlanet::GenerateResourceRequest {
+                explorer_id: 0,
+                resource: BasicResourceType::Oxygen,
+            })
+            .expect("Failed to send message");
+        assert!(
+            planet
+                .expl_rx
+                .recv_timeout(Duration::from_millis(100))
+                .is_err(),
+            "Generation must be refused in maintenance"
+        );
+        match planet.send_orch(OrchestratorToPlanet::InternalStateRequest) {
+            PlanetToOrchestrator::InternalStateResponse { planet_state, .. } => {
+                assert_eq!(planet_state.charged_cells_count, 0);
+                assert!(!planet_state.has_rocket);
+            }
+            _other => panic!("Wrong response received"),
+        }
+
+        // Back to running, the buffered sunrays build the reserve rocket and
+        // charge a cell.
+        handle.maintenance.store(false, Ordering::Release);
+        assert!(planet.generate(BasicResourceType::Oxygen).is_some());
+        assert!(planet.asteroid().is_some());
+    }
+
+    #[test]
+    fn test_housekeeping_refills_reserve() {
+        let rockets = with_planet_state(|state, generator, comb| {
+            let mut ai = AI::with_reserve(3, true);
+            ai.on_start(state, generator, comb);
+            for _ in 0..4 {
+                state.charge_cell(Sunray::default());
+            }
+            // Not due yet: nothing is built.
+            ai.handle_internal_state_req(state, generator, comb);
+            assert_eq!(ai.rocket_count(state), 0);
+            ai.handle().housekeeping.store(true, Ordering::Release);
+            ai.handle_internal_state_req(state, generator, comb);
+            let charged = state.cells_iter().filter(|c| c.is_charged()).count();
+            (ai.rocket_count(state), charged)
+        });
+        assert_eq!(rockets, (3, 1), "Three cells must become rockets");
+    }
+
+    #[test]
+    fn test_handle_asteroid_returns_none_without_charge() {
+        let launched = with_planet_state(|state, generator, comb| {
+            let mut ai = AI::new();
+            ai.on_start(state, generator, comb);
+            ai.handle_asteroid(state, generator, comb).is_some()
+        });
+        assert!(!launched, "Expected no rocket launched on asteroid event");
+    }
+
+    #[test]
+    fn test_dry_run_leaves_cells_untouched() {
+        let (response, charged) = with_planet_state(|state, generator, comb| {
+            let mut ai = AI::with_config(AiConfig {
+                dry_run: true,
+                ..AiConfig::default()
+            });
+            ai.on_start(state, generator, comb);
+            ai.on_explorer_arrival(state, generator, comb, 1);
+            state.charge_cell(Sunray::default());
+            let msg = ExplorerToPlanet::GenerateResourceRequest {
+                explorer_id: 1,
+                resource: BasicResourceType::Oxygen,
+            };
+            let response = ai.handle_explorer_msg(state, generator, comb, msg);
+            ai.handle_sunray(state, generator, comb, Sunray::default());
+            let charged = state.cells_iter().filter(|c| c.is_charged()).count();
+            (response, charged)
+        });
+        assert!(
+            matches!(
+                response,
+                Some(PlanetToExplorer::GenerateResourceResponse { resource: None })
+            ),
+            "A dry run must answer without handing out resources"
+        );
+        assert_eq!(charged, 1, "A dry run must not change the charged cells");
+    }
+
+    #[test]
+    fn test_handle_asteroid_launches_after_charge() {
+        let launched = with_planet_state(|state, generator, comb| {
+            let mut ai = AI::with_reserve(1, false);
+            ai.on_start(state, generator, comb);
+            state.charge_cell(Sunray::default());
+            ai.handle_asteroid(state, generator, comb).is_some()
+        });
+        assert!(launched, "Expected a rocket launched on asteroid event");
+    }
+
+    fn lookup(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|&(name, value)| (name.to_owned(), value.to_owned()))
+            .collect();
+        move |name| vars.get(name).cloned()
+    }
+
+    #[test]
+    fn test_config_from_fully_specified_env() {
+        let config = AiConfig::from_lookup(lookup(&[
+            ("TRIP_ROCKET_RESERVE", "2"),
+            ("TRIP_AUTO_BUILD", "false"),
+            ("TRIP_MAX_ROCKETS", "5"),
+            ("TRIP_BUILD_THRESHOLD", "3"),
+            ("TRIP_SUNRAY_BUFFER_CAPACITY", "16"),
+            ("TRIP_CELL_SELECTION", "round_robin"),
+            ("TRIP_RATE_LIMIT_BURST", "4"),
+            ("TRIP_RATE_LIMIT_PER_SECOND", "2"),
+            ("TRIP_GENERATION_QUOTA", "1"),
+            ("TRIP_MAX_HEALTH", "300"),
+            ("TRIP_ASTEROID_DAMAGE", " 50 "),
+            ("TRIP_DRY_RUN", "true"),
+            ("TRIP_ACTIONABLE_COMBINATIONS", "true"),
+            ("TRIP_START_GRACE_MS", "250"),
+        ]));
+
+        assert_eq!(
+            config,
+            AiConfig {
+                rocket_reserve: 2,
+                auto_build: false,
+                max_rockets: 5,
+                build_threshold: 3,
+                sunray_buffer_capacity: 16,
+                cell_selection: CellSelection::RoundRobin,
+                explorer_rate_limit: Some(RateLimit {
+                    burst: 4,
+                    per_second: 2,
+                }),
+                generation_quota: Some(1),
+                max_health: 300,
+                asteroid_damage: 50,
+                dry_run: true,
+                actionable_combinations: true,
+                start_grace: Duration::from_millis(250),
+            }
+        );
+    }
+
+    #[test]
+    fn test_default_config_leaves_out_timing_policies() {
+        let config = AiConfig::default();
+        assert_eq!(config.cell_selection, CellSelection::FirstAvailable);
+        assert_eq!(config.explorer_rate_limit, None);
+        assert_eq!(config.start_grace, Duration::ZERO);
+    }
+
+    #[test]
+    fn test_config_from_env_falls_back_to_defaults() {
+        assert_eq!(AiConfig::from_lookup(lookup(&[])), AiConfig::default());
+
+        let config = AiConfig::from_lookup(lookup(&[
+            ("TRIP_ROCKET_RESERVE", "3"),
+            ("TRIP_MAX_ROCKETS", "-1"),
+            ("TRIP_AUTO_BUILD", "yes"),
+            ("TRIP_CELL_SELECTION", "random"),
+            ("TRIP_RATE_LIMIT_BURST", "4"),
+            ("TRIP_START_GRACE_MS", "soon"),
+        ]));
+        assert_eq!(
+            config,
+            AiConfig {
+                rocket_reserve: 3,
+                ..AiConfig::default()
+            }
+        );
+    }
 }
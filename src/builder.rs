@@ -0,0 +1,488 @@
+//! Step-by-step construction of a [`Trip`] planet.
+
+use common_game::components::planet::{Planet, PlanetAI, PlanetType};
+use common_game::components::resource::{BasicResourceType, ComplexResourceType};
+use common_game::components::rocket::Rocket;
+use common_game::protocols::orchestrator_planet::{OrchestratorToPlanet, PlanetToOrchestrator};
+use common_game::protocols::planet_explorer::ExplorerToPlanet;
+use crossbeam_channel::{Receiver, Sender, TryRecvError};
+use log::{debug, error, info};
+use std::cell::{Cell, RefCell};
+use std::fmt::Debug;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::ai::{AI, AiConfig, RocketHook};
+use crate::guard::PanicGuard;
+use crate::relay::{Link, Relay};
+use crate::{AuditEvent, DefenseEvent, Trip, TripError, default_rules_for};
+
+/// Channels connecting a planet to the orchestrator and to the explorers.
+type Channels = (
+    Receiver<OrchestratorToPlanet>,
+    Sender<PlanetToOrchestrator>,
+    Receiver<ExplorerToPlanet>,
+);
+
+/// Builder for a [`Trip`] planet with every option exposed.
+///
+/// Only the channels are mandatory; everything else falls back to the same
+/// defaults as [`trip`](crate::trip): a [`PlanetType::A`] generating the
+/// resource given by [`default_rules_for`], with no combination rules and our own AI.
+///
+/// # Example
+///
+/// ```
+/// use common_game::components::planet::PlanetType;
+/// use common_game::components::resource::BasicResourceType;
+/// use trip::TripBuilder;
+///
+/// let (_orch_tx, orch_rx) = crossbeam_channel::unbounded();
+/// let (planet_tx, _planet_rx) = crossbeam_channel::unbounded();
+/// let (_expl_tx, expl_rx) = crossbeam_channel::unbounded();
+///
+/// let trip = TripBuilder::new(7)
+///     .planet_type(PlanetType::D)
+///     .generation_rules(vec![BasicResourceType::Carbon, BasicResourceType::Silicon])
+///     .channels(orch_rx, planet_tx, expl_rx)
+///     .build();
+/// assert!(trip.is_ok());
+/// ```
+pub struct TripBuilder {
+    id: u32,
+    planet_type: PlanetType,
+    gen_rules: Option<Vec<BasicResourceType>>,
+    comb_rules: Vec<ComplexResourceType>,
+    /// Custom AI replacing ours, if any.
+    ai: Option<Box<dyn PlanetAI>>,
+    /// Policies of our AI, which is only built once the planet type is
+    /// known.
+    ai_config: AiConfig,
+    channels: Option<Channels>,
+    defense_events: Option<Sender<DefenseEvent>>,
+    on_rocket_launched: Option<RocketHook>,
+    audit_events: Option<Sender<AuditEvent>>,
+    housekeeping: Option<Duration>,
+    idle_warning: Option<Duration>,
+    stop_drain: usize,
+    explorer_queue_cap: Option<usize>,
+    ack_stopped_sunrays: bool,
+    outbox_capacity: Option<usize>,
+}
+
+impl TripBuilder {
+    /// Starts building the planet with the given `id`.
+    #[must_use]
+    pub fn new(id: u32) -> Self {
+        Self {
+            id,
+            planet_type: PlanetType::A,
+            gen_rules: None,
+            comb_rules: vec![],
+            ai: None,
+            ai_config: AiConfig::default(),
+            channels: None,
+            defense_events: None,
+            on_rocket_launched: None,
+            audit_events: None,
+            housekeeping: None,
+            idle_warning: None,
+            stop_drain: 0,
+            explorer_queue_cap: None,
+            ack_stopped_sunrays: false,
+            outbox_capacity: None,
+        }
+    }
+
+    /// Sets the [`PlanetType`], [`PlanetType::A`] by default.
+    #[must_use]
+    pub fn planet_type(mut self, planet_type: PlanetType) -> Self {
+        self.planet_type = planet_type;
+        self
+    }
+
+    /// Sets the basic resources the planet can generate, by default
+    /// the ones given by [`default_rules_for`] the planet type.
+    #[must_use]
+    pub fn generation_rules(mut self, gen_rules: Vec<BasicResourceType>) -> Self {
+        self.gen_rules = Some(gen_rules);
+        self
+    }
+
+    /// Sets the complex resources the planet can combine, none by default.
+    #[must_use]
+    pub fn combination_rules(mut self, comb_rules: Vec<ComplexResourceType>) -> Self {
+        self.comb_rules = comb_rules;
+        self
+    }
+
+    /// Replaces our AI with a custom one.
+    ///
+    /// [`Trip::metrics`] cannot see inside a custom AI and reports zeros.
+    #[must_use]
+    pub fn ai(mut self, ai: Box<dyn PlanetAI>) -> Self {
+        self.ai = Some(ai);
+        self
+    }
+
+    /// Uses our AI with the policies in `config` instead of the defaults.
+    ///
+    /// This and [`ai`](TripBuilder::ai) replace each other: the last call
+    /// wins.
+    #[must_use]
+    pub fn ai_config(mut self, config: AiConfig) -> Self {
+        self.ai = None;
+        self.ai_config = config;
+        self
+    }
+
+    /// Sets the channels used to talk with the orchestrator and the explorers.
+    #[must_use]
+    pub fn channels(
+        mut self,
+        orch_to_planet: Receiver<OrchestratorToPlanet>,
+        planet_to_orch: Sender<PlanetToOrchestrator>,
+        expl_to_planet: Receiver<ExplorerToPlanet>,
+    ) -> Self {
+        self.channels = Some((orch_to_planet, planet_to_orch, expl_to_planet));
+        self
+    }
+
+    /// Reports the outcome of every asteroid hit as a [`DefenseEvent`] on
+    /// `events`, e.g. to count defenses against losses.
+    ///
+    /// The subscriber is optional: if its receiver is dropped, events are
+    /// discarded and the planet keeps running.
+    #[must_use]
+    pub fn defense_events(mut self, events: Sender<DefenseEvent>) -> Self {
+        self.defense_events = Some(events);
+        self
+    }
+
+    /// Calls `hook` for every rocket our AI launches against an asteroid,
+    /// e.g. to feed an event bus without parsing the `AsteroidAck`s.
+    ///
+    /// The hook runs on the planet's thread, in the middle of handling the
+    /// asteroid, so it should return quickly. A panic in it is contained
+    /// like any other panic of the AI. A custom AI set with
+    /// [`ai`](TripBuilder::ai) never calls it.
+    #[must_use]
+    pub fn on_rocket_launched(mut self, hook: Box<dyn Fn(&Rocket) + Send>) -> Self {
+        self.on_rocket_launched = Some(hook);
+        self
+    }
+
+    /// Reports every significant step of our AI as an [`AuditEvent`] on
+    /// `events`, so a collector thread can persist a chronological audit.
+    ///
+    /// Like [`defense_events`](TripBuilder::defense_events), the collector is
+    /// optional: if its receiver is dropped, events are discarded. A custom
+    /// AI set with [`ai`](TripBuilder::ai) reports nothing.
+    #[must_use]
+    pub fn audit_events(mut self, events: Sender<AuditEvent>) -> Self {
+        self.audit_events = Some(events);
+        self
+    }
+
+    /// Lets the planet perform housekeeping every `interval`, even when no
+    /// message arrives; disabled by default.
+    ///
+    /// While running, our AI uses these periodic rounds to refill its rocket
+    /// reserve from charged cells. The planet is woken up for each round by
+    /// an `InternalStateRequest` whose reply the orchestrator never sees. A
+    /// custom [`ai`](TripBuilder::ai) gets no housekeeping.
+    #[must_use]
+    pub fn housekeeping(mut self, interval: Duration) -> Self {
+        self.housekeeping = Some(interval);
+        self
+    }
+
+    /// Logs a warning naming the planet when neither the orchestrator nor
+    /// the explorers sent anything for `interval`; disabled by default.
+    ///
+    /// Meant to spot a planet starved by an orchestrator that died without
+    /// dropping its channels. The warning is logged once per idle stretch,
+    /// and the next message arms it again.
+    #[must_use]
+    pub fn idle_warning(mut self, interval: Duration) -> Self {
+        self.idle_warning = Some(interval);
+        self
+    }
+
+    /// Answers up to `max_requests` explorer requests still pending when the
+    /// orchestrator stops the planet; disabled by default.
+    ///
+    /// Without it, requests queued behind a `StopPlanetAI` are answered with
+    /// `Stopped`. With it, the stop is held back, for a short bounded time,
+    /// until the planet has handled them.
+    #[must_use]
+    pub fn drain_on_stop(mut self, max_requests: usize) -> Self {
+        self.stop_drain = max_requests;
+        self
+    }
+
+    /// Rejects requests of an explorer that already has `cap` requests
+    /// waiting for the planet; unbounded by default.
+    ///
+    /// Keeps a single chatty explorer from growing the planet's queue
+    /// without bound while the planet is busy. Rejected requests get no
+    /// answer, are logged with a warning and counted in
+    /// [`TripMetrics::explorer_requests_rejected`](crate::TripMetrics::explorer_requests_rejected).
+    #[must_use]
+    pub fn max_queued_per_explorer(mut self, cap: usize) -> Self {
+        self.explorer_queue_cap = Some(cap);
+        self
+    }
+
+    /// Answers sunrays reaching a stopped planet with a `SunrayAck` instead
+    /// of `Stopped`; disabled by default.
+    ///
+    /// The sunray is still counted in
+    /// [`DroppedWhileStopped::sunrays`](crate::DroppedWhileStopped::sunrays)
+    /// and only charges a cell if it fits in
+    /// [`AiConfig::sunray_buffer_capacity`](crate::AiConfig::sunray_buffer_capacity):
+    /// the ack only confirms the planet is alive. A dead planet answers
+    /// nothing at all, while `Stopped` replies to the other messages still
+    /// tell a stopped planet apart.
+    #[must_use]
+    pub fn ack_sunrays_while_stopped(mut self, on: bool) -> Self {
+        self.ack_stopped_sunrays = on;
+        self
+    }
+
+    /// Queues up to `capacity` replies to the orchestrator while its channel
+    /// is full, instead of holding up the relay until it makes room;
+    /// disabled by default.
+    ///
+    /// The queue is flushed as soon as the orchestrator reads again, so a
+    /// slow orchestrator no longer delays the explorers. Replies that do not
+    /// fit, or are still queued when the planet's loop returns, are dropped
+    /// with a warning and counted in
+    /// [`RunReport::replies_dropped`](crate::RunReport::replies_dropped).
+    #[must_use]
+    pub fn orchestrator_outbox(mut self, capacity: usize) -> Self {
+        self.outbox_capacity = Some(capacity);
+        self
+    }
+
+    /// Builds the planet.
+    ///
+    /// # Errors
+    ///
+    /// - [`TripError::MissingChannels`] if [`TripBuilder::channels`] was never
+    ///   called.
+    /// - [`TripError::OrchestratorChannelClosed`] or
+    ///   [`TripError::ExplorerChannelClosed`] if a channel is already closed.
+    /// - [`TripError::PlanetInit`] if [`Planet::new`] rejects the
+    ///   configuration for the chosen planet type.
+    pub fn build(self) -> Result<Trip, TripError> {
+        let id = self.id;
+        let planet_type = self.planet_type;
+        let Some((orch_to_planet, planet_to_orch, expl_to_planet)) = self.channels else {
+            error!("planet {id} built without channels");
+            return Err(TripError::MissingChannels);
+        };
+
+        let (held_orchestrator, held_explorer) =
+            check_channels(id, &orch_to_planet, &expl_to_planet)?;
+        let (to_planet, relayed_orch) = crossbeam_channel::unbounded();
+        let (to_planet_expl, relayed_expl) = crossbeam_channel::unbounded();
+        let (from_planet_tx, from_planet) = crossbeam_channel::unbounded();
+        let (shutdown_tx, shutdown_rx) = crossbeam_channel::bounded(1);
+        let (pause_tx, pause_rx) = crossbeam_channel::bounded(1);
+        let panics = Arc::default();
+        // These are carried out by the relay on behalf of our AI only.
+        let (start_grace, sunray_buffer_capacity, housekeeping) = if self.ai.is_some() {
+            (Duration::ZERO, 0, None)
+        } else {
+            let config = &self.ai_config;
+            (
+                config.start_grace,
+                config.sunray_buffer_capacity,
+                self.housekeeping,
+            )
+        };
+        let (ai, ai_handle) = if let Some(ai) = self.ai {
+            (ai, None)
+        } else {
+            let ai = AI::with_config(self.ai_config)
+                .for_planet_type(planet_type)
+                .on_rocket_launched(self.on_rocket_launched)
+                .audit_events(self.audit_events);
+            let handle = ai.handle();
+            (Box::new(ai) as Box<dyn PlanetAI>, Some(handle))
+        };
+        let planet = Planet::new(
+            id,
+            planet_type,
+            Box::new(PanicGuard::new(ai, Arc::clone(&panics))),
+            self.gen_rules
+                .unwrap_or_else(|| default_rules_for(planet_type)),
+            self.comb_rules,
+            (relayed_orch, from_planet_tx),
+            relayed_expl,
+        )
+        .map_err(|source| TripError::PlanetInit { id, source })?;
+
+        info!("planet_id={id} type={planet_type:?} initialized");
+        Ok(Trip::new(
+            planet,
+            Link {
+                from_orchestrator: orch_to_planet,
+                to_orchestrator: planet_to_orch,
+                from_explorers: expl_to_planet,
+                from_planet,
+                defense_events: self.defense_events,
+                planet_id: id,
+                idle_warning: self.idle_warning,
+                housekeeping,
+                housekeeping_due: ai_handle
+                    .as_ref()
+                    .map_or_else(Arc::default, |ai| Arc::clone(&ai.housekeeping)),
+                stop_drain: self.stop_drain,
+                explorer_queue_cap: self.explorer_queue_cap,
+                rejected: Arc::default(),
+                ack_stopped_sunrays: self.ack_stopped_sunrays,
+                sunray_buffer_capacity,
+                buffered_sunrays: Cell::new(0),
+                start_grace,
+                running: Arc::default(),
+                shutdown_rx,
+                shutdown_tx,
+                paused: Arc::default(),
+                pause_rx,
+                pause_tx,
+                dropped: Arc::default(),
+                held_orchestrator: Cell::new(held_orchestrator),
+                held_explorer: Cell::new(held_explorer),
+                relayed: Cell::new(0),
+                panics,
+                outbox_capacity: self.outbox_capacity,
+                backlog: RefCell::default(),
+                replies_dropped: Cell::new(0),
+                resets: ai_handle
+                    .as_ref()
+                    .map_or_else(Arc::default, |ai| Arc::clone(&ai.resets)),
+                pruned_resets: Cell::new(0),
+            },
+            Relay {
+                to_planet,
+                to_planet_expl,
+            },
+            ai_handle,
+        ))
+    }
+}
+
+/// Checks that the orchestrator and explorer channels of planet `id` are
+/// open, returning the messages taken from them in the process.
+///
+/// Checking a channel takes a message if one is queued: it is held back and
+/// relayed before anything else once the planet runs.
+///
+/// # Errors
+/// Returns [`TripError::OrchestratorChannelClosed`] or
+/// [`TripError::ExplorerChannelClosed`] for the first closed channel.
+fn check_channels(
+    id: u32,
+    orchestrator: &Receiver<OrchestratorToPlanet>,
+    explorers: &Receiver<ExplorerToPlanet>,
+) -> Result<(Option<OrchestratorToPlanet>, Option<ExplorerToPlanet>), TripError> {
+    let held_orchestrator = check_open(
+        id,
+        orchestrator,
+        "OrchestratorToPlanet",
+        TripError::OrchestratorChannelClosed,
+    )?;
+    let held_explorer = check_open(
+        id,
+        explorers,
+        "ExplorerToPlanet",
+        TripError::ExplorerChannelClosed,
+    )?;
+    Ok((held_orchestrator, held_explorer))
+}
+
+/// Checks that the `name` channel of planet `id` is open, returning the
+/// message taken from it in the process, if one was queued.
+///
+/// # Errors
+/// Returns `closed` if the channel is closed.
+fn check_open<T: Debug>(
+    id: u32,
+    channel: &Receiver<T>,
+    name: &str,
+    closed: TripError,
+) -> Result<Option<T>, TripError> {
+    match channel.try_recv() {
+        Err(TryRecvError::Disconnected) => {
+            error!("{name} channel is closed for planet {id}");
+            Err(closed)
+        }
+        Err(TryRecvError::Empty) => {
+            debug!("{name} channel open for planet {id}");
+            Ok(None)
+        }
+        Ok(msg) => {
+            debug!("{name} channel open for planet {id}, holding {msg:?}");
+            Ok(Some(msg))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_with_defaults() {
+        let (_orch_tx, orch_rx) = crossbeam_channel::unbounded();
+        let (planet_tx, _planet_rx) = crossbeam_channel::unbounded();
+        let (_expl_tx, expl_rx) = crossbeam_channel::unbounded();
+
+        let trip = TripBuilder::new(4)
+            .channels(orch_rx, planet_tx, expl_rx)
+            .build()
+            .expect("Planet must be created");
+        assert_eq!(trip.planet.id(), 4);
+        assert!(matches!(trip.planet.planet_type(), PlanetType::A));
+        assert!(trip.planet.generator().contains(BasicResourceType::Oxygen));
+        assert!(trip.planet.combinator().all_available_recipes().is_empty());
+    }
+
+    #[test]
+    fn test_build_fully_customized() {
+        let (_orch_tx, orch_rx) = crossbeam_channel::unbounded();
+        let (planet_tx, _planet_rx) = crossbeam_channel::unbounded();
+        let (_expl_tx, expl_rx) = crossbeam_channel::unbounded();
+
+        let trip = TripBuilder::new(5)
+            .planet_type(PlanetType::C)
+            .generation_rules(vec![BasicResourceType::Hydrogen])
+            .combination_rules(vec![ComplexResourceType::Water])
+            .ai(Box::new(AI::with_reserve(0, false)))
+            .channels(orch_rx, planet_tx, expl_rx)
+            .build()
+            .expect("Planet must be created");
+        assert_eq!(trip.planet.id(), 5);
+        assert!(matches!(trip.planet.planet_type(), PlanetType::C));
+        assert!(
+            trip.planet
+                .generator()
+                .contains(BasicResourceType::Hydrogen)
+        );
+        assert!(
+            trip.planet
+                .combinator()
+                .contains(ComplexResourceType::Water)
+        );
+    }
+
+    #[test]
+    fn test_build_without_channels() {
+        assert!(matches!(
+            TripBuilder::new(6).build(),
+            Err(TripError::MissingChannels)
+        ));
+    }
+}
@@ -0,0 +1,41 @@
+//! Errors returned while setting up a [`Trip`](crate::Trip) planet.
+
+use std::fmt;
+
+/// Reason why a [`Trip`](crate::Trip) planet could not be created.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TripError {
+    /// The orchestrator-to-planet channel was already disconnected.
+    OrchestratorChannelClosed,
+    /// The explorer-to-planet channel was already disconnected.
+    ExplorerChannelClosed,
+    /// A [`TripBuilder`](crate::TripBuilder) was built without channels.
+    MissingChannels,
+    /// [`Planet::new`](common_game::components::planet::Planet::new) rejected
+    /// the configuration of planet `id`, e.g. its rules, with the message in
+    /// `source`.
+    PlanetInit { id: u32, source: String },
+}
+
+impl fmt::Display for TripError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::OrchestratorChannelClosed => {
+                f.write_str("OrchestratorToPlanet channel is closed")
+            }
+            Self::ExplorerChannelClosed => f.write_str("ExplorerToPlanet channel is closed"),
+            Self::MissingChannels => f.write_str("no channels were given to the planet"),
+            Self::PlanetInit { id, source } => {
+                write!(f, "planet_id={id} initialization failed: {source}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for TripError {}
+
+impl From<TripError> for String {
+    fn from(e: TripError) -> Self {
+        e.to_string()
+    }
+}
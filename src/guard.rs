@@ -0,0 +1,188 @@
+//! Panic isolation for the AI of a [`Trip`](crate::Trip) planet.
+//!
+//! The `common_game` planet calls its AI on the planet's own thread, so a
+//! panicking handler would take the whole planet down with it. [`PanicGuard`]
+//! wraps the AI and turns such a panic into an error log and the answer a
+//! planet gives when it has nothing to say.
+//!
+//! With the `tracing` feature, every handler also runs inside a span
+//! carrying the planet id, the kind of message and the explorer id, if any,
+//! so the log lines of one message can be told apart from the others.
+
+use common_game::components::planet::{DummyPlanetState, PlanetAI, PlanetState};
+use common_game::components::resource::{Combinator, Generator};
+use common_game::components::rocket::Rocket;
+use common_game::components::sunray::Sunray;
+use common_game::protocols::planet_explorer::{ExplorerToPlanet, PlanetToExplorer};
+use log::error;
+use std::any::Any;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// [`PlanetAI`] running another AI with every handler shielded from panics.
+///
+/// After a panic, the wrapped AI keeps handling the next messages with
+/// whatever state the panic left behind: the planet stays up, but the AI
+/// may have lost track of part of its work.
+pub(crate) struct PanicGuard {
+    ai: Box<dyn PlanetAI>,
+    /// Panics caught so far, shared with the [`Trip`](crate::Trip).
+    panics: Arc<AtomicU64>,
+}
+
+impl PanicGuard {
+    pub(crate) fn new(ai: Box<dyn PlanetAI>, panics: Arc<AtomicU64>) -> Self {
+        Self { ai, panics }
+    }
+
+    /// Runs `handler` on the wrapped AI, logging a panic with the kind of
+    /// message that caused it.
+    ///
+    /// # Returns
+    /// `None` if the handler panicked.
+    fn shielded<R>(
+        &mut self,
+        planet_id: u32,
+        msg: &str,
+        explorer_id: Option<u32>,
+        handler: impl FnOnce(&mut dyn PlanetAI) -> R,
+    ) -> Option<R> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!("planet_msg", planet_id, msg, explorer_id).entered();
+        #[cfg(not(feature = "tracing"))]
+        let _ = explorer_id;
+        let ai = &mut *self.ai;
+        panic::catch_unwind(AssertUnwindSafe(|| handler(ai)))
+            .inspect_err(|payload| {
+                self.panics.fetch_add(1, Ordering::Relaxed);
+                error!(
+                    "planet_id={planet_id} msg={msg} ai_panicked: {}",
+                    reason(payload.as_ref())
+                );
+            })
+            .ok()
+    }
+}
+
+/// Returns the message a panic was raised with.
+fn reason(payload: &(dyn Any + Send)) -> &str {
+    payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("non-string panic payload")
+}
+
+/// Returns the name of an explorer request, for logging.
+fn explorer_msg_kind(msg: &ExplorerToPlanet) -> &'static str {
+    match msg {
+        ExplorerToPlanet::SupportedResourceRequest { .. } => "SupportedResourceRequest",
+        ExplorerToPlanet::SupportedCombinationRequest { .. } => "SupportedCombinationRequest",
+        ExplorerToPlanet::GenerateResourceRequest { .. } => "GenerateResourceRequest",
+        ExplorerToPlanet::CombineResourceRequest { .. } => "CombineResourceRequest",
+        ExplorerToPlanet::AvailableEnergyCellRequest { .. } => "AvailableEnergyCellRequest",
+    }
+}
+
+impl PlanetAI for PanicGuard {
+    fn handle_sunray(
+        &mut self,
+        state: &mut PlanetState,
+        generator: &Generator,
+        combinator: &Combinator,
+        sunray: Sunray,
+    ) {
+        self.shielded(state.id(), "Sunray", None, |ai| {
+            ai.handle_sunray(state, generator, combinator, sunray);
+        });
+    }
+
+    /// A panic leaves the planet undefended.
+    fn handle_asteroid(
+        &mut self,
+        state: &mut PlanetState,
+        generator: &Generator,
+        combinator: &Combinator,
+    ) -> Option<Rocket> {
+        self.shielded(state.id(), "Asteroid", None, |ai| {
+            ai.handle_asteroid(state, generator, combinator)
+        })
+        .flatten()
+    }
+
+    /// A panic is answered with the state as the planet sees it.
+    fn handle_internal_state_req(
+        &mut self,
+        state: &mut PlanetState,
+        generator: &Generator,
+        combinator: &Combinator,
+    ) -> DummyPlanetState {
+        self.shielded(state.id(), "InternalStateRequest", None, |ai| {
+            ai.handle_internal_state_req(state, generator, combinator)
+        })
+        .unwrap_or_else(|| state.to_dummy())
+    }
+
+    /// A panic leaves the explorer without an answer.
+    fn handle_explorer_msg(
+        &mut self,
+        state: &mut PlanetState,
+        generator: &Generator,
+        combinator: &Combinator,
+        msg: ExplorerToPlanet,
+    ) -> Option<PlanetToExplorer> {
+        let kind = explorer_msg_kind(&msg);
+        let explorer_id = msg.explorer_id();
+        self.shielded(state.id(), kind, Some(explorer_id), |ai| {
+            ai.handle_explorer_msg(state, generator, combinator, msg)
+        })
+        .flatten()
+    }
+
+    fn on_explorer_arrival(
+        &mut self,
+        state: &mut PlanetState,
+        generator: &Generator,
+        combinator: &Combinator,
+        explorer_id: u32,
+    ) {
+        self.shielded(
+            state.id(),
+            "IncomingExplorerRequest",
+            Some(explorer_id),
+            |ai| {
+                ai.on_explorer_arrival(state, generator, combinator, explorer_id);
+            },
+        );
+    }
+
+    fn on_explorer_departure(
+        &mut self,
+        state: &mut PlanetState,
+        generator: &Generator,
+        combinator: &Combinator,
+        explorer_id: u32,
+    ) {
+        self.shielded(
+            state.id(),
+            "OutgoingExplorerRequest",
+            Some(explorer_id),
+            |ai| {
+                ai.on_explorer_departure(state, generator, combinator, explorer_id);
+            },
+        );
+    }
+
+    fn on_start(&mut self, state: &PlanetState, generator: &Generator, combinator: &Combinator) {
+        self.shielded(state.id(), "StartPlanetAI", None, |ai| {
+            ai.on_start(state, generator, combinator);
+        });
+    }
+
+    fn on_stop(&mut self, state: &PlanetState, generator: &Generator, combinator: &Combinator) {
+        self.shielded(state.id(), "StopPlanetAI", None, |ai| {
+            ai.on_stop(state, generator, combinator);
+        });
+    }
+}
@@ -1,23 +1,900 @@
-use common_game::components::planet::{Planet, PlanetType};
-use common_game::components::resource::BasicResourceType;
+use common_game::components::planet::{Planet, PlanetAI, PlanetType};
+use common_game::components::resource::{BasicResourceType, ComplexResourceType};
 use common_game::protocols::orchestrator_planet::{OrchestratorToPlanet, PlanetToOrchestrator};
-use common_game::protocols::planet_explorer::ExplorerToPlanet;
-use log::{debug, error, info};
+use common_game::protocols::planet_explorer::{ExplorerToPlanet, PlanetToExplorer};
+use log::{error, info};
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+use std::time::Instant;
 
 mod ai;
+mod builder;
+mod error;
+mod guard;
+mod relay;
+mod resource_names;
 
-use crate::ai::AI;
+use crate::ai::{AI, AiHandle, AiStats, RuleOverride};
+pub use crate::ai::{AiConfig, CellSelection, PlanetMode, RateLimit};
+pub use crate::builder::TripBuilder;
+pub use crate::error::TripError;
+use crate::relay::{Link, Relay, Shutdown};
 
-/// Constructs and returns a fully initialized [`Planet`] instance for our group.
+/// Our group's planet, ready to be driven by an orchestrator.
+///
+/// A `Trip` owns the underlying `common_game` [`Planet`] together with our
+/// [`AI`](crate::ai::AI), and is obtained through [`trip`],
+/// [`trip_with_type`], [`trip_with_rules`], [`trip_with_ai`],
+/// [`Trip::new_pair`] or a [`TripBuilder`]; [`Trip::from_planet`] wraps a
+/// planet built by the caller instead. The wrapped planet is not exposed, so the public
+/// surface stays independent of `common_game` internals.
+///
+/// The planet never touches the caller's channels directly: messages are
+/// relayed through an internal set of channels, which lets `Trip` observe
+/// the traffic and decide when the planet's loop has to wind down.
+///
+/// A panic in the AI, built-in or injected, is logged and contained to the
+/// message that caused it: the planet answers as if the AI had nothing to
+/// say and keeps running.
+pub struct Trip {
+    planet: Planet,
+    link: Link,
+    relay: Option<Relay>,
+    ai: Option<AiHandle>,
+}
+
+/// Handle to observe and stop a [`Trip`] from other threads while it runs.
+///
+/// Obtained through [`Trip::handle`]; cloning it is cheap.
+///
+/// [`Trip::run`] borrows the `Trip` mutably and blocks the owning thread, so
+/// while the planet runs it can only be reached through a handle. The
+/// `Trip` methods that forward to a handle are a shortcut for before the
+/// planet runs or after its loop returned, and the snapshots taken by
+/// [`Trip::planet_info`] and [`Trip::snapshot`] never see the loop
+/// half-way through a message.
+#[derive(Debug, Clone)]
+pub struct TripHandle {
+    running: Arc<AtomicBool>,
+    shutdown: crossbeam_channel::Sender<()>,
+    paused: Arc<AtomicBool>,
+    pause: crossbeam_channel::Sender<()>,
+    maintenance: Option<Arc<AtomicBool>>,
+    generation_rules: Option<Arc<Mutex<RuleOverride>>>,
+    resets: Option<Arc<AtomicU64>>,
+}
+
+impl TripHandle {
+    /// Returns `true` between the planet acknowledging a `StartPlanetAI` and
+    /// it acknowledging a `StopPlanetAI` or `KillPlanet`, or its loop
+    /// returning.
+    #[must_use]
+    pub fn is_running(&self) -> bool {
+        self.running.load(Ordering::Acquire)
+    }
+
+    /// Asks the planet's loop to wind down, without relying on the
+    /// orchestrator dropping its channels.
+    ///
+    /// The message loop stops relaying, lets the planet finish the messages
+    /// already handed to it and returns; [`Trip::run`] reports
+    /// [`Termination::ShutdownRequested`]. Messages still queued on
+    /// the caller's channels are dropped. If the planet is not running yet,
+    /// its next run returns right away. Calling it again has no effect.
+    pub fn shutdown(&self) {
+        // A full channel means a shutdown is already pending.
+        let _ = self.shutdown.try_send(());
+    }
+
+    /// Stops the planet's AI as a `StopPlanetAI` would, without going
+    /// through the orchestrator.
+    ///
+    /// The orchestrator is not told: it gets no `StopPlanetAIResult`, and
+    /// its messages are answered with `Stopped` until [`resume`] is called.
+    /// A `StartPlanetAI` of the orchestrator is followed by a new pause. Only
+    /// takes effect while [`Trip::run`] relays messages; a pause requested
+    /// before applies when the planet next starts.
+    ///
+    /// [`resume`]: TripHandle::resume
+    pub fn pause(&self) {
+        self.set_paused(true);
+    }
+
+    /// Starts the planet's AI again after a [`pause`](TripHandle::pause).
+    ///
+    /// A planet stopped by the orchestrator in the meantime stays stopped:
+    /// only the orchestrator's own `StartPlanetAI` restarts it.
+    pub fn resume(&self) {
+        self.set_paused(false);
+    }
+
+    fn set_paused(&self, paused: bool) {
+        self.paused.store(paused, Ordering::Release);
+        // A full channel means the relay has yet to look at the flag.
+        let _ = self.pause.try_send(());
+    }
+
+    /// Switches a running planet into [`PlanetMode::Maintenance`], or back
+    /// to [`PlanetMode::Running`], from its next message on.
+    ///
+    /// In maintenance the planet keeps answering internal state requests
+    /// and explorers' read-only queries, but refuses to generate or combine
+    /// resources and to build rockets. A planet built with a custom
+    /// [`TripBuilder::ai`] ignores the switch.
+    pub fn set_maintenance(&self, on: bool) {
+        if let Some(maintenance) = &self.maintenance {
+            maintenance.store(on, Ordering::Release);
+        }
+    }
+
+    /// Clears the AI's runtime state for a new simulation epoch, keeping its
+    /// configuration.
+    ///
+    /// Activity counters are zeroed and health restored, the rocket reserve
+    /// and the buffered sunrays are discarded, and on its next start the
+    /// planet lets go of every explorer, which have to be sent in again.
+    /// Energy cells keep their charge and the rocket standing on the planet
+    /// stays. The reset takes effect when the
+    /// planet next stops, so it never disturbs a running epoch, or when it
+    /// next starts if it is stopped already. A planet built with a custom
+    /// [`TripBuilder::ai`] ignores the call.
+    pub fn reset(&self) {
+        if let Some(resets) = &self.resets {
+            resets.fetch_add(1, Ordering::AcqRel);
+        }
+    }
+
+    /// Narrows the basic resources the planet generates to `rules`, or
+    /// restores the rules it was built with if `None`, from the next
+    /// explorer request on.
+    ///
+    /// `common_game` has no orchestrator message for this and fixes a
+    /// planet's generator when it is created, so rules can only be
+    /// narrowed: resources outside the planet's own rules are ignored with a
+    /// warning. Explorers see the change in their next
+    /// `SupportedResourceResponse`, as does [`Trip::planet_info`]. A planet
+    /// built with a custom [`TripBuilder::ai`] ignores the call.
+    pub fn set_generation_rules(&self, rules: Option<&[BasicResourceType]>) {
+        if let Some(generation_rules) = &self.generation_rules {
+            *generation_rules
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner) = RuleOverride {
+                rules: rules.map(|rules| rules.iter().copied().collect()),
+                changed: true,
+            };
+        }
+    }
+}
+
+/// Messages the planet answered with `Stopped` because its AI was not
+/// running, by kind; see [`TripMetrics::dropped_while_stopped`].
+///
+/// A planet that seems to ignore everything usually received its messages
+/// before `StartPlanetAI` or after `StopPlanetAI`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct DroppedWhileStopped {
+    /// Sunrays. Up to
+    /// [`AiConfig::sunray_buffer_capacity`] of them are replayed once the
+    /// planet starts again, the rest are lost for good.
+    pub sunrays: u64,
+    /// Asteroids, which were not answered with an `AsteroidAck`.
+    pub asteroids: u64,
+    /// Internal state requests.
+    pub internal_state_requests: u64,
+    /// Incoming and outgoing explorer requests.
+    pub explorer_transfers: u64,
+    /// `StopPlanetAI` requests for a planet already stopped.
+    pub stop_requests: u64,
+    /// Requests of the explorers themselves.
+    pub explorer_requests: u64,
+}
+
+/// Basic resources generated over the planet's lifetime, by type; see
+/// [`TripMetrics::yields`].
+///
+/// There is one counter per basic resource rather than a map, so
+/// [`TripMetrics`] stays `Copy`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ResourceYields {
+    /// Oxygen units generated.
+    pub oxygen: u64,
+    /// Hydrogen units generated.
+    pub hydrogen: u64,
+    /// Carbon units generated.
+    pub carbon: u64,
+    /// Silicon units generated.
+    pub silicon: u64,
+}
+
+impl ResourceYields {
+    /// Returns how many units of `resource` were generated.
+    #[must_use]
+    pub fn get(&self, resource: BasicResourceType) -> u64 {
+        match resource {
+            BasicResourceType::Oxygen => self.oxygen,
+            BasicResourceType::Hydrogen => self.hydrogen,
+            BasicResourceType::Carbon => self.carbon,
+            BasicResourceType::Silicon => self.silicon,
+        }
+    }
+
+    /// Counts one more unit of `resource`.
+    pub(crate) fn record(&mut self, resource: BasicResourceType) {
+        let count = match resource {
+            BasicResourceType::Oxygen => &mut self.oxygen,
+            BasicResourceType::Hydrogen => &mut self.hydrogen,
+            BasicResourceType::Carbon => &mut self.carbon,
+            BasicResourceType::Silicon => &mut self.silicon,
+        };
+        *count += 1;
+    }
+}
+
+/// Complex resources combined over the planet's lifetime, by recipe; see
+/// [`TripMetrics::complex_yields`].
+///
+/// Like [`ResourceYields`], one counter per recipe keeps [`TripMetrics`]
+/// `Copy`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ComplexYields {
+    /// Water combined.
+    pub water: u64,
+    /// Diamonds combined.
+    pub diamond: u64,
+    /// Life combined.
+    pub life: u64,
+    /// Robots combined.
+    pub robot: u64,
+    /// Dolphins combined.
+    pub dolphin: u64,
+    /// AI partners combined.
+    pub ai_partner: u64,
+}
+
+impl ComplexYields {
+    /// Returns how many units of `resource` were combined.
+    #[must_use]
+    pub fn get(&self, resource: ComplexResourceType) -> u64 {
+        match resource {
+            ComplexResourceType::Water => self.water,
+            ComplexResourceType::Diamond => self.diamond,
+            ComplexResourceType::Life => self.life,
+            ComplexResourceType::Robot => self.robot,
+            ComplexResourceType::Dolphin => self.dolphin,
+            ComplexResourceType::AIPartner => self.ai_partner,
+        }
+    }
+
+    /// Counts one more unit of `resource`.
+    pub(crate) fn record(&mut self, resource: ComplexResourceType) {
+        let count = match resource {
+            ComplexResourceType::Water => &mut self.water,
+            ComplexResourceType::Diamond => &mut self.diamond,
+            ComplexResourceType::Life => &mut self.life,
+            ComplexResourceType::Robot => &mut self.robot,
+            ComplexResourceType::Dolphin => &mut self.dolphin,
+            ComplexResourceType::AIPartner => &mut self.ai_partner,
+        };
+        *count += 1;
+    }
+}
+
+/// Snapshot of the activity counters of our AI, see [`Trip::metrics`].
+///
+/// With the `serde` feature enabled, the snapshot can be serialized, e.g. to
+/// ship it to a monitoring pipeline as JSON.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct TripMetrics {
+    /// Sunrays received, whether they charged a cell or not.
+    pub sunrays: u64,
+    /// Rockets built, for the reserve or on an asteroid hit.
+    pub rockets_built: u64,
+    /// Rockets launched against asteroids.
+    pub rockets_launched: u64,
+    /// Basic resources generated for explorers.
+    pub resources_generated: u64,
+    /// [`TripMetrics::resources_generated`] broken down by resource. A
+    /// [`Trip::reset`] leaves it alone, as it covers the whole lifetime.
+    pub yields: ResourceYields,
+    /// Complex resources combined for explorers.
+    pub complex_resources_produced: u64,
+    /// [`TripMetrics::complex_resources_produced`] broken down by recipe,
+    /// over the planet's lifetime like [`TripMetrics::yields`].
+    pub complex_yields: ComplexYields,
+    /// Explorer requests handled while running.
+    pub explorers_served: u64,
+    /// Rockets that could not be built on any charged cell, whether for the
+    /// reserve or against an asteroid. A sunray that fails to build its
+    /// rocket is still acknowledged, so this is where such failures show.
+    pub rocket_build_failures: u64,
+    /// Rockets ready for launch as of the last `InternalStateRequest`.
+    pub stockpiled_rockets: usize,
+    /// Whether a rocket was ready as of the last `InternalStateRequest`.
+    pub has_rocket: bool,
+    /// Charged energy cells as of the last `InternalStateRequest` or
+    /// asteroid, so a scheduler can tell when a rocket built to answer an
+    /// asteroid has depleted the planet.
+    pub charged_cells: usize,
+    /// Explorers currently on the planet. Explorers found disconnected are
+    /// let go of right away, or when the planet is restarted if it is
+    /// stopped.
+    pub explorers: usize,
+    /// Health left, lowered by every asteroid met without a rocket; zero is
+    /// game over. `common_game`'s `InternalStateResponse` has no room for
+    /// it, so it is only reported here.
+    pub health: u32,
+    /// Explorer requests dropped for exceeding
+    /// [`AiConfig::explorer_rate_limit`].
+    pub rate_limited_requests: u64,
+    /// Messages that reached the planet while it was stopped. Unlike the
+    /// other counters, these are kept for custom AIs as well.
+    pub dropped_while_stopped: DroppedWhileStopped,
+    /// Explorer requests rejected for exceeding
+    /// [`TripBuilder::max_queued_per_explorer`]; kept for custom AIs as well.
+    pub explorer_requests_rejected: u64,
+}
+
+impl From<AiStats> for TripMetrics {
+    fn from(stats: AiStats) -> Self {
+        Self {
+            sunrays: stats.sunrays_received,
+            rockets_built: stats.rockets_built,
+            rockets_launched: stats.rockets_launched,
+            resources_generated: stats.resources_generated,
+            yields: stats.yields,
+            complex_resources_produced: stats.complex_resources_produced,
+            complex_yields: stats.complex_yields,
+            explorers_served: stats.explorers_served,
+            rocket_build_failures: stats.rocket_build_failures,
+            stockpiled_rockets: stats.stockpiled_rockets,
+            has_rocket: stats.stockpiled_rockets > 0,
+            charged_cells: stats.charged_cells,
+            explorers: stats.explorers,
+            health: stats.health,
+            rate_limited_requests: stats.rate_limited,
+            dropped_while_stopped: DroppedWhileStopped::default(),
+            explorer_requests_rejected: 0,
+        }
+    }
+}
+
+/// What an explorer learns from a planet, see [`Trip::planet_info`].
+///
+/// The explorer protocol of `common_game` is fixed, so explorers still need
+/// three requests to gather the same information; in-process callers get it
+/// in one go, together with the total number of cells and the rockets ready
+/// for launch, which the protocol has no room for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlanetInfo {
+    /// Basic resources the planet can generate, as in a
+    /// `SupportedResourceResponse`.
+    pub supported_resources: HashSet<BasicResourceType>,
+    /// Complex resources the planet can combine, as in a
+    /// `SupportedCombinationResponse`.
+    pub supported_combinations: HashSet<ComplexResourceType>,
+    /// Charged energy cells, as in an `AvailableEnergyCellResponse`.
+    pub available_cells: u32,
+    /// Energy cells of the planet, charged or not, see
+    /// [`Trip::energy_cell_capacity`].
+    pub total_cells: u32,
+    /// Rockets ready to meet an asteroid: the one held by the planet's state
+    /// plus our AI's reserve, see [`PlanetSnapshot::rocket_reserve`]. Lets
+    /// explorers tell well-defended planets apart.
+    pub rockets: usize,
+}
+
+/// The state of a planet together with its metrics, see [`Trip::snapshot`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct PlanetSnapshot {
+    /// Charged energy cells.
+    pub charged_cells: usize,
+    /// Energy cells of the planet, charged or not.
+    pub total_cells: usize,
+    /// Rockets built in advance and kept by the AI, besides the one held by
+    /// the planet's state.
+    pub rocket_reserve: usize,
+    /// Whether a rocket is ready for launch, in the planet's state or in the
+    /// reserve, as our AI reports in an `InternalStateResponse`.
+    pub has_rocket: bool,
+    /// Whether the planet's AI is running, see [`Trip::is_running`].
+    pub is_running: bool,
+    /// The AI's activity counters, see [`Trip::metrics`].
+    pub metrics: TripMetrics,
+}
+
+/// The caller's ends of the channels created by [`Trip::new_pair`].
+///
+/// The planet holds the other ends, except for the planet-to-explorer
+/// channel: the planet only learns of it when the orchestrator hands
+/// [`planet_to_expl`](TripChannels::planet_to_expl) over in an
+/// `IncomingExplorerRequest`.
+#[derive(Debug)]
+pub struct TripChannels {
+    /// Sends orchestrator messages to the planet.
+    pub orch_to_planet: crossbeam_channel::Sender<OrchestratorToPlanet>,
+    /// Receives the planet's answers to the orchestrator.
+    pub planet_to_orch: crossbeam_channel::Receiver<PlanetToOrchestrator>,
+    /// Sends explorer requests to the planet.
+    pub expl_to_planet: crossbeam_channel::Sender<ExplorerToPlanet>,
+    /// Sender to hand the planet in an `IncomingExplorerRequest`.
+    pub planet_to_expl: crossbeam_channel::Sender<PlanetToExplorer>,
+    /// Receives the planet's answers to the explorers sent through
+    /// [`planet_to_expl`](TripChannels::planet_to_expl).
+    pub expl_from_planet: crossbeam_channel::Receiver<PlanetToExplorer>,
+}
+
+/// Why [`Trip::run`] returned, see [`RunReport::reason`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum Termination {
+    /// [`TripHandle::shutdown`] was called.
+    ShutdownRequested,
+    /// The orchestrator dropped its sender, or its receiver: the first reply
+    /// that cannot be delivered shuts the planet down instead of leaving it
+    /// spinning.
+    OrchestratorDisconnected,
+    /// The planet's loop returned on its own after a `KillPlanet`.
+    Killed,
+    /// The deadline given to [`Trip::run_until`] passed.
+    DeadlineReached,
+    /// The planet's loop failed, with the error it reported.
+    PlanetFailed(String),
+}
+
+/// Summary of one call to [`Trip::run`] or [`Trip::run_until`], meant for
+/// the orchestrator's logs.
+///
+/// Explorers never stop the planet, so a disconnected explorer is not a
+/// [`Termination`] but shows in [`RunReport::explorers_disconnected`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct RunReport {
+    /// Why the run ended.
+    pub reason: Termination,
+    /// Messages of the orchestrator and the explorers handed to the planet
+    /// during the run.
+    pub messages_processed: u64,
+    /// Panics of the AI the planet recovered from during the run.
+    pub panics_recovered: u64,
+    /// Explorers found to have dropped their receiver during the run.
+    pub explorers_disconnected: usize,
+    /// Replies to the orchestrator dropped during the run because the
+    /// [`TripBuilder::orchestrator_outbox`] was full.
+    pub replies_dropped: u64,
+}
+
+/// How the relay loop of one run ended, see [`Trip::run_relay`].
+struct Ended {
+    shutdown: Shutdown,
+    result: Result<(), String>,
+    explorers_disconnected: usize,
+}
+
+/// Outcome of an asteroid hitting the planet.
+///
+/// Sent to the subscriber registered with [`TripBuilder::defense_events`]
+/// every time the planet answers an asteroid, next to the regular
+/// `AsteroidAck` sent to the orchestrator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum DefenseEvent {
+    /// A rocket was launched and the planet survived.
+    Defended { planet_id: u32 },
+    /// No rocket could be launched, the planet is left to its fate.
+    Undefended { planet_id: u32 },
+}
+
+/// Significant step taken by our AI, in the order taken.
+///
+/// Sent to the collector registered with [`TripBuilder::audit_events`], for
+/// post-run analysis. Resource names serialize as in the logs, e.g.
+/// `"oxygen"`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum AuditEvent {
+    /// The AI started running.
+    Started { planet_id: u32 },
+    /// The AI stopped.
+    Stopped { planet_id: u32 },
+    /// A sunray charged the energy cell at index `cell`.
+    SunrayCharged { planet_id: u32, cell: usize },
+    /// A rocket was built, for the reserve or against an asteroid.
+    RocketBuilt { planet_id: u32 },
+    /// A rocket was launched against an asteroid.
+    RocketLaunched { planet_id: u32 },
+    /// A basic resource was generated for an explorer.
+    ResourceGenerated {
+        planet_id: u32,
+        explorer_id: u32,
+        #[cfg_attr(feature = "serde", serde(serialize_with = "resource_names::serialize"))]
+        resource: BasicResourceType,
+    },
+}
+
+impl Trip {
+    /// Wraps an already constructed [`Planet`] listening on `relay`, with the
+    /// caller's channels in `link` and the shared state of its AI in `ai`,
+    /// if it is ours.
+    fn new(planet: Planet, link: Link, relay: Relay, ai: Option<AiHandle>) -> Self {
+        Self {
+            planet,
+            link,
+            relay: Some(relay),
+            ai,
+        }
+    }
+
+    /// Creates a planet like [`trip`] does, on unbounded channels of its
+    /// own, and returns it with the caller's ends of those channels.
+    ///
+    /// # Errors
+    ///
+    /// - [`TripError::PlanetInit`] if [`Planet::new`] fails due to invalid
+    ///   parameters.
+    pub fn new_pair(id: u32) -> Result<(Self, TripChannels), TripError> {
+        let (orch_to_planet, orch_rx) = crossbeam_channel::unbounded();
+        let (planet_tx, planet_to_orch) = crossbeam_channel::unbounded();
+        let (expl_to_planet, expl_rx) = crossbeam_channel::unbounded();
+        let (planet_to_expl, expl_from_planet) = crossbeam_channel::unbounded();
+        let trip = trip(id, orch_rx, planet_tx, expl_rx)?;
+        let channels = TripChannels {
+            orch_to_planet,
+            planet_to_orch,
+            expl_to_planet,
+            planet_to_expl,
+            expl_from_planet,
+        };
+        Ok((trip, channels))
+    }
+
+    /// Wraps a [`Planet`] built by the caller, e.g. with an AI and rules of
+    /// its own, so it can be run like any other `Trip`.
+    ///
+    /// Such a planet talks to the channels it was built with directly, as
+    /// there is no way to slip the relay in between, so everything the
+    /// relay provides is lost: [`Trip::run`] only returns once the planet's
+    /// own loop does, [`TripHandle`] controls have no effect, panics of its
+    /// AI are not contained and [`Trip::metrics`] reports zeros. Prefer a
+    /// [`TripBuilder`] with a custom [`TripBuilder::ai`] when the planet's
+    /// rules are enough to describe it.
+    #[must_use]
+    pub fn from_planet(planet: Planet) -> Self {
+        let link = Link::detached(planet.id());
+        Self {
+            planet,
+            link,
+            relay: None,
+            ai: None,
+        }
+    }
+
+    /// Returns the id the planet was created with.
+    #[must_use]
+    pub fn planet_id(&self) -> u32 {
+        self.planet.id()
+    }
+
+    /// Returns the type the planet was created with.
+    #[must_use]
+    pub fn planet_type(&self) -> PlanetType {
+        self.planet.planet_type()
+    }
+
+    /// Returns the number of energy cells of the planet.
+    ///
+    /// The capacity is fixed by the [`PlanetType`] in `common_game` and
+    /// cannot be configured: at most this many cells can be charged at once.
+    /// Every type comes with at least one, so there is no planet without
+    /// energy cells to guard against.
+    #[must_use]
+    pub fn energy_cell_capacity(&self) -> usize {
+        self.planet.state().cells_count()
+    }
+
+    /// Returns the supported resources, supported combinations and available
+    /// energy cells of the planet, as its explorers would be told, along with
+    /// its rockets.
+    ///
+    /// Taken while the planet's loop is not running, see [`TripHandle`].
+    #[must_use]
+    pub fn planet_info(&self) -> PlanetInfo {
+        let state = self.planet.state();
+        let charged = state.cells_iter().filter(|cell| cell.is_charged()).count();
+        PlanetInfo {
+            supported_resources: self.supported_resources(),
+            supported_combinations: self.planet.combinator().all_available_recipes(),
+            available_cells: AI::cell_count(state.id(), charged),
+            total_cells: AI::cell_count(state.id(), state.cells_count()),
+            rockets: usize::from(state.has_rocket())
+                + self.ai_stats().map_or(0, |stats| stats.reserve_rockets),
+        }
+    }
+
+    /// Returns the basic resources of the planet's generation rules, as
+    /// narrowed through [`TripHandle::set_generation_rules`].
+    fn supported_resources(&self) -> HashSet<BasicResourceType> {
+        let mut resources = self.planet.generator().all_available_recipes();
+        if let Some(ai) = &self.ai
+            && let Some(rules) = &ai
+                .generation_rules
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .rules
+        {
+            resources.retain(|resource| rules.contains(resource));
+        }
+        resources
+    }
+
+    /// Returns whether the planet's AI is currently active, see
+    /// [`TripHandle::is_running`].
+    #[must_use]
+    pub fn is_running(&self) -> bool {
+        self.handle().is_running()
+    }
+
+    /// Asks the planet's loop to wind down, see [`TripHandle::shutdown`].
+    pub fn shutdown(&self) {
+        self.handle().shutdown();
+    }
+
+    /// Clears the AI's runtime state for a new simulation epoch, see
+    /// [`TripHandle::reset`].
+    pub fn reset(&self) {
+        self.handle().reset();
+    }
+
+    /// Stops the planet's AI without going through the orchestrator, see
+    /// [`TripHandle::pause`].
+    pub fn pause(&self) {
+        self.handle().pause();
+    }
+
+    /// Starts the planet's AI again after a pause, see
+    /// [`TripHandle::resume`].
+    pub fn resume(&self) {
+        self.handle().resume();
+    }
+
+    /// Returns a handle to observe and stop the planet while [`Trip::run`] blocks
+    /// the thread that owns it.
+    #[must_use]
+    pub fn handle(&self) -> TripHandle {
+        TripHandle {
+            running: Arc::clone(&self.link.running),
+            shutdown: self.link.shutdown_tx.clone(),
+            paused: Arc::clone(&self.link.paused),
+            pause: self.link.pause_tx.clone(),
+            maintenance: self.ai.as_ref().map(|ai| Arc::clone(&ai.maintenance)),
+            generation_rules: self.ai.as_ref().map(|ai| Arc::clone(&ai.generation_rules)),
+            resets: self.ai.as_ref().map(|ai| Arc::clone(&ai.resets)),
+        }
+    }
+
+    /// Returns a snapshot of the AI's activity counters.
+    ///
+    /// All counters are read at once, so the snapshot is consistent even
+    /// while the planet is running. A planet built with a custom
+    /// [`TripBuilder::ai`] reports zeros.
+    #[must_use]
+    pub fn metrics(&self) -> TripMetrics {
+        self.metrics_of(self.ai_stats())
+    }
+
+    /// Returns the planet's state, whether its AI is running and the AI's
+    /// activity counters, all at once.
+    ///
+    /// Taken while the planet's loop is not running, see [`TripHandle`], so
+    /// nothing changes under it: unlike an `InternalStateRequest` followed by [`Trip::metrics`], its
+    /// parts always agree. The cell and rocket counts of
+    /// [`PlanetSnapshot::metrics`], otherwise as of the last
+    /// `InternalStateRequest`, are brought up to date to match. A planet
+    /// built with a custom [`TripBuilder::ai`] reports an empty reserve.
+    #[must_use]
+    pub fn snapshot(&self) -> PlanetSnapshot {
+        let planet = self.planet.state();
+        let stats = self.ai_stats();
+        let charged_cells = planet.cells_iter().filter(|cell| cell.is_charged()).count();
+        let rocket_reserve = stats.map_or(0, |stats| stats.reserve_rockets);
+        let stockpiled_rockets = usize::from(planet.has_rocket()) + rocket_reserve;
+        PlanetSnapshot {
+            charged_cells,
+            total_cells: planet.cells_count(),
+            rocket_reserve,
+            has_rocket: stockpiled_rockets > 0,
+            is_running: self.is_running(),
+            metrics: TripMetrics {
+                charged_cells,
+                stockpiled_rockets,
+                has_rocket: stockpiled_rockets > 0,
+                ..self.metrics_of(stats)
+            },
+        }
+    }
+
+    /// Returns a copy of the AI's activity counters, unless the AI is a
+    /// custom one.
+    fn ai_stats(&self) -> Option<AiStats> {
+        self.ai
+            .as_ref()
+            .map(|ai| *ai.stats.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+
+    /// Returns the metrics made of `stats` and the counters of the relay.
+    fn metrics_of(&self, stats: Option<AiStats>) -> TripMetrics {
+        let metrics = stats.map_or_else(TripMetrics::default, TripMetrics::from);
+        TripMetrics {
+            dropped_while_stopped: *self
+                .link
+                .dropped
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner),
+            explorer_requests_rejected: self.link.rejected.load(Ordering::Relaxed),
+            ..metrics
+        }
+    }
+
+    /// Runs the planet's message loop, blocking the current thread.
+    ///
+    /// Blocks until the planet is killed, shut down through a
+    /// [`TripHandle`] or the orchestrator disconnects, and reports which of
+    /// these happened in [`RunReport::reason`].
+    ///
+    /// Whatever the reason, the planet is wound down for good: a later call
+    /// returns right away. A call is therefore not bounded by a number of
+    /// messages, as the messages past the bound could never be answered;
+    /// bound it in time with [`Trip::run_until`] instead.
+    ///
+    /// Explorers never stop the planet: replies an explorer does not pick
+    /// up in time, or cannot receive anymore, are dropped with a warning.
+    #[must_use = "the report tells whether the planet ended cleanly"]
+    pub fn run(&mut self) -> RunReport {
+        self.run_reported(None)
+    }
+
+    /// Runs the planet's message loop for a bounded time, blocking the
+    /// current thread until `deadline` at the latest.
+    ///
+    /// The loop ends early for the same reasons as [`Trip::run`]; otherwise
+    /// it keeps handling messages, queued ones included, and winds the
+    /// planet down gracefully when the deadline passes, with
+    /// [`Termination::DeadlineReached`] as the report's reason. Messages
+    /// still queued on the caller's channels then are dropped and their
+    /// number is logged.
+    #[must_use = "the report tells whether the planet ended cleanly"]
+    pub fn run_until(&mut self, deadline: Instant) -> RunReport {
+        self.run_reported(Some(deadline))
+    }
+
+    fn run_reported(&mut self, deadline: Option<Instant>) -> RunReport {
+        let panics = self.link.panics.load(Ordering::Relaxed);
+        let ended = self.run_relay(deadline);
+        let reason = match (ended.shutdown, ended.result) {
+            (Shutdown::Requested, _) => Termination::ShutdownRequested,
+            (Shutdown::Disconnected, _) => Termination::OrchestratorDisconnected,
+            (Shutdown::DeadlineReached, _) => Termination::DeadlineReached,
+            (_, Ok(())) => Termination::Killed,
+            (_, Err(e)) => Termination::PlanetFailed(e),
+        };
+        RunReport {
+            reason,
+            messages_processed: self.link.relayed.take(),
+            panics_recovered: self.link.panics.load(Ordering::Relaxed) - panics,
+            explorers_disconnected: ended.explorers_disconnected,
+            replies_dropped: self.link.replies_dropped.take(),
+        }
+    }
+
+    /// Former name of [`Trip::run_until`], reducing its report to whether
+    /// the planet's loop failed.
+    ///
+    /// # Errors
+    ///
+    /// - `Err(String)` with the reason of [`Termination::PlanetFailed`].
+    #[deprecated(note = "use `Trip::run_until`, whose report tells how the run ended")]
+    pub fn run_with_deadline(&mut self, deadline: Instant) -> Result<(), String> {
+        match self.run_until(deadline).reason {
+            Termination::PlanetFailed(e) => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    /// Runs [`Planet::run`] on a scoped thread while relaying messages
+    /// between it and the caller's channels, until the planet exits, the
+    /// orchestrator disconnects or `deadline` passes.
+    fn run_relay(&mut self, deadline: Option<Instant>) -> Ended {
+        let Some(relay) = self.relay.take() else {
+            // The planet is already disconnected, let it report so.
+            return Ended {
+                shutdown: Shutdown::PlanetExited,
+                result: self.planet.run(),
+                explorers_disconnected: 0,
+            };
+        };
+        let id = self.planet.id();
+        let planet = &mut self.planet;
+        let link = &self.link;
+
+        thread::scope(|scope| {
+            let (done_tx, done_rx) = crossbeam_channel::bounded::<()>(0);
+            let handle = scope.spawn(move || {
+                let _done = done_tx;
+                planet.run()
+            });
+
+            let mut outboxes = HashMap::new();
+            let shutdown = link.forward(&relay, &mut outboxes, &done_rx, deadline);
+            drop(relay);
+            link.running.store(false, Ordering::Release);
+
+            let dropped =
+                link.from_orchestrator.try_iter().count() + link.from_explorers.try_iter().count();
+            info!("planet_id={id} shutting down ({shutdown:?}), dropped_pending={dropped}");
+
+            let result = handle
+                .join()
+                .unwrap_or_else(|_| Err(format!("planet {id} panicked")));
+            // Replies to the messages relayed before the shutdown.
+            link.flush(&outboxes);
+            if shutdown == Shutdown::PlanetExited
+                && let Err(e) = &result
+            {
+                error!("planet_id={id} loop failed, shut down: {e}");
+            }
+            Ended {
+                shutdown,
+                result,
+                explorers_disconnected: outboxes
+                    .values()
+                    .filter(|outbox| outbox.is_disconnected())
+                    .count(),
+            }
+        })
+    }
+}
+
+/// Returns the generation rules a planet of `planet_type` gets when none are
+/// supplied.
+///
+/// Every type generates a single, different basic resource, which fits the
+/// one rule limit of types A and C:
+///
+/// | Type | Resource |
+/// |------|----------|
+/// | A    | Oxygen   |
+/// | B    | Hydrogen |
+/// | C    | Carbon   |
+/// | D    | Silicon  |
+#[must_use]
+pub fn default_rules_for(planet_type: PlanetType) -> Vec<BasicResourceType> {
+    let resource = match planet_type {
+        PlanetType::A => BasicResourceType::Oxygen,
+        PlanetType::B => BasicResourceType::Hydrogen,
+        PlanetType::C => BasicResourceType::Carbon,
+        PlanetType::D => BasicResourceType::Silicon,
+    };
+    vec![resource]
+}
+
+/// Constructs and returns a fully initialized [`Trip`] planet for our group.
 ///
 /// This function is the public entry point used by other groups' orchestrators
-/// to instantiate our planet.
+/// to instantiate our planet. The planet is built as a [`PlanetType::A`]; use
+/// [`trip_with_type`] to choose a different type.
 ///
 /// # Behavior
 ///
-/// - Creates a new [`AI`] instance for this planet type.
+/// - Creates a new [`AI`](crate::ai::AI) instance for this planet type.
 /// - Configures the planet with our group's predefined generation and combination rules.
-/// - Initializes the internal [`Planet`] using [`Planet::new`] and returns it.
+/// - Initializes the internal [`Planet`] using [`Planet::new`] and returns it
+///   wrapped in a [`Trip`].
 ///
 /// # Parameters
 ///
@@ -28,52 +905,137 @@ use crate::ai::AI;
 ///
 /// # Returns
 ///
-/// - `Ok(Planet)` on successful construction.
+/// - `Ok(Trip)` on successful construction.
 ///
 /// # Errors
 ///
-/// - `Err(String)` if [`Planet::new`] fails due to invalid parameters.
+/// - [`TripError::OrchestratorChannelClosed`] or
+///   [`TripError::ExplorerChannelClosed`] if a channel is already closed.
+/// - [`TripError::PlanetInit`] if [`Planet::new`] fails due to invalid
+///   parameters.
 ///
 /// # See Also
+/// - [`trip_with_type`]
+/// - [`trip_with_rules`]
+/// - [`trip_with_ai`]
+/// - [`TripBuilder`]
 /// - [`Planet::new`]
-/// - [`AI`]
+/// - [`AI`](crate::ai::AI)
 pub fn trip(
     id: u32,
     orch_to_planet: crossbeam_channel::Receiver<OrchestratorToPlanet>,
     planet_to_orch: crossbeam_channel::Sender<PlanetToOrchestrator>,
     expl_to_planet: crossbeam_channel::Receiver<ExplorerToPlanet>,
-) -> Result<Planet, String> {
-    match orch_to_planet.try_recv() {
-        Err(crossbeam_channel::TryRecvError::Disconnected) => {
-            error!("OrchestratorToPlanet channel is closed for planet {id}");
-            return Err("OrchestratorToPlanet Channel is closed".to_string());
-        }
-        _ => debug!("ExplorerToPlanet channel open for planet {id}"),
-    }
-    match expl_to_planet.try_recv() {
-        Err(crossbeam_channel::TryRecvError::Disconnected) => {
-            return Err("ExplorerToPlanet channel is closed".to_string());
-        }
-        _ => debug!("ExplorerToPlanet channel open for planet {id}"),
-    }
-    let planet = Planet::new(
-        id,
-        PlanetType::A,
-        Box::new(AI::new()),
-        // gen rule
-        vec![BasicResourceType::Oxygen],
-        vec![],
-        (orch_to_planet, planet_to_orch),
-        expl_to_planet,
-    )?;
+) -> Result<Trip, TripError> {
+    TripBuilder::new(id)
+        .channels(orch_to_planet, planet_to_orch, expl_to_planet)
+        .build()
+}
 
-    info!("planet_id={id} initialized");
-    Ok(planet)
+/// Constructs and returns a fully initialized [`Trip`] driven by `ai`.
+///
+/// Behaves exactly like [`trip`], but lets the caller inject its own
+/// [`PlanetAI`], e.g. a test double or an alternatively configured AI.
+/// [`Trip::metrics`] cannot see inside a custom AI and reports zeros.
+///
+/// # Errors
+///
+/// - [`TripError::OrchestratorChannelClosed`] or
+///   [`TripError::ExplorerChannelClosed`] if a channel is already closed.
+/// - [`TripError::PlanetInit`] if [`Planet::new`] fails due to invalid
+///   parameters.
+pub fn trip_with_ai(
+    id: u32,
+    ai: Box<dyn PlanetAI>,
+    orch_to_planet: crossbeam_channel::Receiver<OrchestratorToPlanet>,
+    planet_to_orch: crossbeam_channel::Sender<PlanetToOrchestrator>,
+    expl_to_planet: crossbeam_channel::Receiver<ExplorerToPlanet>,
+) -> Result<Trip, TripError> {
+    TripBuilder::new(id)
+        .ai(ai)
+        .channels(orch_to_planet, planet_to_orch, expl_to_planet)
+        .build()
+}
+
+/// Constructs and returns a fully initialized [`Trip`] of the given
+/// [`PlanetType`].
+///
+/// Behaves exactly like [`trip`], but forwards `planet_type` to
+/// [`Planet::new`] instead of using [`PlanetType::A`]. The planet generates
+/// the resource given by [`default_rules_for`] its type and has no combination rules; use
+/// [`trip_with_rules`] to change them.
+///
+/// # Parameters
+///
+/// - `id`: The planet's unique identifier within the galaxy.
+/// - `planet_type`: The type of planet to build.
+/// - `orch_to_planet`: Receiver for orchestrator-to-planet messages.
+/// - `planet_to_orch`: Sender for planet-to-orchestrator messages.
+/// - `expl_to_planet`: Receiver for explorer-to-planet messages.
+///
+/// # Errors
+///
+/// - [`TripError::OrchestratorChannelClosed`] or
+///   [`TripError::ExplorerChannelClosed`] if a channel is already closed.
+/// - [`TripError::PlanetInit`] if [`Planet::new`] fails due to invalid
+///   parameters.
+pub fn trip_with_type(
+    id: u32,
+    planet_type: PlanetType,
+    orch_to_planet: crossbeam_channel::Receiver<OrchestratorToPlanet>,
+    planet_to_orch: crossbeam_channel::Sender<PlanetToOrchestrator>,
+    expl_to_planet: crossbeam_channel::Receiver<ExplorerToPlanet>,
+) -> Result<Trip, TripError> {
+    TripBuilder::new(id)
+        .planet_type(planet_type)
+        .channels(orch_to_planet, planet_to_orch, expl_to_planet)
+        .build()
+}
+
+/// Constructs and returns a fully initialized [`Trip`] with custom
+/// generation and combination rules.
+///
+/// The AI answers `SupportedResourceRequest`, `GenerateResourceRequest`,
+/// `SupportedCombinationRequest` and `CombineResourceRequest` according to
+/// the rules configured here.
+///
+/// # Parameters
+///
+/// - `id`: The planet's unique identifier within the galaxy.
+/// - `planet_type`: The type of planet to build.
+/// - `gen_rules`: The basic resources the planet can generate.
+/// - `comb_rules`: The complex resources the planet can combine.
+/// - `orch_to_planet`: Receiver for orchestrator-to-planet messages.
+/// - `planet_to_orch`: Sender for planet-to-orchestrator messages.
+/// - `expl_to_planet`: Receiver for explorer-to-planet messages.
+///
+/// # Errors
+///
+/// - [`TripError::OrchestratorChannelClosed`] or
+///   [`TripError::ExplorerChannelClosed`] if a channel is already closed.
+/// - [`TripError::PlanetInit`] if [`Planet::new`] rejects the rules for the
+///   given `planet_type`.
+pub fn trip_with_rules(
+    id: u32,
+    planet_type: PlanetType,
+    gen_rules: Vec<BasicResourceType>,
+    comb_rules: Vec<ComplexResourceType>,
+    orch_to_planet: crossbeam_channel::Receiver<OrchestratorToPlanet>,
+    planet_to_orch: crossbeam_channel::Sender<PlanetToOrchestrator>,
+    expl_to_planet: crossbeam_channel::Receiver<ExplorerToPlanet>,
+) -> Result<Trip, TripError> {
+    TripBuilder::new(id)
+        .planet_type(planet_type)
+        .generation_rules(gen_rules)
+        .combination_rules(comb_rules)
+        .channels(orch_to_planet, planet_to_orch, expl_to_planet)
+        .build()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::collections::HashSet;
     use std::sync::Once;
 
     static INIT: Once = Once::new();
@@ -95,6 +1057,17 @@ mod tests {
         assert!(trip.is_ok());
     }
 
+    #[test]
+    fn test_planet_id_round_trips() {
+        setup_logger();
+        let (_orch_tx, orch_rx) = crossbeam_channel::unbounded();
+        let (planet_tx, _planet_rx) = crossbeam_channel::unbounded();
+        let (_expl_tx, expl_rx) = crossbeam_channel::unbounded();
+
+        let trip = trip(42, orch_rx, planet_tx, expl_rx).expect("Planet must be created");
+        assert_eq!(trip.planet_id(), 42);
+    }
+
     #[test]
     fn test_planet_new_with_closed_channels() {
         setup_logger();
@@ -109,4 +1082,203 @@ mod tests {
         let result = trip(1, orch_rx, planet_tx, expl_rx);
         assert!(result.is_err());
     }
+
+    #[test]
+    fn test_closed_channel_errors_name_the_channel() {
+        setup_logger();
+        let (orch_tx, orch_rx) = crossbeam_channel::unbounded();
+        let (planet_tx, _planet_rx) = crossbeam_channel::unbounded();
+        let (_expl_tx, expl_rx) = crossbeam_channel::unbounded::<ExplorerToPlanet>();
+        drop(orch_tx);
+        assert!(matches!(
+            trip(1, orch_rx, planet_tx.clone(), expl_rx),
+            Err(TripError::OrchestratorChannelClosed)
+        ));
+
+        let (_orch_tx, orch_rx) = crossbeam_channel::unbounded();
+        let (expl_tx, expl_rx) = crossbeam_channel::unbounded::<ExplorerToPlanet>();
+        drop(expl_tx);
+        assert!(matches!(
+            trip(1, orch_rx, planet_tx.clone(), expl_rx),
+            Err(TripError::ExplorerChannelClosed)
+        ));
+
+        let (_orch_tx, orch_rx) = crossbeam_channel::unbounded();
+        let (_expl_tx, expl_rx) = crossbeam_channel::unbounded();
+        let result = trip_with_rules(
+            1,
+            PlanetType::A,
+            vec![],
+            vec![],
+            orch_rx,
+            planet_tx,
+            expl_rx,
+        );
+        assert!(matches!(result, Err(TripError::PlanetInit { id: 1, .. })));
+    }
+
+    #[test]
+    fn test_planet_init_error_names_the_planet() {
+        setup_logger();
+        let (planet_tx, _planet_rx) = crossbeam_channel::unbounded();
+        let (_orch_tx, orch_rx) = crossbeam_channel::unbounded();
+        let (_expl_tx, expl_rx) = crossbeam_channel::unbounded();
+        let Err(e) = trip_with_rules(
+            42,
+            PlanetType::B,
+            vec![],
+            vec![],
+            orch_rx,
+            planet_tx,
+            expl_rx,
+        ) else {
+            panic!("planet without generation rules was created");
+        };
+
+        let TripError::PlanetInit { id, source } = &e else {
+            panic!("unexpected error {e:?}");
+        };
+        assert_eq!(*id, 42);
+        assert!(!source.is_empty());
+        assert!(e.to_string().starts_with("planet_id=42 "), "{e}");
+    }
+
+    #[test]
+    fn test_default_rules_for_each_type() {
+        setup_logger();
+        for (planet_type, expected) in [
+            (PlanetType::A, BasicResourceType::Oxygen),
+            (PlanetType::B, BasicResourceType::Hydrogen),
+            (PlanetType::C, BasicResourceType::Carbon),
+            (PlanetType::D, BasicResourceType::Silicon),
+        ] {
+            assert_eq!(default_rules_for(planet_type), vec![expected]);
+
+            let (_orch_tx, orch_rx) = crossbeam_channel::unbounded();
+            let (planet_tx, _planet_rx) = crossbeam_channel::unbounded();
+            let (_expl_tx, expl_rx) = crossbeam_channel::unbounded();
+            let trip = trip_with_type(0, planet_type, orch_rx, planet_tx, expl_rx)
+                .expect("Default rules must be valid for every type");
+            assert_eq!(
+                trip.planet.generator().all_available_recipes(),
+                HashSet::from([expected])
+            );
+        }
+    }
+
+    #[test]
+    fn test_energy_cell_capacity_per_type() {
+        setup_logger();
+        for (planet_type, capacity) in [
+            (PlanetType::A, 5),
+            (PlanetType::B, 1),
+            (PlanetType::C, 1),
+            (PlanetType::D, 5),
+        ] {
+            let (_orch_tx, orch_rx) = crossbeam_channel::unbounded();
+            let (planet_tx, _planet_rx) = crossbeam_channel::unbounded();
+            let (_expl_tx, expl_rx) = crossbeam_channel::unbounded();
+            let trip = trip_with_type(0, planet_type, orch_rx, planet_tx, expl_rx)
+                .expect("Planet must be created");
+            assert_eq!(trip.energy_cell_capacity(), capacity);
+        }
+    }
+
+    #[test]
+    fn test_planet_creation_with_type() {
+        setup_logger();
+        for (id, planet_type) in [(2, PlanetType::A), (3, PlanetType::C)] {
+            let (_orch_tx, orch_rx) = crossbeam_channel::unbounded();
+            let (planet_tx, _planet_rx) = crossbeam_channel::unbounded();
+            let (_expl_tx, expl_rx) = crossbeam_channel::unbounded();
+
+            let trip = trip_with_type(id, planet_type, orch_rx, planet_tx, expl_rx)
+                .expect("Planet must be created");
+            assert_eq!(trip.planet.id(), id);
+            assert!(matches!(
+                (trip.planet.planet_type(), planet_type),
+                (PlanetType::A, PlanetType::A) | (PlanetType::C, PlanetType::C)
+            ));
+        }
+    }
+
+    #[test]
+    fn test_planet_type_accessor() {
+        setup_logger();
+        let build = |planet_type| {
+            let (_orch_tx, orch_rx) = crossbeam_channel::unbounded();
+            let (planet_tx, _planet_rx) = crossbeam_channel::unbounded();
+            let (_expl_tx, expl_rx) = crossbeam_channel::unbounded();
+            trip_with_type(0, planet_type, orch_rx, planet_tx, expl_rx)
+                .expect("Planet must be created")
+        };
+        let (b, d) = (build(PlanetType::B), build(PlanetType::D));
+        assert!(matches!(b.planet_type(), PlanetType::B));
+        assert!(matches!(d.planet_type(), PlanetType::D));
+    }
+
+    #[test]
+    fn test_flush_drops_replies_a_full_orchestrator_has_no_room_for() {
+        setup_logger();
+        let (to_orchestrator, orch_rx) = crossbeam_channel::bounded(1);
+        let (planet_tx, from_planet) = crossbeam_channel::unbounded();
+        let link = Link {
+            to_orchestrator,
+            from_planet,
+            ..Link::detached(0)
+        };
+        for _ in 0..3 {
+            planet_tx
+                .send(PlanetToOrchestrator::StopPlanetAIResult { planet_id: 0 })
+                .unwrap();
+        }
+
+        // Without an outbox, a blocking send would never return here.
+        link.flush(&HashMap::new());
+
+        assert_eq!(orch_rx.len(), 1);
+        assert_eq!(link.replies_dropped.get(), 2);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_metrics_serialize_to_json() {
+        let metrics = TripMetrics {
+            sunrays: 3,
+            rockets_built: 1,
+            stockpiled_rockets: 1,
+            has_rocket: true,
+            charged_cells: 2,
+            ..TripMetrics::default()
+        };
+        let json = serde_json::to_string(&metrics).expect("Metrics must serialize");
+        for field in [
+            "\"sunrays\":3",
+            "\"rockets_built\":1",
+            "\"rockets_launched\":0",
+            "\"resources_generated\":0",
+            "\"explorers_served\":0",
+            "\"rocket_build_failures\":0",
+            "\"stockpiled_rockets\":1",
+            "\"has_rocket\":true",
+            "\"charged_cells\":2",
+            "\"dropped_while_stopped\":{\"sunrays\":0",
+        ] {
+            assert!(json.contains(field), "{field} missing from {json}");
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_audit_event_names_the_resource() {
+        let event = AuditEvent::ResourceGenerated {
+            planet_id: 1,
+            explorer_id: 2,
+            resource: BasicResourceType::Oxygen,
+        };
+        assert_eq!(
+            serde_json::to_string(&event).expect("Audit events must serialize"),
+            r#"{"ResourceGenerated":{"planet_id":1,"explorer_id":2,"resource":"oxygen"}}"#
+        );
+    }
 }
@@ -0,0 +1,1168 @@
+//! Message relay between a [`Trip`](crate::Trip) and its wrapped planet.
+//!
+//! The `common_game` planet owns its loop and talks to the channels it was
+//! built with. `Trip` hands it internal channels instead and relays every
+//! message from and to the caller's channels, which lets it stop the planet
+//! gracefully, observe its replies and shield it from slow explorers.
+//!
+//! When several channels are ready at once, the relay picks in a fixed
+//! order rather than at random, see [`Link::next_event`]: the orchestrator
+//! first, so an asteroid is never held up by explorer chatter, and explorer
+//! requests last. The orchestrator's own messages stay in the order they
+//! were sent, control messages and game events alike, since replies are
+//! matched to requests by that order.
+
+use common_game::components::sunray::Sunray;
+use common_game::protocols::orchestrator_planet::{OrchestratorToPlanet, PlanetToOrchestrator};
+use common_game::protocols::planet_explorer::{ExplorerToPlanet, PlanetToExplorer};
+use crossbeam_channel::{
+    Receiver, RecvError, Select, SendError, SendTimeoutError, Sender, TrySendError,
+};
+use log::{debug, error, warn};
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use crate::{DefenseEvent, DroppedWhileStopped};
+
+/// The caller's ends of the channels, as handed to the constructors.
+pub(crate) struct Link {
+    pub(crate) from_orchestrator: Receiver<OrchestratorToPlanet>,
+    pub(crate) to_orchestrator: Sender<PlanetToOrchestrator>,
+    pub(crate) from_explorers: Receiver<ExplorerToPlanet>,
+    /// Messages the wrapped planet sends to the orchestrator.
+    pub(crate) from_planet: Receiver<PlanetToOrchestrator>,
+    pub(crate) defense_events: Option<Sender<DefenseEvent>>,
+    /// Id of the wrapped planet, for logging.
+    pub(crate) planet_id: u32,
+    /// Time without a message of the orchestrator or the explorers after
+    /// which a warning is logged, if enabled.
+    pub(crate) idle_warning: Option<Duration>,
+    /// Interval between two housekeeping rounds, if enabled.
+    pub(crate) housekeeping: Option<Duration>,
+    /// Tells our AI a housekeeping round is due, see
+    /// [`AiHandle::housekeeping`](crate::ai::AiHandle::housekeeping).
+    pub(crate) housekeeping_due: Arc<AtomicBool>,
+    /// Explorer requests still handed to the planet when a stop arrives.
+    pub(crate) stop_drain: usize,
+    /// Requests of a single explorer waiting for the planet beyond which
+    /// new ones are rejected, if bounded.
+    pub(crate) explorer_queue_cap: Option<usize>,
+    /// Explorer requests rejected for exceeding [`Link::explorer_queue_cap`],
+    /// over the planet's lifetime.
+    pub(crate) rejected: Arc<AtomicU64>,
+    /// Whether a sunray answered with `Stopped` is acknowledged instead.
+    pub(crate) ack_stopped_sunrays: bool,
+    /// Sunrays answered with `Stopped` kept for replay once the planet
+    /// starts again, see
+    /// [`AiConfig::sunray_buffer_capacity`](crate::AiConfig::sunray_buffer_capacity).
+    pub(crate) sunray_buffer_capacity: usize,
+    /// Sunrays answered with `Stopped` waiting for the next start. A sunray
+    /// carries nothing, so counting them is enough to replay them.
+    pub(crate) buffered_sunrays: Cell<usize>,
+    /// Time explorer requests wait after the planet starts, see
+    /// [`AiConfig::start_grace`](crate::AiConfig::start_grace).
+    pub(crate) start_grace: Duration,
+    /// Whether the planet acknowledged a start and no stop or kill since.
+    pub(crate) running: Arc<AtomicBool>,
+    /// Shutdown requests from [`TripHandle::shutdown`](crate::TripHandle::shutdown).
+    pub(crate) shutdown_rx: Receiver<()>,
+    /// Kept so `shutdown_rx` never disconnects, handed out to handles.
+    pub(crate) shutdown_tx: Sender<()>,
+    /// Whether [`TripHandle::pause`](crate::TripHandle::pause) asked for the
+    /// AI to be kept stopped.
+    pub(crate) paused: Arc<AtomicBool>,
+    /// Wakes the relay loop up when [`Link::paused`] changes.
+    pub(crate) pause_rx: Receiver<()>,
+    /// Kept so `pause_rx` never disconnects, handed out to handles.
+    pub(crate) pause_tx: Sender<()>,
+    /// Messages answered with `Stopped`, by kind.
+    pub(crate) dropped: Arc<Mutex<DroppedWhileStopped>>,
+    /// Message taken from the orchestrator while checking its channel,
+    /// relayed before any other.
+    pub(crate) held_orchestrator: Cell<Option<OrchestratorToPlanet>>,
+    /// Message taken from the explorers while checking their channel,
+    /// relayed right after [`Link::held_orchestrator`].
+    pub(crate) held_explorer: Cell<Option<ExplorerToPlanet>>,
+    /// Messages of the orchestrator and the explorers handed to the planet
+    /// since the current run started.
+    pub(crate) relayed: Cell<u64>,
+    /// Panics of the AI the planet recovered from, over all runs.
+    pub(crate) panics: Arc<AtomicU64>,
+    /// Capacity of [`Link::backlog`], if replies wait there instead of
+    /// blocking the relay on a full orchestrator channel.
+    pub(crate) outbox_capacity: Option<usize>,
+    /// Replies waiting for room in the orchestrator's channel, oldest first.
+    pub(crate) backlog: RefCell<VecDeque<PlanetToOrchestrator>>,
+    /// Replies to the orchestrator dropped since the current run started.
+    pub(crate) replies_dropped: Cell<u64>,
+    /// Resets requested through [`TripHandle::reset`](crate::TripHandle::reset),
+    /// over the planet's lifetime.
+    pub(crate) resets: Arc<AtomicU64>,
+    /// Value of [`Link::resets`] when the explorers were last let go of.
+    pub(crate) pruned_resets: Cell<u64>,
+}
+
+/// Sending halves of the channels the wrapped planet listens on.
+///
+/// Dropping it disconnects the planet from the orchestrator, which makes
+/// the planet's loop return once the already relayed messages are handled.
+pub(crate) struct Relay {
+    pub(crate) to_planet: Sender<OrchestratorToPlanet>,
+    pub(crate) to_planet_expl: Sender<ExplorerToPlanet>,
+}
+
+/// Replies of the planet to one explorer, and where to deliver them.
+pub(crate) struct Outbox {
+    from_planet: Receiver<PlanetToExplorer>,
+    to_explorer: Sender<PlanetToExplorer>,
+    /// Whether a delivery found the explorer's receiver dropped.
+    disconnected: bool,
+}
+
+impl Outbox {
+    /// Returns whether a delivery found the explorer's receiver dropped.
+    pub(crate) fn is_disconnected(&self) -> bool {
+        self.disconnected
+    }
+}
+
+/// Why the relay loop stopped forwarding messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Shutdown {
+    /// The planet's loop returned on its own, e.g. after a kill request.
+    PlanetExited,
+    /// The orchestrator dropped its sender or its receiver.
+    Disconnected,
+    /// The deadline given to [`Trip::run_until`](crate::Trip::run_until)
+    /// passed.
+    DeadlineReached,
+    /// [`TripHandle::shutdown`](crate::TripHandle::shutdown) was called.
+    Requested,
+}
+
+/// Kind of an orchestrator request, used to tell which request a reply of
+/// the planet answers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Request {
+    Sunray,
+    Asteroid,
+    Start,
+    Stop,
+    Kill,
+    InternalState,
+    IncomingExplorer,
+    OutgoingExplorer,
+    /// A stop sent by the relay to pause the planet, whose reply is not
+    /// meant for the orchestrator.
+    Pause,
+    /// A start sent by the relay to resume a paused planet, whose reply is
+    /// not meant for the orchestrator.
+    Resume,
+    /// A sunray the planet answered with `Stopped`, sent again by the relay
+    /// once it started; the orchestrator already got its reply.
+    Replay,
+    /// An internal state request sent by the relay to wake the planet up
+    /// for housekeeping, whose reply is not meant for the orchestrator.
+    Housekeeping,
+}
+
+impl Request {
+    fn of(msg: &OrchestratorToPlanet) -> Self {
+        match msg {
+            OrchestratorToPlanet::Sunray(_) => Self::Sunray,
+            OrchestratorToPlanet::Asteroid(_) => Self::Asteroid,
+            OrchestratorToPlanet::StartPlanetAI => Self::Start,
+            OrchestratorToPlanet::StopPlanetAI => Self::Stop,
+            OrchestratorToPlanet::KillPlanet => Self::Kill,
+            OrchestratorToPlanet::InternalStateRequest => Self::InternalState,
+            OrchestratorToPlanet::IncomingExplorerRequest { .. } => Self::IncomingExplorer,
+            OrchestratorToPlanet::OutgoingExplorerRequest { .. } => Self::OutgoingExplorer,
+        }
+    }
+
+    /// Returns the kind of request `reply` answers, or `None` for a
+    /// `Stopped` reply, which answers any request.
+    fn answered_by(reply: &PlanetToOrchestrator) -> Option<Self> {
+        match reply {
+            PlanetToOrchestrator::SunrayAck { .. } => Some(Self::Sunray),
+            PlanetToOrchestrator::AsteroidAck { .. } => Some(Self::Asteroid),
+            PlanetToOrchestrator::StartPlanetAIResult { .. } => Some(Self::Start),
+            PlanetToOrchestrator::StopPlanetAIResult { .. } => Some(Self::Stop),
+            PlanetToOrchestrator::KillPlanetResult { .. } => Some(Self::Kill),
+            PlanetToOrchestrator::InternalStateResponse { .. } => Some(Self::InternalState),
+            PlanetToOrchestrator::IncomingExplorerResponse { .. } => Some(Self::IncomingExplorer),
+            PlanetToOrchestrator::OutgoingExplorerResponse { .. } => Some(Self::OutgoingExplorer),
+            PlanetToOrchestrator::Stopped { .. } => None,
+        }
+    }
+
+    /// Returns the kind of request the planet takes this one for.
+    fn as_seen_by_planet(self) -> Self {
+        match self {
+            Self::Pause => Self::Stop,
+            Self::Resume => Self::Start,
+            Self::Replay => Self::Sunray,
+            Self::Housekeeping => Self::InternalState,
+            other => other,
+        }
+    }
+}
+
+/// Bookkeeping of one call to [`Link::forward`].
+#[derive(Default)]
+struct Flow {
+    /// Requests relayed to the planet and not answered yet, oldest first.
+    pending: VecDeque<Request>,
+    /// Explorers the relay asked the planet to let go of, whose departure
+    /// is not meant for the orchestrator.
+    pruned: HashSet<u32>,
+    /// Set while a start grace period runs: explorer requests then wait in
+    /// their channel until it fires.
+    grace: Option<Receiver<Instant>>,
+    /// Whether the planet was stopped by a pause rather than by the
+    /// orchestrator, so only such a stop is undone by a resume.
+    paused_by_us: bool,
+    /// When the relay warns about being idle, unless a message arrives
+    /// first; `None` once it warned, until the next message.
+    idle_deadline: Option<Instant>,
+    /// Ids of the explorers whose requests were handed to the planet, in
+    /// order; the planet has yet to pick up the last ones.
+    queued: VecDeque<u32>,
+    /// Number of entries of [`Flow::queued`] by explorer.
+    queued_by: HashMap<u32, usize>,
+    /// Set while a `StopPlanetAI` is held back for the planet to pick up
+    /// the explorer requests ahead of it, see [`Link::drain_explorers`]; it
+    /// fires when the stop is relayed regardless.
+    held_stop: Option<Receiver<Instant>>,
+}
+
+impl Flow {
+    /// Forgets the requests the planet picked up, given how many are still
+    /// waiting in its channel.
+    ///
+    /// The planet reads its channel in order, so those are the oldest ones.
+    fn trim_queued(&mut self, waiting: usize) {
+        while self.queued.len() > waiting {
+            let Some(explorer_id) = self.queued.pop_front() else {
+                break;
+            };
+            if let Some(count) = self.queued_by.get_mut(&explorer_id) {
+                *count -= 1;
+                if *count == 0 {
+                    self.queued_by.remove(&explorer_id);
+                }
+            }
+        }
+    }
+}
+
+/// A message picked up by the relay loop.
+enum Event {
+    Orchestrator(Result<OrchestratorToPlanet, RecvError>),
+    Explorer(Result<ExplorerToPlanet, RecvError>),
+    Planet(Result<PlanetToOrchestrator, RecvError>),
+    ToExplorer(u32, Result<PlanetToExplorer, RecvError>),
+    PlanetExited,
+    DeadlineReached,
+    ShutdownRequested,
+    Housekeeping,
+    /// The start grace period passed, explorer requests are relayed again.
+    GraceElapsed,
+    /// [`Link::paused`] may have changed.
+    PauseToggled,
+    /// A held stop waited [`Link::STOP_DRAIN_TIMEOUT`] for the planet.
+    DrainElapsed,
+    /// No message arrived for [`Link::idle_warning`].
+    Idle,
+    /// The oldest reply of the backlog was handed to the orchestrator.
+    Flushed(Result<(), SendError<PlanetToOrchestrator>>),
+}
+
+impl Link {
+    /// Returns a link for a planet talking to the caller's channels
+    /// itself, see [`Trip::from_planet`](crate::Trip::from_planet).
+    ///
+    /// Its channels are placeholders nobody sends on: without a [`Relay`],
+    /// the link is never used to forward messages.
+    pub(crate) fn detached(planet_id: u32) -> Self {
+        let (shutdown_tx, shutdown_rx) = crossbeam_channel::bounded(1);
+        let (pause_tx, pause_rx) = crossbeam_channel::bounded(1);
+        Self {
+            from_orchestrator: crossbeam_channel::never(),
+            to_orchestrator: crossbeam_channel::unbounded().0,
+            from_explorers: crossbeam_channel::never(),
+            from_planet: crossbeam_channel::never(),
+            defense_events: None,
+            planet_id,
+            idle_warning: None,
+            housekeeping: None,
+            housekeeping_due: Arc::default(),
+            stop_drain: 0,
+            explorer_queue_cap: None,
+            rejected: Arc::default(),
+            ack_stopped_sunrays: false,
+            sunray_buffer_capacity: 0,
+            buffered_sunrays: Cell::new(0),
+            start_grace: Duration::ZERO,
+            running: Arc::default(),
+            shutdown_rx,
+            shutdown_tx,
+            paused: Arc::default(),
+            pause_rx,
+            pause_tx,
+            dropped: Arc::default(),
+            held_orchestrator: Cell::new(None),
+            held_explorer: Cell::new(None),
+            relayed: Cell::new(0),
+            panics: Arc::default(),
+            outbox_capacity: None,
+            backlog: RefCell::default(),
+            replies_dropped: Cell::new(0),
+            resets: Arc::default(),
+            pruned_resets: Cell::new(0),
+        }
+    }
+
+    /// Bound on a single attempt to deliver a reply to an explorer.
+    const EXPLORER_SEND_TIMEOUT: Duration = Duration::from_millis(10);
+
+    /// Attempts made to deliver a reply to an explorer before dropping it.
+    const EXPLORER_SEND_ATTEMPTS: u32 = 3;
+
+    /// Bound on the time a stop is held back while the planet works through
+    /// the drained explorer requests.
+    const STOP_DRAIN_TIMEOUT: Duration = Duration::from_millis(100);
+
+    /// Relays messages between the caller's channels and the planet until
+    /// one of the shutdown conditions is met.
+    ///
+    /// Explorers registered along the way are tracked in `outboxes`.
+    pub(crate) fn forward(
+        &self,
+        relay: &Relay,
+        outboxes: &mut HashMap<u32, Outbox>,
+        done: &Receiver<()>,
+        deadline: Option<Instant>,
+    ) -> Shutdown {
+        let timeout = deadline.map_or_else(crossbeam_channel::never, crossbeam_channel::at);
+        let ticker = self
+            .housekeeping
+            .map_or_else(crossbeam_channel::never, crossbeam_channel::tick);
+        // Explorers may come and go: losing their channel is not a reason to
+        // stop, so it is swapped for one that never delivers.
+        let mut from_explorers = self.from_explorers.clone();
+        let mut flow = Flow {
+            idle_deadline: self.idle_deadline(),
+            ..Flow::default()
+        };
+        loop {
+            if flow.held_stop.is_some()
+                && relay.to_planet_expl.is_empty()
+                && !self.release_stop(relay, outboxes, &mut flow)
+            {
+                return Shutdown::PlanetExited;
+            }
+            let event = self.next_event(&from_explorers, outboxes, &flow, done, &timeout, &ticker);
+            if matches!(event, Event::Orchestrator(Ok(_)) | Event::Explorer(Ok(_))) {
+                flow.idle_deadline = self.idle_deadline();
+            }
+            match event {
+                Event::Orchestrator(Ok(msg)) => {
+                    if !self.on_orchestrator(relay, outboxes, &mut flow, &from_explorers, msg) {
+                        return Shutdown::PlanetExited;
+                    }
+                }
+                Event::Orchestrator(Err(_)) => return Shutdown::Disconnected,
+                Event::Explorer(Ok(msg)) => {
+                    if Self::wastes_energy(&msg, outboxes) || !self.admit(relay, &mut flow, &msg) {
+                        continue;
+                    }
+                    if !Self::to_planet(relay, &mut flow, msg) {
+                        return Shutdown::PlanetExited;
+                    }
+                    self.count_relayed();
+                }
+                Event::Explorer(Err(_)) => from_explorers = crossbeam_channel::never(),
+                Event::Planet(Ok(msg)) => {
+                    if let Some(shutdown) = self.on_reply(relay, &mut flow, msg) {
+                        return shutdown;
+                    }
+                }
+                Event::Planet(Err(_)) | Event::PlanetExited => return Shutdown::PlanetExited,
+                Event::ToExplorer(explorer_id, Ok(msg)) => {
+                    if matches!(msg, PlanetToExplorer::Stopped) {
+                        self.count_dropped(|dropped| dropped.explorer_requests += 1);
+                    }
+                    let outbox = outboxes
+                        .get_mut(&explorer_id)
+                        .expect("replies only come from registered outboxes");
+                    if !Self::deliver_to_explorer(explorer_id, &outbox.to_explorer, msg) {
+                        outbox.disconnected = true;
+                        self.prune_now(relay, outboxes, &mut flow);
+                    }
+                }
+                Event::ToExplorer(explorer_id, Err(_)) => {
+                    // The planet forgot the explorer, so will we.
+                    outboxes.remove(&explorer_id);
+                }
+                Event::Flushed(result) => {
+                    if let Err(e) = result {
+                        error!("orchestrator unreachable, {:?} not delivered", e.0);
+                        return Shutdown::Disconnected;
+                    }
+                }
+                Event::PauseToggled => {
+                    if !self.apply_pause(relay, &mut flow) {
+                        return Shutdown::PlanetExited;
+                    }
+                }
+                Event::Idle => {
+                    warn!(
+                        "planet_id={} no message for {:?}, is the orchestrator alive?",
+                        self.planet_id,
+                        self.idle_warning.unwrap_or_default()
+                    );
+                    flow.idle_deadline = None;
+                }
+                Event::GraceElapsed => {
+                    debug!("start grace elapsed, relaying explorer requests");
+                    flow.grace = None;
+                }
+                Event::DrainElapsed => {
+                    if !self.release_stop(relay, outboxes, &mut flow) {
+                        return Shutdown::PlanetExited;
+                    }
+                }
+                Event::DeadlineReached => return Shutdown::DeadlineReached,
+                Event::ShutdownRequested => return Shutdown::Requested,
+                Event::Housekeeping => {
+                    if !self.request_housekeeping(relay, &mut flow) {
+                        return Shutdown::PlanetExited;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns whether `msg` would have the planet spend a charged cell on
+    /// an explorer already known to be gone, in which case it is not
+    /// relayed and gets no answer.
+    ///
+    /// A `Sender` cannot tell whether its receiver is still there, so an
+    /// explorer is only known to be gone once a reply to it could not be
+    /// delivered; a request racing the explorer's disconnection still
+    /// spends its cell.
+    fn wastes_energy(msg: &ExplorerToPlanet, outboxes: &HashMap<u32, Outbox>) -> bool {
+        let ExplorerToPlanet::GenerateResourceRequest {
+            explorer_id,
+            resource,
+        } = msg
+        else {
+            return false;
+        };
+        let gone = outboxes
+            .get(explorer_id)
+            .is_some_and(Outbox::is_disconnected);
+        if gone {
+            warn!("explorer_id={explorer_id} disconnected, {resource:?} not generated");
+        }
+        gone
+    }
+
+    /// Returns whether the explorer sending `msg` has room left under
+    /// [`Link::explorer_queue_cap`]; if not, the request is counted in
+    /// [`Link::rejected`] and gets no answer.
+    fn admit(&self, relay: &Relay, flow: &mut Flow, msg: &ExplorerToPlanet) -> bool {
+        let Some(cap) = self.explorer_queue_cap else {
+            return true;
+        };
+        flow.trim_queued(relay.to_planet_expl.len());
+        let explorer_id = msg.explorer_id();
+        let queued = flow.queued_by.get(&explorer_id).copied().unwrap_or(0);
+        if queued < cap {
+            return true;
+        }
+        warn!("explorer_id={explorer_id} has {queued} requests queued, {msg:?} rejected");
+        self.rejected.fetch_add(1, Ordering::Relaxed);
+        false
+    }
+
+    /// Hands an explorer request to the planet, keeping track of its sender
+    /// in [`Flow::queued`].
+    ///
+    /// # Returns
+    /// `false` if the planet already exited.
+    fn to_planet(relay: &Relay, flow: &mut Flow, msg: ExplorerToPlanet) -> bool {
+        let explorer_id = msg.explorer_id();
+        if relay.to_planet_expl.send(msg).is_err() {
+            return false;
+        }
+        flow.queued.push_back(explorer_id);
+        *flow.queued_by.entry(explorer_id).or_default() += 1;
+        true
+    }
+
+    /// Hands a message of the orchestrator to the planet, along with the
+    /// bookkeeping a start or a stop needs.
+    ///
+    /// # Returns
+    /// `false` if the planet already exited.
+    fn on_orchestrator(
+        &self,
+        relay: &Relay,
+        outboxes: &mut HashMap<u32, Outbox>,
+        flow: &mut Flow,
+        from_explorers: &Receiver<ExplorerToPlanet>,
+        msg: OrchestratorToPlanet,
+    ) -> bool {
+        if matches!(msg, OrchestratorToPlanet::StopPlanetAI) {
+            flow.paused_by_us = false;
+            if !self.drain_explorers(relay, flow, from_explorers) {
+                return false;
+            }
+            if flow.held_stop.is_some() {
+                return true;
+            }
+        }
+        self.relay_orchestrator(relay, outboxes, flow, msg)
+    }
+
+    /// Relays a stop held back by [`Link::drain_explorers`], once the planet
+    /// picked up the explorer requests ahead of it or the wait timed out.
+    ///
+    /// # Returns
+    /// `false` if the planet already exited.
+    fn release_stop(
+        &self,
+        relay: &Relay,
+        outboxes: &mut HashMap<u32, Outbox>,
+        flow: &mut Flow,
+    ) -> bool {
+        let waiting = relay.to_planet_expl.len();
+        if waiting > 0 {
+            warn!("{waiting} explorer requests still pending at stop");
+        }
+        flow.held_stop = None;
+        self.relay_orchestrator(relay, outboxes, flow, OrchestratorToPlanet::StopPlanetAI)
+    }
+
+    /// Hands a message of the orchestrator to the planet as is, registering
+    /// an arriving explorer and letting go of pruned ones on a start.
+    ///
+    /// # Returns
+    /// `false` if the planet already exited.
+    fn relay_orchestrator(
+        &self,
+        relay: &Relay,
+        outboxes: &mut HashMap<u32, Outbox>,
+        flow: &mut Flow,
+        msg: OrchestratorToPlanet,
+    ) -> bool {
+        let starts = matches!(msg, OrchestratorToPlanet::StartPlanetAI);
+        let msg = Self::register_explorer(msg, outboxes);
+        flow.pending.push_back(Request::of(&msg));
+        if relay.to_planet.send(msg).is_err() {
+            return false;
+        }
+        self.count_relayed();
+        if starts {
+            let resets = self.resets.load(Ordering::Acquire);
+            let reset = self.pruned_resets.replace(resets) != resets;
+            if reset {
+                self.buffered_sunrays.set(0);
+            }
+            Self::prune(relay, outboxes, &mut flow.pending, &mut flow.pruned, reset);
+        }
+        true
+    }
+
+    /// Stops a running planet if [`Link::paused`] is set, or starts it again
+    /// if it is cleared and the planet was stopped by a pause.
+    ///
+    /// # Returns
+    /// `false` if the planet already exited.
+    fn apply_pause(&self, relay: &Relay, flow: &mut Flow) -> bool {
+        let paused = self.paused.load(Ordering::Acquire);
+        let (msg, request) = if paused && !flow.paused_by_us && self.running.load(Ordering::Acquire)
+        {
+            (OrchestratorToPlanet::StopPlanetAI, Request::Pause)
+        } else if !paused && flow.paused_by_us {
+            (OrchestratorToPlanet::StartPlanetAI, Request::Resume)
+        } else {
+            return true;
+        };
+        debug!("{request:?} requested through the handle");
+        flow.paused_by_us = paused;
+        flow.pending.push_back(request);
+        relay.to_planet.send(msg).is_ok()
+    }
+
+    /// Handles a reply of the planet, delivering it to the orchestrator
+    /// unless it answers a request of the relay itself.
+    ///
+    /// # Returns
+    /// Why the relay loop has to stop, if it does.
+    fn on_reply(
+        &self,
+        relay: &Relay,
+        flow: &mut Flow,
+        msg: PlanetToOrchestrator,
+    ) -> Option<Shutdown> {
+        let answered = self.match_reply(&mut flow.pending, &msg);
+        self.update_grace(&mut flow.grace, &msg);
+        if matches!(answered, Some(Request::Pause | Request::Resume)) {
+            // A pause racing a stop of the orchestrator finds the planet
+            // stopped already: it is not ours to undo.
+            if matches!(msg, PlanetToOrchestrator::Stopped { .. }) {
+                flow.paused_by_us = false;
+            }
+            self.track_running(&msg);
+            if matches!(msg, PlanetToOrchestrator::StartPlanetAIResult { .. }) {
+                self.replay_sunrays(relay, flow);
+            }
+            return None;
+        }
+        if matches!(answered, Some(Request::Replay | Request::Housekeeping)) {
+            return None;
+        }
+        if matches!(msg, PlanetToOrchestrator::StartPlanetAIResult { .. }) {
+            if !self.apply_pause(relay, flow) {
+                return Some(Shutdown::PlanetExited);
+            }
+            if !flow.paused_by_us {
+                self.replay_sunrays(relay, flow);
+            }
+        }
+        if let PlanetToOrchestrator::OutgoingExplorerResponse { explorer_id, .. } = msg
+            && flow.pruned.remove(&explorer_id)
+        {
+            return None;
+        }
+        let msg = match msg {
+            PlanetToOrchestrator::Stopped { planet_id }
+                if self.ack_stopped_sunrays && answered == Some(Request::Sunray) =>
+            {
+                PlanetToOrchestrator::SunrayAck { planet_id }
+            }
+            msg => msg,
+        };
+        (!self.deliver(msg)).then_some(Shutdown::Disconnected)
+    }
+
+    /// Keeps a sunray the planet answered with `Stopped` for
+    /// [`Link::replay_sunrays`], unless the buffer is full.
+    fn buffer_sunray(&self) {
+        let buffered = self.buffered_sunrays.get();
+        if buffered < self.sunray_buffer_capacity {
+            self.buffered_sunrays.set(buffered + 1);
+            debug!(
+                "planet_id={} sunray buffered while stopped, pending={}",
+                self.planet_id,
+                buffered + 1
+            );
+        } else if self.sunray_buffer_capacity > 0 {
+            warn!(
+                "planet_id={} sunray buffer full, sunray lost",
+                self.planet_id
+            );
+        }
+    }
+
+    /// Sends the planet the sunrays it answered with `Stopped`, now that it
+    /// started again.
+    fn replay_sunrays(&self, relay: &Relay, flow: &mut Flow) {
+        let count = self.buffered_sunrays.take();
+        if count == 0 {
+            return;
+        }
+        debug!(
+            "planet_id={} replaying {count} sunrays received while stopped",
+            self.planet_id
+        );
+        for _ in 0..count {
+            flow.pending.push_back(Request::Replay);
+            if relay
+                .to_planet
+                .send(OrchestratorToPlanet::Sunray(Sunray::default()))
+                .is_err()
+            {
+                // The planet exited, which the relay loop notices on its own.
+                return;
+            }
+        }
+    }
+
+    /// Starts the start grace period when `reply` acknowledges a start, and
+    /// ends it when the planet stops.
+    fn update_grace(&self, grace: &mut Option<Receiver<Instant>>, reply: &PlanetToOrchestrator) {
+        match reply {
+            PlanetToOrchestrator::StartPlanetAIResult { .. } if !self.start_grace.is_zero() => {
+                debug!("explorer requests held for {:?}", self.start_grace);
+                *grace = Some(crossbeam_channel::after(self.start_grace));
+            }
+            PlanetToOrchestrator::StopPlanetAIResult { .. }
+            | PlanetToOrchestrator::KillPlanetResult { .. } => *grace = None,
+            _ => {}
+        }
+    }
+
+    /// Returns when to warn about being idle if no message arrives from now
+    /// on.
+    fn idle_deadline(&self) -> Option<Instant> {
+        self.idle_warning.map(|interval| Instant::now() + interval)
+    }
+
+    /// Counts a message of the orchestrator or an explorer handed to the
+    /// planet.
+    fn count_relayed(&self) {
+        self.relayed.set(self.relayed.get() + 1);
+    }
+
+    /// Blocks until one of the relayed channels is ready and receives from
+    /// it.
+    ///
+    /// When several are ready, the first of these wins:
+    /// 1. messages of the orchestrator;
+    /// 2. replies of the planet to the orchestrator, and the planet exiting;
+    /// 3. shutdown requests, the deadline, pause toggles, the end of the
+    ///    start grace period, a held stop timing out and housekeeping;
+    /// 4. replies waiting in the backlog, then replies to explorers, lowest
+    ///    explorer id first;
+    /// 5. explorer requests.
+    ///
+    /// Replies go before requests so they cannot pile up behind a busy
+    /// explorer. A flood of orchestrator messages holds explorer requests
+    /// back, as it does in the planet's own loop.
+    fn next_event(
+        &self,
+        from_explorers: &Receiver<ExplorerToPlanet>,
+        outboxes: &HashMap<u32, Outbox>,
+        flow: &Flow,
+        done: &Receiver<()>,
+        timeout: &Receiver<Instant>,
+        ticker: &Receiver<Instant>,
+    ) -> Event {
+        if let Some(msg) = self.held_orchestrator.take() {
+            return Event::Orchestrator(Ok(msg));
+        }
+        if let Some(msg) = self.held_explorer.take() {
+            return Event::Explorer(Ok(msg));
+        }
+        // During a grace period, explorer requests wait in their channel.
+        let paused = crossbeam_channel::never();
+        let no_grace = crossbeam_channel::never();
+        let (from_explorers, grace) = match &flow.grace {
+            Some(timer) => (&paused, timer),
+            None => (from_explorers, &no_grace),
+        };
+        // While a stop is held back, nothing may overtake it: messages of the
+        // orchestrator and the explorers, pause toggles and housekeeping
+        // wait for it to be relayed.
+        let held = crossbeam_channel::never();
+        let held_pause = crossbeam_channel::never();
+        let held_tick = crossbeam_channel::never();
+        let no_hold = crossbeam_channel::never();
+        let (from_orchestrator, from_explorers, pause_rx, ticker, released) = match &flow.held_stop
+        {
+            Some(timer) => (&held, &paused, &held_pause, &held_tick, timer),
+            None => (
+                &self.from_orchestrator,
+                from_explorers,
+                &self.pause_rx,
+                ticker,
+                &no_hold,
+            ),
+        };
+        // Operations are registered by priority.
+        let mut sel = Select::new_biased();
+        let orchestrator = sel.recv(from_orchestrator);
+        let planet = sel.recv(&self.from_planet);
+        let exited = sel.recv(done);
+        let requested = sel.recv(&self.shutdown_rx);
+        let expired = sel.recv(timeout);
+        let toggled = sel.recv(pause_rx);
+        let graced = sel.recv(grace);
+        let drained = sel.recv(released);
+        let tick = sel.recv(ticker);
+        let flush = (!self.backlog.borrow().is_empty()).then(|| sel.send(&self.to_orchestrator));
+        let mut by_id: Vec<(u32, &Outbox)> = outboxes.iter().map(|(&id, o)| (id, o)).collect();
+        by_id.sort_unstable_by_key(|&(id, _)| id);
+        let outgoing: Vec<(usize, u32, &Outbox)> = by_id
+            .into_iter()
+            .map(|(id, outbox)| (sel.recv(&outbox.from_planet), id, outbox))
+            .collect();
+        let explorers = sel.recv(from_explorers);
+
+        let op = match flow.idle_deadline {
+            Some(deadline) => match sel.select_deadline(deadline) {
+                Ok(op) => op,
+                Err(_) => return Event::Idle,
+            },
+            None => sel.select(),
+        };
+        match op.index() {
+            i if i == orchestrator => Event::Orchestrator(op.recv(from_orchestrator)),
+            i if i == explorers => Event::Explorer(op.recv(from_explorers)),
+            i if i == planet => Event::Planet(op.recv(&self.from_planet)),
+            i if i == exited => {
+                let _ = op.recv(done);
+                Event::PlanetExited
+            }
+            i if i == expired => {
+                let _ = op.recv(timeout);
+                Event::DeadlineReached
+            }
+            i if i == tick => {
+                let _ = op.recv(ticker);
+                Event::Housekeeping
+            }
+            i if i == graced => {
+                let _ = op.recv(grace);
+                Event::GraceElapsed
+            }
+            i if i == drained => {
+                let _ = op.recv(released);
+                Event::DrainElapsed
+            }
+            i if i == toggled => {
+                let _ = op.recv(pause_rx);
+                Event::PauseToggled
+            }
+            i if i == requested => {
+                let _ = op.recv(&self.shutdown_rx);
+                Event::ShutdownRequested
+            }
+            i if Some(i) == flush => {
+                let msg = self
+                    .backlog
+                    .borrow_mut()
+                    .pop_front()
+                    .expect("flushing only with a backlog");
+                Event::Flushed(op.send(&self.to_orchestrator, msg))
+            }
+            i => {
+                let &(_, id, outbox) = outgoing
+                    .iter()
+                    .find(|(index, _, _)| *index == i)
+                    .expect("selected operation belongs to an outbox");
+                Event::ToExplorer(id, op.recv(&outbox.from_planet))
+            }
+        }
+    }
+
+    /// Hands up to [`Link::stop_drain`] pending explorer requests to the
+    /// planet and, if it has yet to pick them up, holds the stop back in
+    /// [`Flow::held_stop`], so they are answered before the planet stops.
+    ///
+    /// The planet always handles the orchestrator first, so without this
+    /// requests queued behind a stop would only get `Stopped` back. The
+    /// relay loop keeps delivering replies meanwhile and relays the stop
+    /// once the planet's explorer channel is empty, or after
+    /// [`Link::STOP_DRAIN_TIMEOUT`].
+    ///
+    /// # Returns
+    /// `false` if the planet already exited.
+    fn drain_explorers(
+        &self,
+        relay: &Relay,
+        flow: &mut Flow,
+        from_explorers: &Receiver<ExplorerToPlanet>,
+    ) -> bool {
+        if self.stop_drain == 0 {
+            return true;
+        }
+        for msg in from_explorers.try_iter().take(self.stop_drain) {
+            if !Self::to_planet(relay, flow, msg) {
+                return false;
+            }
+            self.count_relayed();
+        }
+        if !relay.to_planet_expl.is_empty() {
+            flow.held_stop = Some(crossbeam_channel::after(Self::STOP_DRAIN_TIMEOUT));
+        }
+        true
+    }
+
+    /// Pairs a reply of the planet with the request it answers, counting the
+    /// request as dropped if the reply is `Stopped`.
+    ///
+    /// The planet answers requests in order, once each, except for a
+    /// `StartPlanetAI` received while running, which gets no reply: such
+    /// requests are skipped until the one the reply answers.
+    ///
+    /// # Returns
+    /// The request the reply answers, if known.
+    fn match_reply(
+        &self,
+        pending: &mut VecDeque<Request>,
+        reply: &PlanetToOrchestrator,
+    ) -> Option<Request> {
+        let Some(kind) = Request::answered_by(reply) else {
+            let request = pending.pop_front();
+            match request {
+                Some(Request::Sunray) => {
+                    self.count_dropped(|d| d.sunrays += 1);
+                    self.buffer_sunray();
+                }
+                // Stopped again before the replay got through.
+                Some(Request::Replay) => self.buffer_sunray(),
+                Some(Request::Asteroid) => self.count_dropped(|d| d.asteroids += 1),
+                Some(Request::InternalState) => {
+                    self.count_dropped(|d| d.internal_state_requests += 1);
+                }
+                Some(Request::IncomingExplorer | Request::OutgoingExplorer) => {
+                    self.count_dropped(|d| d.explorer_transfers += 1);
+                }
+                Some(Request::Stop) => self.count_dropped(|d| d.stop_requests += 1),
+                Some(
+                    Request::Start
+                    | Request::Kill
+                    | Request::Pause
+                    | Request::Resume
+                    | Request::Housekeeping,
+                )
+                | None => {}
+            }
+            return request;
+        };
+        std::iter::from_fn(|| pending.pop_front())
+            .find(|request| request.as_seen_by_planet() == kind)
+    }
+
+    fn count_dropped(&self, update: impl FnOnce(&mut DroppedWhileStopped)) {
+        update(&mut self.dropped.lock().unwrap_or_else(PoisonError::into_inner));
+    }
+
+    /// Swaps the sender of an arriving explorer for an internal one, so its
+    /// replies go through the relay.
+    fn register_explorer(
+        msg: OrchestratorToPlanet,
+        outboxes: &mut HashMap<u32, Outbox>,
+    ) -> OrchestratorToPlanet {
+        match msg {
+            OrchestratorToPlanet::IncomingExplorerRequest {
+                explorer_id,
+                new_sender,
+            } => {
+                let (to_outbox, from_planet) = crossbeam_channel::unbounded();
+                outboxes.insert(
+                    explorer_id,
+                    Outbox {
+                        from_planet,
+                        to_explorer: new_sender,
+                        disconnected: false,
+                    },
+                );
+                OrchestratorToPlanet::IncomingExplorerRequest {
+                    explorer_id,
+                    new_sender: to_outbox,
+                }
+            }
+            other => other,
+        }
+    }
+
+    /// Flags a housekeeping round as due and wakes a running planet up with
+    /// an `InternalStateRequest`, whose handler runs it.
+    ///
+    /// A stopped planet would answer `Stopped` without calling the AI, so
+    /// the round is skipped until it runs again.
+    ///
+    /// # Returns
+    /// `false` if the planet already exited.
+    fn request_housekeeping(&self, relay: &Relay, flow: &mut Flow) -> bool {
+        if !self.running.load(Ordering::Acquire) {
+            return true;
+        }
+        self.housekeeping_due.store(true, Ordering::Release);
+        flow.pending.push_back(Request::Housekeeping);
+        relay
+            .to_planet
+            .send(OrchestratorToPlanet::InternalStateRequest)
+            .is_ok()
+    }
+
+    /// Asks the planet to let go of the explorers found disconnected while it
+    /// runs, so their state is freed without waiting for a restart.
+    ///
+    /// A planet that is stopped, or about to be, would only answer
+    /// `Stopped`: those explorers are left to the next start.
+    fn prune_now(&self, relay: &Relay, outboxes: &HashMap<u32, Outbox>, flow: &mut Flow) {
+        let stopping = flow
+            .pending
+            .iter()
+            .any(|request| matches!(request, Request::Stop | Request::Kill | Request::Pause));
+        if self.running.load(Ordering::Acquire) && !stopping {
+            Self::prune(relay, outboxes, &mut flow.pending, &mut flow.pruned, false);
+        }
+    }
+
+    /// Asks the planet to let go of the explorers found disconnected, or of
+    /// every explorer after a `reset`, so a restarted planet does not keep
+    /// their state around.
+    ///
+    /// `crossbeam_channel` cannot tell whether a receiver is alive without
+    /// sending to it, so an explorer counts as disconnected once a reply to
+    /// it could not be delivered. Its outbox goes away once the planet drops
+    /// the sender, as for any departure.
+    fn prune(
+        relay: &Relay,
+        outboxes: &HashMap<u32, Outbox>,
+        pending: &mut VecDeque<Request>,
+        pruned: &mut HashSet<u32>,
+        reset: bool,
+    ) {
+        for (&explorer_id, outbox) in outboxes {
+            if !(reset || outbox.disconnected) || !pruned.insert(explorer_id) {
+                continue;
+            }
+            let reason = if reset { "reset" } else { "disconnected" };
+            debug!("explorer_id={explorer_id} {reason}, pruned");
+            pending.push_back(Request::OutgoingExplorer);
+            // A planet that already exited is noticed by the relay loop.
+            let _ = relay
+                .to_planet
+                .send(OrchestratorToPlanet::OutgoingExplorerRequest { explorer_id });
+        }
+    }
+
+    /// Updates [`Link::running`] from a reply of the planet.
+    fn track_running(&self, reply: &PlanetToOrchestrator) {
+        match reply {
+            PlanetToOrchestrator::StartPlanetAIResult { .. } => {
+                self.running.store(true, Ordering::Release);
+            }
+            PlanetToOrchestrator::StopPlanetAIResult { .. }
+            | PlanetToOrchestrator::KillPlanetResult { .. } => {
+                self.running.store(false, Ordering::Release);
+            }
+            _ => {}
+        }
+    }
+
+    /// Sends a message of the planet to the orchestrator, reporting asteroid
+    /// outcomes to the defense subscriber on the way.
+    ///
+    /// With an outbox, a message finding the orchestrator's channel full is
+    /// queued in [`Link::backlog`] for the relay loop to flush, or dropped if
+    /// the backlog is full as well.
+    ///
+    /// # Returns
+    /// `false` if the orchestrator dropped its receiver.
+    pub(crate) fn deliver(&self, msg: PlanetToOrchestrator) -> bool {
+        self.observe(&msg);
+        let Some(capacity) = self.outbox_capacity else {
+            return match self.to_orchestrator.send(msg) {
+                Ok(()) => true,
+                Err(e) => {
+                    error!("orchestrator unreachable, {:?} not delivered", e.0);
+                    false
+                }
+            };
+        };
+        let mut backlog = self.backlog.borrow_mut();
+        // Replies keep their order: nothing overtakes the backlog.
+        let msg = if backlog.is_empty() {
+            match self.to_orchestrator.try_send(msg) {
+                Ok(()) => return true,
+                Err(TrySendError::Full(msg)) => msg,
+                Err(TrySendError::Disconnected(msg)) => {
+                    error!("orchestrator unreachable, {msg:?} not delivered");
+                    return false;
+                }
+            }
+        } else {
+            msg
+        };
+        if backlog.len() < capacity {
+            backlog.push_back(msg);
+        } else {
+            self.drop_reply(&msg);
+        }
+        true
+    }
+
+    /// Updates [`Link::running`] from a message of the planet to the
+    /// orchestrator, and reports asteroid outcomes to the defense subscriber.
+    fn observe(&self, msg: &PlanetToOrchestrator) {
+        self.track_running(msg);
+        if let PlanetToOrchestrator::AsteroidAck { planet_id, rocket } = &msg {
+            let event = if rocket.is_some() {
+                DefenseEvent::Defended {
+                    planet_id: *planet_id,
+                }
+            } else {
+                DefenseEvent::Undefended {
+                    planet_id: *planet_id,
+                }
+            };
+            if let Some(events) = &self.defense_events
+                && events.send(event).is_err()
+            {
+                debug!("planet_id={planet_id} defense subscriber gone, {event:?} not reported");
+            }
+        }
+    }
+
+    /// Drops a reply the orchestrator has no room for.
+    fn drop_reply(&self, msg: &PlanetToOrchestrator) {
+        warn!("orchestrator not receiving, outbox full, {msg:?} dropped");
+        self.replies_dropped.set(self.replies_dropped.get() + 1);
+    }
+
+    /// Sends a reply of the planet to an explorer.
+    ///
+    /// A full channel is retried with a growing timeout, up to
+    /// [`Link::EXPLORER_SEND_ATTEMPTS`] times, then the reply is dropped: a
+    /// slow explorer must not stall the planet.
+    ///
+    /// # Returns
+    /// `false` if the explorer dropped its receiver.
+    fn deliver_to_explorer(
+        explorer_id: u32,
+        to_explorer: &Sender<PlanetToExplorer>,
+        mut msg: PlanetToExplorer,
+    ) -> bool {
+        for attempt in 1..=Self::EXPLORER_SEND_ATTEMPTS {
+            match to_explorer.send_timeout(msg, Self::EXPLORER_SEND_TIMEOUT * attempt) {
+                Ok(()) => return true,
+                Err(SendTimeoutError::Timeout(unsent)) => {
+                    debug!("explorer_id={explorer_id} channel full, attempt {attempt}");
+                    msg = unsent;
+                }
+                Err(SendTimeoutError::Disconnected(unsent)) => {
+                    warn!("explorer_id={explorer_id} disconnected, {unsent:?} dropped");
+                    return false;
+                }
+            }
+        }
+        warn!("explorer_id={explorer_id} not receiving, {msg:?} dropped");
+        true
+    }
+
+    /// Delivers the replies the planet produced before it stopped.
+    ///
+    /// Replies the orchestrator still has no room for are dropped, with or
+    /// without an outbox, so a stalled orchestrator cannot hold the shutdown
+    /// up.
+    pub(crate) fn flush(&self, outboxes: &HashMap<u32, Outbox>) {
+        let mut backlog = self.backlog.take();
+        for msg in self.from_planet.try_iter() {
+            self.observe(&msg);
+            backlog.push_back(msg);
+        }
+        for msg in backlog {
+            if let Err(e) = self.to_orchestrator.try_send(msg) {
+                self.drop_reply(&e.into_inner());
+            }
+        }
+        for (&explorer_id, outbox) in outboxes {
+            for msg in outbox.from_planet.try_iter() {
+                Self::deliver_to_explorer(explorer_id, &outbox.to_explorer, msg);
+            }
+        }
+    }
+}
@@ -0,0 +1,43 @@
+//! Text names of the basic resources, for logs.
+//!
+//! [`BasicResourceType`] is foreign to this crate, so the conversion is a
+//! plain function rather than a `Display` impl. Names are the lowercase
+//! variant names, e.g. `"oxygen"`.
+
+use common_game::components::resource::BasicResourceType;
+
+/// Returns the name of a basic resource.
+pub(crate) const fn name(resource: BasicResourceType) -> &'static str {
+    match resource {
+        BasicResourceType::Oxygen => "oxygen",
+        BasicResourceType::Hydrogen => "hydrogen",
+        BasicResourceType::Carbon => "carbon",
+        BasicResourceType::Silicon => "silicon",
+    }
+}
+
+/// Serializes a basic resource as its name.
+#[cfg(feature = "serde")]
+pub(crate) fn serialize<S: serde::Serializer>(
+    resource: &BasicResourceType,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(name(*resource))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_names_are_lowercase_variant_names() {
+        for resource in [
+            BasicResourceType::Oxygen,
+            BasicResourceType::Hydrogen,
+            BasicResourceType::Carbon,
+            BasicResourceType::Silicon,
+        ] {
+            assert_eq!(name(resource), format!("{resource:?}").to_lowercase());
+        }
+    }
+}
@@ -1,25 +1,62 @@
 use common_game::protocols::orchestrator_planet::OrchestratorToPlanet;
 use common_game::protocols::orchestrator_planet::PlanetToOrchestrator;
-use common_game::protocols::planet_explorer::ExplorerToPlanet;
+use common_game::protocols::planet_explorer::{ExplorerToPlanet, PlanetToExplorer};
 use std::thread;
 use std::time::Duration;
-use trip::trip;
+use trip::{RunReport, Trip, TripChannels, trip};
 
 // Helper struct to hold test resources
 pub struct TestHarness {
     pub orch_tx: crossbeam_channel::Sender<OrchestratorToPlanet>,
     pub planet_rx: crossbeam_channel::Receiver<PlanetToOrchestrator>,
     pub expl_tx: crossbeam_channel::Sender<ExplorerToPlanet>,
-    pub handle: thread::JoinHandle<Result<(), String>>,
+    pub pte_tx: crossbeam_channel::Sender<PlanetToExplorer>,
+    pub pte_rx: crossbeam_channel::Receiver<PlanetToExplorer>,
+    pub handle: thread::JoinHandle<RunReport>,
+    pub cell_capacity: usize,
 }
 
 impl TestHarness {
     pub fn setup() -> Self {
-        let (orch_tx, orch_rx) = crossbeam_channel::unbounded();
-        let (planet_tx, planet_rx) = crossbeam_channel::unbounded();
-        let (expl_tx, expl_rx) = crossbeam_channel::unbounded();
+        let (trip, channels) = Trip::new_pair(0).unwrap();
+        let TripChannels {
+            orch_to_planet,
+            planet_to_orch,
+            expl_to_planet,
+            planet_to_expl,
+            expl_from_planet,
+        } = channels;
+        Self::spawn(
+            trip,
+            orch_to_planet,
+            planet_to_orch,
+            expl_to_planet,
+            planet_to_expl,
+            expl_from_planet,
+        )
+    }
+
+    /// Like [`TestHarness::setup`], but every channel holds at most
+    /// `capacity` messages, so a slow reader applies backpressure.
+    pub fn setup_bounded(capacity: usize) -> Self {
+        let (orch_tx, orch_rx) = crossbeam_channel::bounded(capacity);
+        let (planet_tx, planet_rx) = crossbeam_channel::bounded(capacity);
+        let (expl_tx, expl_rx) = crossbeam_channel::bounded(capacity);
+        let (pte_tx, pte_rx) = crossbeam_channel::bounded(capacity);
+
+        let trip = trip(0, orch_rx, planet_tx, expl_rx).unwrap();
+        Self::spawn(trip, orch_tx, planet_rx, expl_tx, pte_tx, pte_rx)
+    }
 
-        let mut trip = trip(0, orch_rx, planet_tx, expl_rx).unwrap();
+    fn spawn(
+        mut trip: Trip,
+        orch_tx: crossbeam_channel::Sender<OrchestratorToPlanet>,
+        planet_rx: crossbeam_channel::Receiver<PlanetToOrchestrator>,
+        expl_tx: crossbeam_channel::Sender<ExplorerToPlanet>,
+        pte_tx: crossbeam_channel::Sender<PlanetToExplorer>,
+        pte_rx: crossbeam_channel::Receiver<PlanetToExplorer>,
+    ) -> Self {
+        let cell_capacity = trip.energy_cell_capacity();
 
         let handle = thread::spawn(move || trip.run());
 
@@ -27,7 +64,10 @@ impl TestHarness {
             orch_tx,
             planet_rx,
             expl_tx,
+            pte_tx,
+            pte_rx,
             handle,
+            cell_capacity,
         }
     }
 
@@ -38,21 +78,61 @@ impl TestHarness {
         let _ = self.recv_pto_with_timeout();
     }
 
-    pub fn stop_and_join(self) -> thread::Result<Result<(), String>> {
+    pub fn stop_and_join(self) -> thread::Result<RunReport> {
         self.orch_tx
             .send(OrchestratorToPlanet::StopPlanetAI)
             .expect("Failed to send StopPlanetAI");
+        self.drain_pending();
         drop(self.orch_tx);
         drop(self.expl_tx);
         self.handle.join()
     }
 
-    pub fn join(self) -> thread::Result<Result<(), String>> {
+    pub fn join(self) -> thread::Result<RunReport> {
         drop(self.orch_tx);
         drop(self.expl_tx);
         self.handle.join()
     }
 
+    /// Performs the incoming-explorer handshake for `explorer_id` and returns
+    /// the receiver on which the planet answers that explorer.
+    pub fn register_explorer(
+        &self,
+        explorer_id: u32,
+    ) -> crossbeam_channel::Receiver<PlanetToExplorer> {
+        self.orch_tx
+            .send(OrchestratorToPlanet::IncomingExplorerRequest {
+                explorer_id,
+                new_sender: self.pte_tx.clone(),
+            })
+            .expect("Failed to send IncomingExplorerRequest");
+        match self.recv_pto_with_timeout() {
+            PlanetToOrchestrator::IncomingExplorerResponse { res: Ok(()), .. } => {}
+            _other => panic!("Wrong response received"),
+        }
+        self.pte_rx.clone()
+    }
+
+    pub fn send_explorer(&self, msg: ExplorerToPlanet) {
+        self.expl_tx
+            .send(msg)
+            .expect("Failed to send explorer message");
+    }
+
+    pub fn recv_pte_with_timeout(&self) -> PlanetToExplorer {
+        self.pte_rx
+            .recv_timeout(Duration::from_millis(500))
+            .expect("No message received")
+    }
+
+    /// Discards the messages the planet sent to the orchestrator and nobody
+    /// read, waiting briefly for the ones still in flight.
+    ///
+    /// Returns how many messages were discarded.
+    pub fn drain_pending(&self) -> usize {
+        std::iter::from_fn(|| self.planet_rx.recv_timeout(Duration::from_millis(50)).ok()).count()
+    }
+
     pub fn recv_pto_with_timeout(&self) -> PlanetToOrchestrator {
         self.planet_rx
             .recv_timeout(Duration::from_millis(500))
@@ -1,12 +1,26 @@
 use common_game::components::asteroid::Asteroid;
+use common_game::components::planet::{
+    DummyPlanetState, Planet, PlanetAI, PlanetState, PlanetType,
+};
+use common_game::components::resource::BasicResourceType;
+use common_game::components::resource::{Combinator, Generator};
+use common_game::components::rocket::Rocket;
 use common_game::components::sunray::Sunray;
 use common_game::protocols::orchestrator_planet::OrchestratorToPlanet;
 use common_game::protocols::orchestrator_planet::OrchestratorToPlanet::IncomingExplorerRequest;
 use common_game::protocols::orchestrator_planet::PlanetToOrchestrator;
 use common_game::protocols::planet_explorer::ExplorerToPlanet;
 use common_game::protocols::planet_explorer::PlanetToExplorer;
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
 use std::thread;
-use trip::trip;
+use std::time::{Duration, Instant};
+use trip::{
+    AiConfig, AuditEvent, ComplexYields, DefenseEvent, DroppedWhileStopped, PlanetInfo,
+    ResourceYields, Termination, Trip, TripBuilder, TripMetrics, trip, trip_with_ai,
+    trip_with_rules,
+};
 
 use std::sync::Once;
 
@@ -45,6 +59,150 @@ fn test_planet_run() {
     assert!(result.is_ok());
 }
 
+#[test]
+fn test_run_reports_orchestrator_disconnect() {
+    setup_logger();
+    let (orch_tx, orch_rx) = crossbeam_channel::unbounded();
+    let (planet_tx, planet_rx) = crossbeam_channel::unbounded();
+    let (_expl_tx, expl_rx) = crossbeam_channel::unbounded();
+
+    let mut trip = trip(0, orch_rx, planet_tx, expl_rx).unwrap();
+    let handle = thread::spawn(move || trip.run());
+
+    for msg in [
+        OrchestratorToPlanet::StartPlanetAI,
+        OrchestratorToPlanet::Sunray(Sunray::default()),
+        OrchestratorToPlanet::InternalStateRequest,
+    ] {
+        orch_tx.send(msg).expect("Failed to send message");
+        planet_rx
+            .recv_timeout(Duration::from_millis(500))
+            .expect("No message received");
+    }
+    drop(orch_tx);
+
+    let report = handle.join().expect("Planet thread panicked");
+    assert_eq!(report.reason, Termination::OrchestratorDisconnected);
+    assert_eq!(report.messages_processed, 3);
+    assert_eq!(report.panics_recovered, 0);
+    assert_eq!(report.explorers_disconnected, 0);
+}
+
+#[test]
+fn test_outbox_drops_replies_to_blocked_orchestrator() {
+    setup_logger();
+    let (orch_tx, orch_rx) = crossbeam_channel::unbounded();
+    let (planet_tx, planet_rx) = crossbeam_channel::bounded(1);
+    let (_expl_tx, expl_rx) = crossbeam_channel::unbounded();
+
+    let mut trip = TripBuilder::new(0)
+        .orchestrator_outbox(4)
+        .channels(orch_rx, planet_tx, expl_rx)
+        .build()
+        .unwrap();
+    let handle = thread::spawn(move || trip.run());
+
+    // Nobody reads: the start result fills the channel, four acks fill the
+    // outbox and the rest is dropped instead of stalling the relay.
+    orch_tx
+        .send(OrchestratorToPlanet::StartPlanetAI)
+        .expect("Failed to send start message");
+    for _ in 0..20 {
+        orch_tx
+            .send(OrchestratorToPlanet::Sunray(Sunray::default()))
+            .expect("Failed to send sunray message");
+    }
+    drop(orch_tx);
+
+    let report = handle.join().expect("Planet thread panicked");
+    assert_eq!(report.reason, Termination::OrchestratorDisconnected);
+    assert_eq!(report.messages_processed, 21);
+    // The outbox is not flushed into a still full channel on shutdown.
+    assert_eq!(report.replies_dropped, 20);
+    assert!(matches!(
+        planet_rx.try_recv(),
+        Ok(PlanetToOrchestrator::StartPlanetAIResult { planet_id: 0 })
+    ));
+    assert!(planet_rx.try_recv().is_err());
+}
+
+#[test]
+fn test_outbox_flushes_in_order_once_orchestrator_reads() {
+    setup_logger();
+    let (orch_tx, orch_rx) = crossbeam_channel::unbounded();
+    let (planet_tx, planet_rx) = crossbeam_channel::bounded(1);
+    let (_expl_tx, expl_rx) = crossbeam_channel::unbounded();
+
+    let mut trip = TripBuilder::new(0)
+        .orchestrator_outbox(8)
+        .channels(orch_rx, planet_tx, expl_rx)
+        .build()
+        .unwrap();
+    let handle = thread::spawn(move || trip.run());
+
+    orch_tx
+        .send(OrchestratorToPlanet::StartPlanetAI)
+        .expect("Failed to send start message");
+    for _ in 0..5 {
+        orch_tx
+            .send(OrchestratorToPlanet::Sunray(Sunray::default()))
+            .expect("Failed to send sunray message");
+    }
+    thread::sleep(Duration::from_millis(50));
+
+    let recv = || {
+        planet_rx
+            .recv_timeout(Duration::from_millis(500))
+            .expect("No message received")
+    };
+    assert!(matches!(
+        recv(),
+        PlanetToOrchestrator::StartPlanetAIResult { planet_id: 0 }
+    ));
+    for _ in 0..5 {
+        assert!(matches!(
+            recv(),
+            PlanetToOrchestrator::SunrayAck { planet_id: 0 }
+        ));
+    }
+
+    drop(orch_tx);
+    let report = handle.join().expect("Planet thread panicked");
+    assert_eq!(report.replies_dropped, 0);
+}
+
+#[test]
+fn test_message_queued_before_trip_is_not_lost() {
+    setup_logger();
+    let (orch_tx, orch_rx) = crossbeam_channel::unbounded();
+    let (planet_tx, planet_rx) = crossbeam_channel::unbounded();
+    let (_expl_tx, expl_rx) = crossbeam_channel::unbounded();
+
+    orch_tx
+        .send(OrchestratorToPlanet::StartPlanetAI)
+        .expect("Failed to send start message");
+    let mut trip = trip(0, orch_rx, planet_tx, expl_rx).unwrap();
+    let handle = thread::spawn(move || trip.run());
+
+    assert!(matches!(
+        planet_rx.recv_timeout(Duration::from_millis(500)),
+        Ok(PlanetToOrchestrator::StartPlanetAIResult { planet_id: 0 })
+    ));
+    orch_tx
+        .send(OrchestratorToPlanet::Sunray(Sunray::default()))
+        .expect("Failed to send sunray message");
+    assert!(
+        matches!(
+            planet_rx.recv_timeout(Duration::from_millis(500)),
+            Ok(PlanetToOrchestrator::SunrayAck { planet_id: 0 })
+        ),
+        "The planet must be running after the queued start"
+    );
+
+    drop(orch_tx);
+    assert!(handle.join().is_ok());
+}
+
 #[test]
 fn test_concurrent_message_sending() {
     setup_logger();
@@ -56,7 +214,7 @@ fn test_concurrent_message_sending() {
 
     let handle = std::thread::spawn(move || {
         for _ in 0..100 {
-            trip.run().ok();
+            let _ = trip.run();
         }
     });
 
@@ -89,22 +247,11 @@ fn test_planet_supported_resource_resp() {
     setup_logger();
     let harness = common::TestHarness::setup();
     harness.start();
-    let (expl_tx, expl_rx) = crossbeam_channel::unbounded();
-
-    harness
-        .orch_tx
-        .send(IncomingExplorerRequest {
-            explorer_id: 0,
-            new_sender: expl_tx,
-        })
-        .expect("Failed to send sunray message");
+    harness.register_explorer(0);
 
-    harness
-        .expl_tx
-        .send(ExplorerToPlanet::SupportedResourceRequest { explorer_id: 0 })
-        .expect("Failed to send asteroid message");
+    harness.send_explorer(ExplorerToPlanet::SupportedResourceRequest { explorer_id: 0 });
 
-    match expl_rx.recv().expect("No message received") {
+    match harness.recv_pte_with_timeout() {
         PlanetToExplorer::SupportedResourceResponse { .. } => {}
         _other => panic!("Wrong response received"),
     }
@@ -118,22 +265,11 @@ fn test_planet_supported_combination_resp() {
     setup_logger();
     let harness = common::TestHarness::setup();
     harness.start();
-    let (expl_tx, expl_rx) = crossbeam_channel::unbounded();
-
-    harness
-        .orch_tx
-        .send(IncomingExplorerRequest {
-            explorer_id: 0,
-            new_sender: expl_tx,
-        })
-        .expect("Failed to send sunray message");
+    harness.register_explorer(0);
 
-    harness
-        .expl_tx
-        .send(ExplorerToPlanet::SupportedCombinationRequest { explorer_id: 0 })
-        .expect("Failed to send asteroid message");
+    harness.send_explorer(ExplorerToPlanet::SupportedCombinationRequest { explorer_id: 0 });
 
-    match expl_rx.recv().expect("No message received") {
+    match harness.recv_pte_with_timeout() {
         PlanetToExplorer::SupportedCombinationResponse { .. } => {}
         _other => panic!("Wrong response received"),
     }
@@ -147,20 +283,9 @@ fn test_planet_available_eng_cell_resp() {
     setup_logger();
     let harness = common::TestHarness::setup();
     harness.start();
-    let (expl_tx, expl_rx) = crossbeam_channel::unbounded();
-
-    harness
-        .orch_tx
-        .send(IncomingExplorerRequest {
-            explorer_id: 0,
-            new_sender: expl_tx,
-        })
-        .expect("Failed to send sunray message");
+    let expl_rx = harness.register_explorer(0);
 
-    harness
-        .expl_tx
-        .send(ExplorerToPlanet::AvailableEnergyCellRequest { explorer_id: 0 })
-        .expect("Failed to send asteroid message");
+    harness.send_explorer(ExplorerToPlanet::AvailableEnergyCellRequest { explorer_id: 0 });
 
     match expl_rx.recv().expect("No message received") {
         PlanetToExplorer::AvailableEnergyCellResponse { available_cells: 0 } => {}
@@ -190,13 +315,12 @@ fn test_planet_sunray_ack() {
     harness
         .orch_tx
         .send(OrchestratorToPlanet::InternalStateRequest)
-        .expect(
-            format!(
+        .unwrap_or_else(|_| {
+            panic!(
                 "Failed to send {:?} message",
                 OrchestratorToPlanet::InternalStateRequest
             )
-            .as_str(),
-        );
+        });
 
     let result = harness.recv_pto_with_timeout();
     match result {
@@ -239,13 +363,12 @@ fn test_planet_multiple_sunray_ack() {
     harness
         .orch_tx
         .send(OrchestratorToPlanet::InternalStateRequest)
-        .expect(
-            format!(
+        .unwrap_or_else(|_| {
+            panic!(
                 "Failed to send {:?} message",
                 OrchestratorToPlanet::InternalStateRequest
             )
-            .as_str(),
-        );
+        });
     let result = harness.recv_pto_with_timeout();
     match result {
         PlanetToOrchestrator::InternalStateResponse {
@@ -253,8 +376,8 @@ fn test_planet_multiple_sunray_ack() {
             planet_id: 0,
         } => {
             assert_eq!(
-                planet_state.charged_cells_count, 5,
-                "Charged cell must be 5"
+                planet_state.charged_cells_count, harness.cell_capacity,
+                "Every cell must be charged"
             );
             assert!(planet_state.has_rocket, "Planet must have rocket");
         }
@@ -265,6 +388,68 @@ fn test_planet_multiple_sunray_ack() {
     assert!(result.is_ok());
 }
 
+#[test]
+fn test_bounded_channels_survive_sunray_burst() {
+    setup_logger();
+    let harness = common::TestHarness::setup_bounded(2);
+    harness.start();
+    let pte_rx = harness.register_explorer(1);
+
+    // Both bursts outrun the capacity, so the planet has to block on full
+    // channels while the test drains them.
+    let orch_tx = harness.orch_tx.clone();
+    let sunrays = thread::spawn(move || {
+        for _ in 0..20 {
+            orch_tx
+                .send(OrchestratorToPlanet::Sunray(Sunray::default()))
+                .expect("Failed to send sunray message");
+        }
+    });
+    for _ in 0..20 {
+        assert!(matches!(
+            harness.recv_pto_with_timeout(),
+            PlanetToOrchestrator::SunrayAck { planet_id: 0 }
+        ));
+    }
+    sunrays.join().expect("Sunray sender panicked");
+
+    let expl_tx = harness.expl_tx.clone();
+    let requests = thread::spawn(move || {
+        for _ in 0..10 {
+            expl_tx
+                .send(ExplorerToPlanet::SupportedResourceRequest { explorer_id: 1 })
+                .expect("Failed to send explorer message");
+        }
+    });
+    for _ in 0..10 {
+        assert!(matches!(
+            pte_rx.recv_timeout(Duration::from_millis(500)),
+            Ok(PlanetToExplorer::SupportedResourceResponse { .. })
+        ));
+    }
+    requests.join().expect("Explorer sender panicked");
+
+    assert!(harness.stop_and_join().is_ok());
+}
+
+#[test]
+fn test_drain_pending_discards_unread_acks() {
+    setup_logger();
+    let harness = common::TestHarness::setup();
+    harness.start();
+
+    for _ in 0..5 {
+        harness
+            .orch_tx
+            .send(OrchestratorToPlanet::Sunray(Sunray::default()))
+            .expect("Failed to send sunray message");
+    }
+    assert_eq!(harness.drain_pending(), 5);
+    assert_eq!(harness.drain_pending(), 0);
+
+    assert!(harness.stop_and_join().is_ok());
+}
+
 #[test]
 fn test_planet_asteroid_ack() {
     setup_logger();
@@ -388,3 +573,2111 @@ fn test_planet_outgoing_expl_resp() {
     let result = harness.stop_and_join();
     assert!(result.is_ok());
 }
+
+#[test]
+fn test_planet_configured_generation_rules() {
+    setup_logger();
+    let (orch_tx, orch_rx) = crossbeam_channel::unbounded();
+    let (planet_tx, planet_rx) = crossbeam_channel::unbounded();
+    let (expl_tx, expl_rx) = crossbeam_channel::unbounded();
+
+    let mut trip = trip_with_rules(
+        0,
+        PlanetType::A,
+        vec![BasicResourceType::Carbon],
+        vec![],
+        orch_rx,
+        planet_tx,
+        expl_rx,
+    )
+    .unwrap();
+    let handle = thread::spawn(move || trip.run());
+
+    orch_tx
+        .send(OrchestratorToPlanet::StartPlanetAI)
+        .expect("Failed to send start message");
+    let (to_expl_tx, to_expl_rx) = crossbeam_channel::unbounded();
+    orch_tx
+        .send(IncomingExplorerRequest {
+            explorer_id: 0,
+            new_sender: to_expl_tx,
+        })
+        .expect("Failed to send incoming explorer message");
+    expl_tx
+        .send(ExplorerToPlanet::SupportedResourceRequest { explorer_id: 0 })
+        .expect("Failed to send supported resource message");
+
+    match to_expl_rx.recv().expect("No message received") {
+        PlanetToExplorer::SupportedResourceResponse { resource_list } => {
+            assert_eq!(resource_list.len(), 1);
+            assert!(resource_list.contains(&BasicResourceType::Carbon));
+        }
+        _other => panic!("Wrong response received"),
+    }
+
+    drop(orch_tx);
+    drop(expl_tx);
+    drop(planet_rx);
+    assert!(handle.join().is_ok());
+}
+
+#[test]
+fn test_generation_rules_can_be_narrowed_mid_run() {
+    setup_logger();
+    let (orch_tx, orch_rx) = crossbeam_channel::unbounded();
+    let (planet_tx, planet_rx) = crossbeam_channel::unbounded();
+    let (expl_tx, expl_rx) = crossbeam_channel::unbounded();
+
+    let mut trip = trip_with_rules(
+        0,
+        PlanetType::D,
+        vec![
+            BasicResourceType::Oxygen,
+            BasicResourceType::Hydrogen,
+            BasicResourceType::Carbon,
+        ],
+        vec![],
+        orch_rx,
+        planet_tx,
+        expl_rx,
+    )
+    .unwrap();
+    let control = trip.handle();
+    let handle = thread::spawn(move || {
+        let _ = trip.run();
+        trip
+    });
+
+    let send = |msg| {
+        orch_tx.send(msg).expect("Failed to send message");
+        planet_rx
+            .recv_timeout(Duration::from_millis(500))
+            .expect("No message received");
+    };
+    let (to_expl_tx, to_expl_rx) = crossbeam_channel::unbounded();
+    send(OrchestratorToPlanet::StartPlanetAI);
+    send(IncomingExplorerRequest {
+        explorer_id: 0,
+        new_sender: to_expl_tx,
+    });
+    let supported = || {
+        expl_tx
+            .send(ExplorerToPlanet::SupportedResourceRequest { explorer_id: 0 })
+            .expect("Failed to send supported resource message");
+        match to_expl_rx
+            .recv_timeout(Duration::from_millis(500))
+            .expect("No message received")
+        {
+            PlanetToExplorer::SupportedResourceResponse { resource_list } => resource_list,
+            _other => panic!("Wrong response received"),
+        }
+    };
+
+    assert_eq!(supported().len(), 3);
+    // Silicon is not among the planet's rules, so it cannot be added.
+    control.set_generation_rules(Some(&[
+        BasicResourceType::Hydrogen,
+        BasicResourceType::Silicon,
+    ]));
+    assert_eq!(supported(), HashSet::from([BasicResourceType::Hydrogen]));
+    control.set_generation_rules(None);
+    assert_eq!(supported().len(), 3);
+
+    control.set_generation_rules(Some(&[BasicResourceType::Carbon]));
+    control.shutdown();
+    let trip = handle.join().expect("Planet thread panicked");
+    assert_eq!(
+        trip.planet_info().supported_resources,
+        HashSet::from([BasicResourceType::Carbon])
+    );
+}
+
+#[test]
+fn test_reset_starts_a_new_epoch() {
+    setup_logger();
+    let (orch_tx, orch_rx) = crossbeam_channel::unbounded();
+    let (planet_tx, planet_rx) = crossbeam_channel::unbounded();
+    let (expl_tx, expl_rx) = crossbeam_channel::unbounded();
+
+    let mut trip = trip(0, orch_rx, planet_tx, expl_rx).unwrap();
+    let control = trip.handle();
+    let handle = thread::spawn(move || {
+        let _ = trip.run();
+        trip
+    });
+
+    let send = |msg| {
+        orch_tx.send(msg).expect("Failed to send message");
+        planet_rx
+            .recv_timeout(Duration::from_millis(500))
+            .expect("No message received")
+    };
+    let (to_expl_tx, to_expl_rx) = crossbeam_channel::unbounded();
+    send(OrchestratorToPlanet::StartPlanetAI);
+    send(IncomingExplorerRequest {
+        explorer_id: 5,
+        new_sender: to_expl_tx,
+    });
+    send(OrchestratorToPlanet::Sunray(Sunray::default()));
+    send(OrchestratorToPlanet::Sunray(Sunray::default()));
+
+    control.reset();
+    send(OrchestratorToPlanet::StopPlanetAI);
+    assert!(matches!(
+        send(OrchestratorToPlanet::StartPlanetAI),
+        PlanetToOrchestrator::StartPlanetAIResult { planet_id: 0 }
+    ));
+    // The departure of the explorer is not meant for the orchestrator.
+    assert!(planet_rx.recv_timeout(Duration::from_millis(100)).is_err());
+    expl_tx
+        .send(ExplorerToPlanet::AvailableEnergyCellRequest { explorer_id: 5 })
+        .expect("Failed to send explorer message");
+    assert!(
+        to_expl_rx.recv_timeout(Duration::from_millis(100)).is_err(),
+        "The explorer must be sent in again after a reset"
+    );
+
+    control.shutdown();
+    let metrics = handle.join().expect("Planet thread panicked").metrics();
+    assert_eq!(metrics.sunrays, 0);
+    assert_eq!(metrics.rockets_built, 0);
+    assert_eq!(metrics.explorers, 0);
+    assert_eq!(metrics.health, 100);
+}
+
+#[test]
+fn test_generation_for_disconnected_explorer_keeps_charge() {
+    setup_logger();
+    let (orch_tx, orch_rx) = crossbeam_channel::unbounded();
+    let (planet_tx, planet_rx) = crossbeam_channel::unbounded();
+    let (expl_tx, expl_rx) = crossbeam_channel::unbounded();
+    let (pte_tx, pte_rx) = crossbeam_channel::unbounded();
+    let mut trip = TripBuilder::new(0)
+        .ai_config(AiConfig {
+            auto_build: false,
+            ..AiConfig::default()
+        })
+        .channels(orch_rx, planet_tx, expl_rx)
+        .build()
+        .unwrap();
+    let handle = thread::spawn(move || trip.run());
+    let recv = || {
+        planet_rx
+            .recv_timeout(Duration::from_millis(500))
+            .expect("No message received")
+    };
+    let charged_cells = || {
+        orch_tx
+            .send(OrchestratorToPlanet::InternalStateRequest)
+            .unwrap();
+        match recv() {
+            PlanetToOrchestrator::InternalStateResponse { planet_state, .. } => {
+                planet_state.charged_cells_count
+            }
+            _other => panic!("Wrong response received"),
+        }
+    };
+
+    orch_tx.send(OrchestratorToPlanet::StartPlanetAI).unwrap();
+    recv();
+    orch_tx
+        .send(IncomingExplorerRequest {
+            explorer_id: 3,
+            new_sender: pte_tx,
+        })
+        .unwrap();
+    recv();
+    orch_tx
+        .send(OrchestratorToPlanet::Sunray(Sunray::default()))
+        .unwrap();
+    recv();
+    assert_eq!(charged_cells(), 1);
+
+    // The first reply finds the explorer gone, the generation is not tried.
+    drop(pte_rx);
+    expl_tx
+        .send(ExplorerToPlanet::SupportedResourceRequest { explorer_id: 3 })
+        .unwrap();
+    thread::sleep(Duration::from_millis(100));
+    expl_tx
+        .send(ExplorerToPlanet::GenerateResourceRequest {
+            explorer_id: 3,
+            resource: BasicResourceType::Oxygen,
+        })
+        .unwrap();
+    thread::sleep(Duration::from_millis(100));
+    assert_eq!(charged_cells(), 1, "The cell must stay charged");
+
+    drop(orch_tx);
+    drop(expl_tx);
+    assert!(handle.join().is_ok());
+}
+
+#[test]
+fn test_explorer_requests_wait_for_start_grace() {
+    setup_logger();
+    let grace = Duration::from_millis(300);
+    let (orch_tx, orch_rx) = crossbeam_channel::unbounded();
+    let (planet_tx, planet_rx) = crossbeam_channel::unbounded();
+    let (expl_tx, expl_rx) = crossbeam_channel::unbounded();
+    let (pte_tx, pte_rx) = crossbeam_channel::unbounded();
+    let mut trip = TripBuilder::new(0)
+        .ai_config(AiConfig {
+            start_grace: grace,
+            ..AiConfig::default()
+        })
+        .channels(orch_rx, planet_tx, expl_rx)
+        .build()
+        .unwrap();
+    let handle = thread::spawn(move || trip.run());
+    let recv = || {
+        planet_rx
+            .recv_timeout(Duration::from_millis(500))
+            .expect("No message received")
+    };
+
+    let started = Instant::now();
+    orch_tx.send(OrchestratorToPlanet::StartPlanetAI).unwrap();
+    recv();
+    orch_tx
+        .send(IncomingExplorerRequest {
+            explorer_id: 3,
+            new_sender: pte_tx,
+        })
+        .unwrap();
+    recv();
+    expl_tx
+        .send(ExplorerToPlanet::SupportedResourceRequest { explorer_id: 3 })
+        .unwrap();
+    orch_tx
+        .send(OrchestratorToPlanet::Sunray(Sunray::default()))
+        .unwrap();
+    assert!(
+        matches!(recv(), PlanetToOrchestrator::SunrayAck { .. }),
+        "Orchestrator messages must not wait"
+    );
+    assert!(
+        pte_rx.recv_timeout(Duration::from_millis(100)).is_err(),
+        "Explorer requests must wait for the grace period"
+    );
+
+    match pte_rx.recv_timeout(Duration::from_secs(1)) {
+        Ok(PlanetToExplorer::SupportedResourceResponse { .. }) => {}
+        _other => panic!("Wrong response received"),
+    }
+    assert!(started.elapsed() >= grace);
+
+    drop(orch_tx);
+    drop(expl_tx);
+    assert!(handle.join().is_ok());
+}
+
+#[test]
+fn test_audit_events_follow_the_script() {
+    setup_logger();
+    let (audit_tx, audit_rx) = crossbeam_channel::unbounded();
+    let (orch_tx, orch_rx) = crossbeam_channel::unbounded();
+    let (planet_tx, planet_rx) = crossbeam_channel::unbounded();
+    let (expl_tx, expl_rx) = crossbeam_channel::unbounded();
+    let (pte_tx, pte_rx) = crossbeam_channel::unbounded();
+    let mut trip = TripBuilder::new(0)
+        .audit_events(audit_tx)
+        .channels(orch_rx, planet_tx, expl_rx)
+        .build()
+        .unwrap();
+    let handle = thread::spawn(move || trip.run());
+    let send = |msg| {
+        orch_tx.send(msg).unwrap();
+        planet_rx
+            .recv_timeout(Duration::from_millis(500))
+            .expect("No message received")
+    };
+
+    send(OrchestratorToPlanet::StartPlanetAI);
+    send(IncomingExplorerRequest {
+        explorer_id: 1,
+        new_sender: pte_tx,
+    });
+    // The first charge becomes the reserve rocket, the second one oxygen.
+    send(OrchestratorToPlanet::Sunray(Sunray::default()));
+    send(OrchestratorToPlanet::Sunray(Sunray::default()));
+    expl_tx
+        .send(ExplorerToPlanet::GenerateResourceRequest {
+            explorer_id: 1,
+            resource: BasicResourceType::Oxygen,
+        })
+        .unwrap();
+    pte_rx
+        .recv_timeout(Duration::from_millis(500))
+        .expect("No message received");
+    send(OrchestratorToPlanet::Asteroid(Asteroid::default()));
+    send(OrchestratorToPlanet::StopPlanetAI);
+    drop(orch_tx);
+    assert!(handle.join().is_ok());
+
+    let planet_id = 0;
+    assert_eq!(
+        audit_rx.try_iter().collect::<Vec<_>>(),
+        [
+            AuditEvent::Started { planet_id },
+            AuditEvent::SunrayCharged { planet_id, cell: 0 },
+            AuditEvent::RocketBuilt { planet_id },
+            AuditEvent::SunrayCharged { planet_id, cell: 0 },
+            AuditEvent::ResourceGenerated {
+                planet_id,
+                explorer_id: 1,
+                resource: BasicResourceType::Oxygen,
+            },
+            AuditEvent::RocketLaunched { planet_id },
+            AuditEvent::Stopped { planet_id },
+        ]
+    );
+}
+
+#[test]
+fn test_rocket_launch_hook_counts_launches() {
+    setup_logger();
+    let launches = Arc::new(AtomicUsize::new(0));
+    let counter = Arc::clone(&launches);
+    let (orch_tx, orch_rx) = crossbeam_channel::unbounded();
+    let (planet_tx, planet_rx) = crossbeam_channel::unbounded();
+    let (_expl_tx, expl_rx) = crossbeam_channel::unbounded();
+    let mut trip = TripBuilder::new(0)
+        .on_rocket_launched(Box::new(move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+        }))
+        .channels(orch_rx, planet_tx, expl_rx)
+        .build()
+        .unwrap();
+    let handle = thread::spawn(move || trip.run());
+    let send = |msg| {
+        orch_tx.send(msg).unwrap();
+        planet_rx
+            .recv_timeout(Duration::from_millis(500))
+            .expect("No message received")
+    };
+
+    send(OrchestratorToPlanet::StartPlanetAI);
+    for _ in 0..2 {
+        send(OrchestratorToPlanet::Sunray(Sunray::default()));
+        match send(OrchestratorToPlanet::Asteroid(Asteroid::default())) {
+            PlanetToOrchestrator::AsteroidAck {
+                rocket: Some(_), ..
+            } => {}
+            _other => panic!("Wrong response received"),
+        }
+    }
+    assert_eq!(launches.load(Ordering::SeqCst), 2);
+
+    // Without a rocket, nothing is launched and the hook is not called.
+    send(OrchestratorToPlanet::Asteroid(Asteroid::default()));
+    assert_eq!(launches.load(Ordering::SeqCst), 2);
+
+    drop(orch_tx);
+    assert!(handle.join().is_ok());
+}
+
+#[test]
+fn test_planet_incoming_expl_replaces_sender() {
+    setup_logger();
+    let harness = common::TestHarness::setup();
+    harness.start();
+
+    let mut receivers = Vec::new();
+    for _ in 0..2 {
+        let (expl_tx, expl_rx) = crossbeam_channel::unbounded();
+        harness
+            .orch_tx
+            .send(IncomingExplorerRequest {
+                explorer_id: 0,
+                new_sender: expl_tx,
+            })
+            .expect("Failed to send incoming explorer message");
+        match harness.recv_pto_with_timeout() {
+            PlanetToOrchestrator::IncomingExplorerResponse {
+                planet_id: 0,
+                explorer_id: 0,
+                res: Ok(()),
+            } => {}
+            _other => panic!("Wrong response received"),
+        }
+        receivers.push(expl_rx);
+    }
+
+    harness
+        .expl_tx
+        .send(ExplorerToPlanet::AvailableEnergyCellRequest { explorer_id: 0 })
+        .expect("Failed to send energy cell message");
+
+    match receivers[1]
+        .recv_timeout(Duration::from_millis(500))
+        .expect("No message received")
+    {
+        PlanetToExplorer::AvailableEnergyCellResponse { .. } => {}
+        _other => panic!("Wrong response received"),
+    }
+    assert!(
+        receivers[0].try_recv().is_err(),
+        "Stale sender must be replaced"
+    );
+
+    let result = harness.stop_and_join();
+    assert!(result.is_ok());
+}
+
+#[test]
+#[allow(deprecated)]
+fn test_run_with_deadline_returns_on_disconnect() {
+    setup_logger();
+    let (orch_tx, orch_rx) = crossbeam_channel::unbounded();
+    let (planet_tx, _planet_rx) = crossbeam_channel::unbounded();
+    let (_expl_tx, expl_rx) = crossbeam_channel::unbounded();
+
+    let mut trip = trip(0, orch_rx, planet_tx, expl_rx).unwrap();
+    let deadline = Instant::now() + Duration::from_secs(5);
+    let handle = thread::spawn(move || trip.run_with_deadline(deadline));
+
+    orch_tx
+        .send(OrchestratorToPlanet::StartPlanetAI)
+        .expect("Failed to send start message");
+    drop(orch_tx);
+
+    let result = handle.join().expect("planet thread panicked");
+    assert_eq!(result, Ok(()));
+    assert!(Instant::now() < deadline, "run outlived its deadline");
+}
+
+#[test]
+#[allow(deprecated)]
+fn test_run_with_deadline_stops_when_deadline_passes() {
+    setup_logger();
+    let (orch_tx, orch_rx) = crossbeam_channel::unbounded();
+    let (planet_tx, _planet_rx) = crossbeam_channel::unbounded();
+    let (_expl_tx, expl_rx) = crossbeam_channel::unbounded();
+
+    let mut trip = trip(0, orch_rx, planet_tx, expl_rx).unwrap();
+    orch_tx
+        .send(OrchestratorToPlanet::StartPlanetAI)
+        .expect("Failed to send start message");
+
+    let start = Instant::now();
+    let result = trip.run_with_deadline(start + Duration::from_millis(100));
+
+    assert_eq!(result, Ok(()));
+    assert!(start.elapsed() < Duration::from_secs(2));
+    // The orchestrator is still connected, the planet just stopped listening.
+    drop(orch_tx);
+}
+
+#[test]
+fn test_run_until_reports_deadline_after_processing_queued_messages() {
+    setup_logger();
+    let (orch_tx, orch_rx) = crossbeam_channel::unbounded();
+    let (planet_tx, planet_rx) = crossbeam_channel::unbounded();
+    let (_expl_tx, expl_rx) = crossbeam_channel::unbounded();
+
+    let mut trip = trip(0, orch_rx, planet_tx, expl_rx).unwrap();
+    for msg in [
+        OrchestratorToPlanet::StartPlanetAI,
+        OrchestratorToPlanet::Sunray(Sunray::default()),
+        OrchestratorToPlanet::Sunray(Sunray::default()),
+    ] {
+        orch_tx.send(msg).expect("Failed to send message");
+    }
+
+    let start = Instant::now();
+    let report = trip.run_until(start + Duration::from_millis(100));
+    let elapsed = start.elapsed();
+
+    assert_eq!(report.reason, Termination::DeadlineReached);
+    assert_eq!(report.messages_processed, 3);
+    assert!(
+        elapsed >= Duration::from_millis(100),
+        "returned early: {elapsed:?}"
+    );
+    assert!(
+        elapsed < Duration::from_secs(1),
+        "returned late: {elapsed:?}"
+    );
+    assert_eq!(planet_rx.try_iter().count(), 3);
+    drop(orch_tx);
+}
+
+#[test]
+fn test_run_stops_when_orchestrator_receiver_is_dropped() {
+    setup_logger();
+    let (orch_tx, orch_rx) = crossbeam_channel::unbounded();
+    let (planet_tx, planet_rx) = crossbeam_channel::unbounded();
+    let (_expl_tx, expl_rx) = crossbeam_channel::unbounded();
+
+    let mut trip = trip(0, orch_rx, planet_tx, expl_rx).unwrap();
+    let (done_tx, done_rx) = crossbeam_channel::bounded(1);
+    thread::spawn(move || done_tx.send(trip.run()));
+
+    drop(planet_rx);
+    orch_tx
+        .send(OrchestratorToPlanet::StartPlanetAI)
+        .expect("Failed to send start message");
+
+    let result = done_rx
+        .recv_timeout(Duration::from_secs(2))
+        .expect("run must terminate once replies cannot be delivered");
+    assert_eq!(result.reason, Termination::OrchestratorDisconnected);
+}
+
+#[test]
+fn test_defense_events_distinguish_defended_and_undefended() {
+    setup_logger();
+    let (orch_tx, orch_rx) = crossbeam_channel::unbounded();
+    let (planet_tx, planet_rx) = crossbeam_channel::unbounded();
+    let (_expl_tx, expl_rx) = crossbeam_channel::unbounded();
+    let (events_tx, events_rx) = crossbeam_channel::unbounded();
+
+    let mut trip = TripBuilder::new(3)
+        .channels(orch_rx, planet_tx, expl_rx)
+        .defense_events(events_tx)
+        .build()
+        .unwrap();
+    let handle = thread::spawn(move || trip.run());
+
+    let recv = || {
+        planet_rx
+            .recv_timeout(Duration::from_millis(500))
+            .expect("No message received")
+    };
+    for msg in [
+        OrchestratorToPlanet::StartPlanetAI,
+        OrchestratorToPlanet::Sunray(Sunray::default()),
+        OrchestratorToPlanet::Asteroid(Asteroid::default()),
+        OrchestratorToPlanet::Asteroid(Asteroid::default()),
+    ] {
+        orch_tx.send(msg).expect("Failed to send message");
+        recv();
+    }
+
+    assert_eq!(
+        events_rx.try_iter().collect::<Vec<_>>(),
+        vec![
+            DefenseEvent::Defended { planet_id: 3 },
+            DefenseEvent::Undefended { planet_id: 3 },
+        ]
+    );
+
+    drop(orch_tx);
+    let _ = handle.join();
+}
+
+#[test]
+fn test_supported_resources_can_be_generated() {
+    setup_logger();
+    let (orch_tx, orch_rx) = crossbeam_channel::unbounded();
+    let (planet_tx, planet_rx) = crossbeam_channel::unbounded();
+    let (expl_tx, expl_rx) = crossbeam_channel::unbounded();
+
+    let mut trip = TripBuilder::new(0)
+        .planet_type(PlanetType::D)
+        .generation_rules(vec![
+            BasicResourceType::Hydrogen,
+            BasicResourceType::Silicon,
+        ])
+        .channels(orch_rx, planet_tx, expl_rx)
+        .build()
+        .unwrap();
+    let handle = thread::spawn(move || trip.run());
+
+    let (to_expl_tx, to_expl_rx) = crossbeam_channel::unbounded();
+    for msg in [
+        OrchestratorToPlanet::StartPlanetAI,
+        IncomingExplorerRequest {
+            explorer_id: 0,
+            new_sender: to_expl_tx,
+        },
+    ] {
+        orch_tx.send(msg).expect("Failed to send message");
+        planet_rx
+            .recv_timeout(Duration::from_millis(500))
+            .expect("No message received");
+    }
+
+    expl_tx
+        .send(ExplorerToPlanet::SupportedResourceRequest { explorer_id: 0 })
+        .expect("Failed to send supported resource message");
+    let PlanetToExplorer::SupportedResourceResponse { resource_list } = to_expl_rx
+        .recv_timeout(Duration::from_millis(500))
+        .expect("No message received")
+    else {
+        panic!("Wrong response received");
+    };
+    assert_eq!(resource_list.len(), 2);
+
+    for resource in resource_list {
+        orch_tx
+            .send(OrchestratorToPlanet::Sunray(Sunray::default()))
+            .expect("Failed to send sunray message");
+        planet_rx
+            .recv_timeout(Duration::from_millis(500))
+            .expect("No message received");
+        expl_tx
+            .send(ExplorerToPlanet::GenerateResourceRequest {
+                explorer_id: 0,
+                resource,
+            })
+            .expect("Failed to send generate message");
+        match to_expl_rx.recv_timeout(Duration::from_millis(500)) {
+            Ok(PlanetToExplorer::GenerateResourceResponse {
+                resource: Some(generated),
+            }) => assert_eq!(generated.get_type(), resource),
+            _other => panic!("Supported {resource:?} must be generated"),
+        }
+    }
+
+    drop(orch_tx);
+    assert!(handle.join().is_ok());
+}
+
+#[test]
+fn test_stalled_explorer_reply_is_dropped() {
+    setup_logger();
+    let harness = common::TestHarness::setup();
+    harness.start();
+
+    // Nobody ever receives on a zero capacity channel, so every send times out.
+    let (stalled_tx, stalled_rx) = crossbeam_channel::bounded(0);
+    harness
+        .orch_tx
+        .send(IncomingExplorerRequest {
+            explorer_id: 1,
+            new_sender: stalled_tx,
+        })
+        .expect("Failed to send incoming explorer message");
+    assert!(matches!(
+        harness.recv_pto_with_timeout(),
+        PlanetToOrchestrator::IncomingExplorerResponse { res: Ok(()), .. }
+    ));
+
+    harness.send_explorer(ExplorerToPlanet::SupportedResourceRequest { explorer_id: 1 });
+    harness
+        .orch_tx
+        .send(OrchestratorToPlanet::InternalStateRequest)
+        .expect("Failed to send internal state request");
+    assert!(
+        matches!(
+            harness.recv_pto_with_timeout(),
+            PlanetToOrchestrator::InternalStateResponse { .. }
+        ),
+        "A stalled explorer must not stall the planet"
+    );
+
+    thread::sleep(Duration::from_millis(100));
+    assert!(
+        stalled_rx.try_recv().is_err(),
+        "The reply must have been dropped"
+    );
+    assert!(harness.stop_and_join().is_ok());
+}
+
+#[test]
+fn test_housekeeping_rebuilds_reserve_while_idle() {
+    setup_logger();
+    let (orch_tx, orch_rx) = crossbeam_channel::unbounded();
+    let (planet_tx, planet_rx) = crossbeam_channel::unbounded();
+    let (_expl_tx, expl_rx) = crossbeam_channel::unbounded();
+
+    let mut trip = TripBuilder::new(0)
+        .channels(orch_rx, planet_tx, expl_rx)
+        .housekeeping(Duration::from_millis(20))
+        .build()
+        .unwrap();
+    let handle = thread::spawn(move || trip.run());
+
+    let send = |msg| {
+        orch_tx.send(msg).expect("Failed to send message");
+        planet_rx
+            .recv_timeout(Duration::from_millis(500))
+            .expect("No message received")
+    };
+    send(OrchestratorToPlanet::StartPlanetAI);
+    // The first sunray becomes the reserve rocket, the next two charge cells.
+    for _ in 0..3 {
+        send(OrchestratorToPlanet::Sunray(Sunray::default()));
+    }
+    let PlanetToOrchestrator::AsteroidAck {
+        rocket: Some(_), ..
+    } = send(OrchestratorToPlanet::Asteroid(Asteroid::default()))
+    else {
+        panic!("Asteroid must be defended");
+    };
+
+    thread::sleep(Duration::from_millis(200));
+    match send(OrchestratorToPlanet::InternalStateRequest) {
+        PlanetToOrchestrator::InternalStateResponse { planet_state, .. } => {
+            assert!(
+                planet_state.has_rocket,
+                "Reserve must be rebuilt while idle"
+            );
+            assert_eq!(planet_state.charged_cells_count, 1);
+        }
+        _other => panic!("Wrong response received"),
+    }
+    assert!(
+        planet_rx.try_recv().is_err(),
+        "Housekeeping must not reach the orchestrator"
+    );
+
+    drop(orch_tx);
+    let _ = handle.join();
+}
+
+#[test]
+fn test_housekeeping_leaves_every_explorer_id_to_explorers() {
+    setup_logger();
+    let (orch_tx, orch_rx) = crossbeam_channel::unbounded();
+    let (planet_tx, planet_rx) = crossbeam_channel::unbounded();
+    let (expl_tx, expl_rx) = crossbeam_channel::unbounded();
+    let (pte_tx, pte_rx) = crossbeam_channel::unbounded();
+
+    let mut trip = TripBuilder::new(0)
+        .channels(orch_rx, planet_tx, expl_rx)
+        .housekeeping(Duration::from_millis(10))
+        .build()
+        .unwrap();
+    let handle = thread::spawn(move || trip.run());
+    let send = |msg| {
+        orch_tx.send(msg).expect("Failed to send message");
+        planet_rx
+            .recv_timeout(Duration::from_millis(500))
+            .expect("No message received")
+    };
+
+    send(OrchestratorToPlanet::StartPlanetAI);
+    assert!(matches!(
+        send(IncomingExplorerRequest {
+            explorer_id: u32::MAX,
+            new_sender: pte_tx,
+        }),
+        PlanetToOrchestrator::IncomingExplorerResponse {
+            explorer_id: u32::MAX,
+            res: Ok(()),
+            ..
+        }
+    ));
+    thread::sleep(Duration::from_millis(50));
+    expl_tx
+        .send(ExplorerToPlanet::AvailableEnergyCellRequest {
+            explorer_id: u32::MAX,
+        })
+        .unwrap();
+    assert!(matches!(
+        pte_rx.recv_timeout(Duration::from_millis(500)),
+        Ok(PlanetToExplorer::AvailableEnergyCellResponse { .. })
+    ));
+    assert!(
+        planet_rx.try_recv().is_err(),
+        "Housekeeping must not reach the orchestrator"
+    );
+
+    drop(orch_tx);
+    let _ = handle.join();
+}
+
+/// Planet AI that only counts the sunrays it is handed.
+struct SunrayCounter(Arc<AtomicUsize>);
+
+impl PlanetAI for SunrayCounter {
+    fn handle_sunray(
+        &mut self,
+        _state: &mut PlanetState,
+        _generator: &Generator,
+        _combinator: &Combinator,
+        _sunray: Sunray,
+    ) {
+        self.0.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn handle_asteroid(
+        &mut self,
+        _state: &mut PlanetState,
+        _generator: &Generator,
+        _combinator: &Combinator,
+    ) -> Option<Rocket> {
+        None
+    }
+
+    fn handle_internal_state_req(
+        &mut self,
+        state: &mut PlanetState,
+        _generator: &Generator,
+        _combinator: &Combinator,
+    ) -> DummyPlanetState {
+        state.to_dummy()
+    }
+
+    fn handle_explorer_msg(
+        &mut self,
+        _state: &mut PlanetState,
+        _generator: &Generator,
+        _combinator: &Combinator,
+        _msg: ExplorerToPlanet,
+    ) -> Option<PlanetToExplorer> {
+        None
+    }
+}
+
+#[test]
+fn test_trip_with_injected_ai() {
+    setup_logger();
+    let (orch_tx, orch_rx) = crossbeam_channel::unbounded();
+    let (planet_tx, planet_rx) = crossbeam_channel::unbounded();
+    let (_expl_tx, expl_rx) = crossbeam_channel::unbounded();
+
+    let sunrays = Arc::new(AtomicUsize::new(0));
+    let ai = Box::new(SunrayCounter(Arc::clone(&sunrays)));
+    let mut trip = trip_with_ai(0, ai, orch_rx, planet_tx, expl_rx).unwrap();
+    let handle = thread::spawn(move || trip.run());
+
+    orch_tx.send(OrchestratorToPlanet::StartPlanetAI).unwrap();
+    planet_rx.recv_timeout(Duration::from_millis(500)).unwrap();
+    for _ in 0..2 {
+        orch_tx
+            .send(OrchestratorToPlanet::Sunray(Sunray::default()))
+            .unwrap();
+        match planet_rx.recv_timeout(Duration::from_millis(500)) {
+            Ok(PlanetToOrchestrator::SunrayAck { planet_id: 0 }) => {}
+            _other => panic!("Wrong response received"),
+        }
+    }
+    assert_eq!(sunrays.load(Ordering::SeqCst), 2);
+
+    // The stub never builds rockets, so the asteroid goes undefended.
+    orch_tx
+        .send(OrchestratorToPlanet::Asteroid(Asteroid::default()))
+        .unwrap();
+    match planet_rx.recv_timeout(Duration::from_millis(500)) {
+        Ok(PlanetToOrchestrator::AsteroidAck { rocket: None, .. }) => {}
+        _other => panic!("Wrong response received"),
+    }
+
+    drop(orch_tx);
+    assert!(handle.join().is_ok());
+}
+
+/// Answers explorer requests only once let through its gate, to keep the
+/// planet busy while requests pile up.
+struct GatedExplorers {
+    entered: crossbeam_channel::Sender<()>,
+    gate: crossbeam_channel::Receiver<()>,
+}
+
+impl PlanetAI for GatedExplorers {
+    fn handle_sunray(
+        &mut self,
+        _state: &mut PlanetState,
+        _generator: &Generator,
+        _combinator: &Combinator,
+        _sunray: Sunray,
+    ) {
+    }
+
+    fn handle_asteroid(
+        &mut self,
+        _state: &mut PlanetState,
+        _generator: &Generator,
+        _combinator: &Combinator,
+    ) -> Option<Rocket> {
+        None
+    }
+
+    fn handle_internal_state_req(
+        &mut self,
+        state: &mut PlanetState,
+        _generator: &Generator,
+        _combinator: &Combinator,
+    ) -> DummyPlanetState {
+        state.to_dummy()
+    }
+
+    fn handle_explorer_msg(
+        &mut self,
+        _state: &mut PlanetState,
+        _generator: &Generator,
+        _combinator: &Combinator,
+        _msg: ExplorerToPlanet,
+    ) -> Option<PlanetToExplorer> {
+        let _ = self.entered.send(());
+        self.gate.recv().ok()?;
+        Some(PlanetToExplorer::AvailableEnergyCellResponse { available_cells: 0 })
+    }
+}
+
+#[test]
+fn test_explorer_queue_cap_rejects_overflow() {
+    setup_logger();
+    let (orch_tx, orch_rx) = crossbeam_channel::unbounded();
+    let (planet_tx, planet_rx) = crossbeam_channel::unbounded();
+    let (expl_tx, expl_rx) = crossbeam_channel::unbounded();
+    let (to_explorer, from_planet) = crossbeam_channel::unbounded();
+    let (entered_tx, entered_rx) = crossbeam_channel::unbounded();
+    let (gate_tx, gate_rx) = crossbeam_channel::unbounded();
+    let ai = GatedExplorers {
+        entered: entered_tx,
+        gate: gate_rx,
+    };
+    let mut trip = TripBuilder::new(0)
+        .ai(Box::new(ai))
+        .max_queued_per_explorer(2)
+        .channels(orch_rx, planet_tx, expl_rx)
+        .build()
+        .unwrap();
+    let handle = thread::spawn(move || {
+        let _ = trip.run();
+        trip
+    });
+
+    orch_tx.send(OrchestratorToPlanet::StartPlanetAI).unwrap();
+    planet_rx.recv_timeout(Duration::from_millis(500)).unwrap();
+    orch_tx
+        .send(IncomingExplorerRequest {
+            explorer_id: 1,
+            new_sender: to_explorer,
+        })
+        .unwrap();
+    planet_rx.recv_timeout(Duration::from_millis(500)).unwrap();
+
+    let request = || ExplorerToPlanet::AvailableEnergyCellRequest { explorer_id: 1 };
+    expl_tx.send(request()).unwrap();
+    entered_rx
+        .recv_timeout(Duration::from_millis(500))
+        .expect("The planet never picked up the request");
+    // The planet is busy with the first request: two more fit in the
+    // queue, the last one is rejected.
+    for _ in 0..3 {
+        expl_tx.send(request()).unwrap();
+    }
+    thread::sleep(Duration::from_millis(50));
+    for _ in 0..3 {
+        gate_tx.send(()).unwrap();
+    }
+    for _ in 0..3 {
+        match from_planet.recv_timeout(Duration::from_millis(500)) {
+            Ok(PlanetToExplorer::AvailableEnergyCellResponse { .. }) => {}
+            _other => panic!("Wrong response received"),
+        }
+    }
+    assert!(
+        from_planet
+            .recv_timeout(Duration::from_millis(100))
+            .is_err()
+    );
+
+    drop(orch_tx);
+    let trip = handle.join().unwrap();
+    assert_eq!(trip.metrics().explorer_requests_rejected, 1);
+}
+
+#[test]
+fn test_snapshot_agrees_with_internal_state() {
+    setup_logger();
+    let (orch_tx, orch_rx) = crossbeam_channel::unbounded();
+    let (planet_tx, planet_rx) = crossbeam_channel::unbounded();
+    let (_expl_tx, expl_rx) = crossbeam_channel::unbounded();
+    let mut trip = TripBuilder::new(0)
+        .ai_config(AiConfig {
+            rocket_reserve: 3,
+            auto_build: true,
+            ..AiConfig::default()
+        })
+        .channels(orch_rx, planet_tx, expl_rx)
+        .build()
+        .unwrap();
+
+    let snapshot = trip.snapshot();
+    assert_eq!(snapshot.charged_cells, 0);
+    assert_eq!(snapshot.total_cells, trip.energy_cell_capacity());
+    assert_eq!(snapshot.rocket_reserve, 0);
+    assert!(!snapshot.has_rocket);
+    assert!(!snapshot.is_running);
+
+    let handle = thread::spawn(move || {
+        let _ = trip.run();
+        trip
+    });
+    let send = |msg| {
+        orch_tx.send(msg).unwrap();
+        planet_rx
+            .recv_timeout(Duration::from_millis(500))
+            .expect("No message received")
+    };
+    send(OrchestratorToPlanet::StartPlanetAI);
+    for _ in 0..4 {
+        send(OrchestratorToPlanet::Sunray(Sunray::default()));
+    }
+    send(OrchestratorToPlanet::Asteroid(Asteroid::default()));
+    let PlanetToOrchestrator::InternalStateResponse { planet_state, .. } =
+        send(OrchestratorToPlanet::InternalStateRequest)
+    else {
+        panic!("Wrong response received");
+    };
+    drop(orch_tx);
+    let trip = handle.join().unwrap();
+
+    let snapshot = trip.snapshot();
+    assert_eq!(snapshot.charged_cells, planet_state.charged_cells_count);
+    assert_eq!(snapshot.has_rocket, planet_state.has_rocket);
+    assert_eq!(snapshot.rocket_reserve, 2);
+    assert_eq!(snapshot.metrics.sunrays, 4);
+    assert_eq!(snapshot.metrics.rockets_launched, 1);
+    assert_eq!(snapshot.metrics.charged_cells, snapshot.charged_cells);
+    assert_eq!(snapshot.metrics.stockpiled_rockets, 2);
+}
+
+/// Records which handlers ran, in order.
+struct HandlerLog(Arc<Mutex<Vec<&'static str>>>);
+
+impl PlanetAI for HandlerLog {
+    fn handle_sunray(
+        &mut self,
+        _state: &mut PlanetState,
+        _generator: &Generator,
+        _combinator: &Combinator,
+        _sunray: Sunray,
+    ) {
+        self.0.lock().unwrap().push("sunray");
+    }
+
+    fn handle_asteroid(
+        &mut self,
+        _state: &mut PlanetState,
+        _generator: &Generator,
+        _combinator: &Combinator,
+    ) -> Option<Rocket> {
+        self.0.lock().unwrap().push("asteroid");
+        None
+    }
+
+    fn handle_internal_state_req(
+        &mut self,
+        state: &mut PlanetState,
+        _generator: &Generator,
+        _combinator: &Combinator,
+    ) -> DummyPlanetState {
+        state.to_dummy()
+    }
+
+    fn handle_explorer_msg(
+        &mut self,
+        _state: &mut PlanetState,
+        _generator: &Generator,
+        _combinator: &Combinator,
+        _msg: ExplorerToPlanet,
+    ) -> Option<PlanetToExplorer> {
+        self.0.lock().unwrap().push("explorer");
+        Some(PlanetToExplorer::AvailableEnergyCellResponse { available_cells: 0 })
+    }
+}
+
+#[test]
+fn test_asteroid_goes_before_explorer_request() {
+    setup_logger();
+    let (orch_tx, orch_rx) = crossbeam_channel::unbounded();
+    // Room for one reply only, so the relay blocks on the second.
+    let (planet_tx, planet_rx) = crossbeam_channel::bounded(1);
+    let (expl_tx, expl_rx) = crossbeam_channel::unbounded();
+    let (to_explorer, from_planet) = crossbeam_channel::unbounded();
+    let log = Arc::new(Mutex::new(Vec::new()));
+    let mut trip = TripBuilder::new(0)
+        .ai(Box::new(HandlerLog(Arc::clone(&log))))
+        .channels(orch_rx, planet_tx, expl_rx)
+        .build()
+        .unwrap();
+    let control = trip.handle();
+    let handle = thread::spawn(move || trip.run());
+
+    orch_tx.send(OrchestratorToPlanet::StartPlanetAI).unwrap();
+    orch_tx
+        .send(IncomingExplorerRequest {
+            explorer_id: 1,
+            new_sender: to_explorer,
+        })
+        .unwrap();
+    // The relay is stuck delivering the second reply, so the explorer
+    // request and the asteroid are both ready when it gets going again.
+    thread::sleep(Duration::from_millis(50));
+    expl_tx
+        .send(ExplorerToPlanet::AvailableEnergyCellRequest { explorer_id: 1 })
+        .unwrap();
+    orch_tx
+        .send(OrchestratorToPlanet::Asteroid(Asteroid::default()))
+        .unwrap();
+    for _ in 0..3 {
+        planet_rx
+            .recv_timeout(Duration::from_millis(500))
+            .expect("No message received");
+    }
+    from_planet
+        .recv_timeout(Duration::from_millis(500))
+        .expect("No explorer reply received");
+
+    control.shutdown();
+    assert_eq!(
+        handle.join().unwrap().reason,
+        Termination::ShutdownRequested
+    );
+    assert_eq!(*log.lock().unwrap(), ["asteroid", "explorer"]);
+}
+
+#[test]
+fn test_trip_from_hand_built_planet() {
+    setup_logger();
+    let (orch_tx, orch_rx) = crossbeam_channel::unbounded();
+    let (planet_tx, planet_rx) = crossbeam_channel::unbounded();
+    let (_expl_tx, expl_rx) = crossbeam_channel::unbounded();
+
+    let sunrays = Arc::new(AtomicUsize::new(0));
+    let planet = Planet::new(
+        5,
+        PlanetType::D,
+        Box::new(SunrayCounter(Arc::clone(&sunrays))),
+        vec![BasicResourceType::Oxygen, BasicResourceType::Carbon],
+        vec![],
+        (orch_rx, planet_tx),
+        expl_rx,
+    )
+    .unwrap();
+    let mut trip = Trip::from_planet(planet);
+    assert_eq!(trip.planet_id(), 5);
+    assert!(matches!(trip.planet_type(), PlanetType::D));
+    assert_eq!(trip.planet_info().supported_resources.len(), 2);
+    let handle = thread::spawn(move || trip.run());
+
+    let send = |msg| {
+        orch_tx.send(msg).expect("Failed to send message");
+        planet_rx
+            .recv_timeout(Duration::from_millis(500))
+            .expect("No message received")
+    };
+    send(OrchestratorToPlanet::StartPlanetAI);
+    match send(OrchestratorToPlanet::Sunray(Sunray::default())) {
+        PlanetToOrchestrator::SunrayAck { planet_id: 5 } => {}
+        _other => panic!("Wrong response received"),
+    }
+    assert_eq!(sunrays.load(Ordering::SeqCst), 1);
+    send(OrchestratorToPlanet::KillPlanet);
+
+    let report = handle.join().expect("Planet thread panicked");
+    assert_eq!(report.reason, Termination::Killed);
+}
+
+/// AI panicking on asteroids and internal state requests, counting sunrays.
+struct FragileAi(Arc<AtomicUsize>);
+
+impl PlanetAI for FragileAi {
+    fn handle_sunray(
+        &mut self,
+        _state: &mut PlanetState,
+        _generator: &Generator,
+        _combinator: &Combinator,
+        _sunray: Sunray,
+    ) {
+        self.0.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn handle_asteroid(
+        &mut self,
+        _state: &mut PlanetState,
+        _generator: &Generator,
+        _combinator: &Combinator,
+    ) -> Option<Rocket> {
+        panic!("asteroid handling is broken");
+    }
+
+    fn handle_internal_state_req(
+        &mut self,
+        _state: &mut PlanetState,
+        _generator: &Generator,
+        _combinator: &Combinator,
+    ) -> DummyPlanetState {
+        panic!("internal state handling is broken");
+    }
+
+    fn handle_explorer_msg(
+        &mut self,
+        _state: &mut PlanetState,
+        _generator: &Generator,
+        _combinator: &Combinator,
+        _msg: ExplorerToPlanet,
+    ) -> Option<PlanetToExplorer> {
+        None
+    }
+}
+
+#[test]
+fn test_planet_survives_panicking_ai() {
+    setup_logger();
+    let (orch_tx, orch_rx) = crossbeam_channel::unbounded();
+    let (planet_tx, planet_rx) = crossbeam_channel::unbounded();
+    let (_expl_tx, expl_rx) = crossbeam_channel::unbounded();
+
+    let sunrays = Arc::new(AtomicUsize::new(0));
+    let ai = Box::new(FragileAi(Arc::clone(&sunrays)));
+    let mut trip = trip_with_ai(0, ai, orch_rx, planet_tx, expl_rx).unwrap();
+    let handle = thread::spawn(move || trip.run());
+
+    let send = |msg| {
+        orch_tx.send(msg).expect("Failed to send message");
+        planet_rx
+            .recv_timeout(Duration::from_millis(500))
+            .expect("No message received")
+    };
+    send(OrchestratorToPlanet::StartPlanetAI);
+    assert!(matches!(
+        send(OrchestratorToPlanet::Asteroid(Asteroid::default())),
+        PlanetToOrchestrator::AsteroidAck { rocket: None, .. }
+    ));
+    assert!(matches!(
+        send(OrchestratorToPlanet::InternalStateRequest),
+        PlanetToOrchestrator::InternalStateResponse { planet_id: 0, .. }
+    ));
+    assert!(
+        matches!(
+            send(OrchestratorToPlanet::Sunray(Sunray::default())),
+            PlanetToOrchestrator::SunrayAck { planet_id: 0 }
+        ),
+        "The planet must keep answering after a panic"
+    );
+    assert_eq!(sunrays.load(Ordering::SeqCst), 1);
+
+    drop(orch_tx);
+    let report = handle.join().expect("The planet thread must not panic");
+    assert_eq!(report.panics_recovered, 2);
+}
+
+#[test]
+fn test_metrics_count_mixed_workload() {
+    setup_logger();
+    let (orch_tx, orch_rx) = crossbeam_channel::unbounded();
+    let (planet_tx, planet_rx) = crossbeam_channel::unbounded();
+    let (expl_tx, expl_rx) = crossbeam_channel::unbounded();
+    let (pte_tx, pte_rx) = crossbeam_channel::unbounded();
+
+    let mut trip = trip(0, orch_rx, planet_tx, expl_rx).unwrap();
+    assert_eq!(
+        trip.metrics(),
+        TripMetrics {
+            health: 100,
+            ..TripMetrics::default()
+        }
+    );
+    let handle = thread::spawn(move || {
+        let result = trip.run();
+        (trip, result)
+    });
+
+    let send = |msg| {
+        orch_tx.send(msg).expect("Failed to send message");
+        planet_rx
+            .recv_timeout(Duration::from_millis(500))
+            .expect("No message received")
+    };
+    send(OrchestratorToPlanet::StartPlanetAI);
+    send(IncomingExplorerRequest {
+        explorer_id: 1,
+        new_sender: pte_tx,
+    });
+    // The first sunray builds the reserve rocket, the next two charge cells.
+    for _ in 0..3 {
+        send(OrchestratorToPlanet::Sunray(Sunray::default()));
+    }
+    for msg in [
+        ExplorerToPlanet::SupportedResourceRequest { explorer_id: 1 },
+        ExplorerToPlanet::GenerateResourceRequest {
+            explorer_id: 1,
+            resource: BasicResourceType::Oxygen,
+        },
+    ] {
+        expl_tx.send(msg).unwrap();
+        pte_rx.recv_timeout(Duration::from_millis(500)).unwrap();
+    }
+    send(OrchestratorToPlanet::Asteroid(Asteroid::default()));
+    send(OrchestratorToPlanet::InternalStateRequest);
+
+    drop(orch_tx);
+    let (trip, _) = handle.join().expect("Planet thread panicked");
+    assert_eq!(
+        trip.metrics(),
+        TripMetrics {
+            sunrays: 3,
+            rockets_built: 1,
+            rockets_launched: 1,
+            resources_generated: 1,
+            yields: ResourceYields {
+                oxygen: 1,
+                ..ResourceYields::default()
+            },
+            complex_resources_produced: 0,
+            complex_yields: ComplexYields::default(),
+            explorers_served: 2,
+            rocket_build_failures: 0,
+            stockpiled_rockets: 0,
+            has_rocket: false,
+            charged_cells: 1,
+            explorers: 1,
+            health: 100,
+            rate_limited_requests: 0,
+            dropped_while_stopped: DroppedWhileStopped::default(),
+            explorer_requests_rejected: 0,
+        }
+    );
+}
+
+#[test]
+fn test_stop_drains_pending_explorer_requests() {
+    setup_logger();
+    let (orch_tx, orch_rx) = crossbeam_channel::unbounded();
+    let (planet_tx, planet_rx) = crossbeam_channel::unbounded();
+    let (expl_tx, expl_rx) = crossbeam_channel::unbounded();
+    let (pte_tx, pte_rx) = crossbeam_channel::unbounded();
+
+    let mut trip = TripBuilder::new(0)
+        .channels(orch_rx, planet_tx, expl_rx)
+        .drain_on_stop(8)
+        .build()
+        .unwrap();
+    let handle = thread::spawn(move || trip.run());
+
+    let send = |msg| {
+        orch_tx.send(msg).expect("Failed to send message");
+        planet_rx
+            .recv_timeout(Duration::from_millis(500))
+            .expect("No message received")
+    };
+    send(OrchestratorToPlanet::StartPlanetAI);
+    send(IncomingExplorerRequest {
+        explorer_id: 1,
+        new_sender: pte_tx,
+    });
+
+    for _ in 0..4 {
+        expl_tx
+            .send(ExplorerToPlanet::SupportedResourceRequest { explorer_id: 1 })
+            .unwrap();
+    }
+    match send(OrchestratorToPlanet::StopPlanetAI) {
+        PlanetToOrchestrator::StopPlanetAIResult { planet_id: 0 } => {}
+        _other => panic!("Wrong response received"),
+    }
+    for _ in 0..4 {
+        match pte_rx.recv_timeout(Duration::from_millis(500)) {
+            Ok(PlanetToExplorer::SupportedResourceResponse { .. }) => {}
+            other => panic!("Pending request must be answered, got {other:?}"),
+        }
+    }
+
+    drop(orch_tx);
+    let _ = handle.join();
+}
+
+/// Planet AI that takes a while to answer explorers.
+struct SlowExplorers(Duration);
+
+impl PlanetAI for SlowExplorers {
+    fn handle_sunray(
+        &mut self,
+        _state: &mut PlanetState,
+        _generator: &Generator,
+        _combinator: &Combinator,
+        _sunray: Sunray,
+    ) {
+    }
+
+    fn handle_asteroid(
+        &mut self,
+        _state: &mut PlanetState,
+        _generator: &Generator,
+        _combinator: &Combinator,
+    ) -> Option<Rocket> {
+        None
+    }
+
+    fn handle_internal_state_req(
+        &mut self,
+        state: &mut PlanetState,
+        _generator: &Generator,
+        _combinator: &Combinator,
+    ) -> DummyPlanetState {
+        state.to_dummy()
+    }
+
+    fn handle_explorer_msg(
+        &mut self,
+        _state: &mut PlanetState,
+        _generator: &Generator,
+        _combinator: &Combinator,
+        _msg: ExplorerToPlanet,
+    ) -> Option<PlanetToExplorer> {
+        thread::sleep(self.0);
+        Some(PlanetToExplorer::SupportedResourceResponse {
+            resource_list: HashSet::new(),
+        })
+    }
+}
+
+#[test]
+fn test_held_stop_keeps_replies_flowing() {
+    setup_logger();
+    let (orch_tx, orch_rx) = crossbeam_channel::unbounded();
+    let (planet_tx, planet_rx) = crossbeam_channel::unbounded();
+    let (expl_tx, expl_rx) = crossbeam_channel::unbounded();
+    let (pte_tx, pte_rx) = crossbeam_channel::unbounded();
+
+    let mut trip = TripBuilder::new(0)
+        .ai(Box::new(SlowExplorers(Duration::from_millis(40))))
+        .channels(orch_rx, planet_tx, expl_rx)
+        .drain_on_stop(8)
+        .build()
+        .unwrap();
+    let handle = thread::spawn(move || trip.run());
+
+    let send = |msg| {
+        orch_tx.send(msg).expect("Failed to send message");
+        planet_rx
+            .recv_timeout(Duration::from_millis(500))
+            .expect("No message received")
+    };
+    send(OrchestratorToPlanet::StartPlanetAI);
+    send(IncomingExplorerRequest {
+        explorer_id: 1,
+        new_sender: pte_tx,
+    });
+
+    for _ in 0..4 {
+        expl_tx
+            .send(ExplorerToPlanet::SupportedResourceRequest { explorer_id: 1 })
+            .unwrap();
+    }
+    let stopped_at = Instant::now();
+    orch_tx.send(OrchestratorToPlanet::StopPlanetAI).unwrap();
+    // The stop waits for the planet, the relay does not.
+    assert!(pte_rx.recv_timeout(Duration::from_millis(500)).is_ok());
+    assert!(
+        stopped_at.elapsed() < Duration::from_millis(90),
+        "The first reply waited for the stop: {:?}",
+        stopped_at.elapsed()
+    );
+    assert!(matches!(
+        planet_rx.recv_timeout(Duration::from_millis(500)),
+        Ok(PlanetToOrchestrator::StopPlanetAIResult { planet_id: 0 })
+    ));
+
+    drop(orch_tx);
+    let _ = handle.join();
+}
+
+#[test]
+fn test_is_running_follows_start_and_stop() {
+    setup_logger();
+    let (orch_tx, orch_rx) = crossbeam_channel::unbounded();
+    let (planet_tx, planet_rx) = crossbeam_channel::unbounded();
+    let (_expl_tx, expl_rx) = crossbeam_channel::unbounded();
+
+    let mut trip = trip(0, orch_rx, planet_tx, expl_rx).unwrap();
+    let status = trip.handle();
+    assert!(!trip.is_running());
+    let handle = thread::spawn(move || trip.run());
+
+    let send = |msg| {
+        orch_tx.send(msg).expect("Failed to send message");
+        planet_rx
+            .recv_timeout(Duration::from_millis(500))
+            .expect("No message received")
+    };
+    send(OrchestratorToPlanet::StartPlanetAI);
+    assert!(status.is_running());
+    send(OrchestratorToPlanet::StopPlanetAI);
+    assert!(!status.is_running());
+    send(OrchestratorToPlanet::StartPlanetAI);
+    assert!(status.is_running());
+
+    drop(orch_tx);
+    let _ = handle.join();
+    assert!(!status.is_running());
+}
+
+#[test]
+fn test_pause_and_resume_bypass_the_orchestrator() {
+    setup_logger();
+    let (orch_tx, orch_rx) = crossbeam_channel::unbounded();
+    let (planet_tx, planet_rx) = crossbeam_channel::unbounded();
+    let (_expl_tx, expl_rx) = crossbeam_channel::unbounded();
+
+    let mut trip = trip(0, orch_rx, planet_tx, expl_rx).unwrap();
+    let status = trip.handle();
+    let handle = thread::spawn(move || trip.run());
+
+    let send = |msg| {
+        orch_tx.send(msg).expect("Failed to send message");
+        planet_rx
+            .recv_timeout(Duration::from_millis(500))
+            .expect("No message received")
+    };
+    let wait_for = |running: bool| {
+        let deadline = Instant::now() + Duration::from_secs(1);
+        while status.is_running() != running {
+            assert!(Instant::now() < deadline, "is_running stayed {}", !running);
+            thread::sleep(Duration::from_millis(1));
+        }
+    };
+    send(OrchestratorToPlanet::StartPlanetAI);
+    assert!(status.is_running());
+
+    status.pause();
+    wait_for(false);
+    assert!(
+        matches!(
+            send(OrchestratorToPlanet::Sunray(Sunray::default())),
+            PlanetToOrchestrator::Stopped { .. }
+        ),
+        "A paused planet must answer like a stopped one"
+    );
+
+    status.resume();
+    wait_for(true);
+    assert!(matches!(
+        send(OrchestratorToPlanet::Sunray(Sunray::default())),
+        PlanetToOrchestrator::SunrayAck { .. }
+    ));
+    assert!(
+        planet_rx.try_recv().is_err(),
+        "Pause and resume must not be acknowledged to the orchestrator"
+    );
+
+    // A stop of the orchestrator is not undone by a resume.
+    status.pause();
+    wait_for(false);
+    send(OrchestratorToPlanet::StopPlanetAI);
+    status.resume();
+    thread::sleep(Duration::from_millis(50));
+    assert!(!status.is_running());
+
+    drop(orch_tx);
+    let _ = handle.join();
+}
+
+#[test]
+fn test_planet_info_reports_charged_and_total_cells() {
+    setup_logger();
+    let (orch_tx, orch_rx) = crossbeam_channel::unbounded();
+    let (planet_tx, planet_rx) = crossbeam_channel::unbounded();
+    let (_expl_tx, expl_rx) = crossbeam_channel::unbounded();
+
+    let mut trip = TripBuilder::new(0)
+        .ai_config(AiConfig {
+            auto_build: false,
+            ..AiConfig::default()
+        })
+        .channels(orch_rx, planet_tx, expl_rx)
+        .build()
+        .unwrap();
+    let info = trip.planet_info();
+    assert_eq!((info.available_cells, info.total_cells), (0, 5));
+
+    let control = trip.handle();
+    let handle = thread::spawn(move || {
+        assert_eq!(trip.run().reason, Termination::ShutdownRequested);
+        trip
+    });
+    let send = |msg| {
+        orch_tx.send(msg).expect("Failed to send message");
+        planet_rx
+            .recv_timeout(Duration::from_millis(500))
+            .expect("No message received")
+    };
+    send(OrchestratorToPlanet::StartPlanetAI);
+    for _ in 0..3 {
+        send(OrchestratorToPlanet::Sunray(Sunray::default()));
+    }
+
+    control.shutdown();
+    let info = handle.join().expect("Run loop panicked").planet_info();
+    assert_eq!((info.available_cells, info.total_cells), (3, 5));
+}
+
+#[test]
+fn test_disconnected_explorer_is_let_go_of_right_away() {
+    setup_logger();
+    let (orch_tx, orch_rx) = crossbeam_channel::unbounded();
+    let (planet_tx, planet_rx) = crossbeam_channel::unbounded();
+    let (expl_tx, expl_rx) = crossbeam_channel::unbounded();
+    let (pte_tx, pte_rx) = crossbeam_channel::unbounded();
+    let mut trip = trip(0, orch_rx, planet_tx, expl_rx).unwrap();
+    let handle = thread::spawn(move || {
+        let _ = trip.run();
+        trip
+    });
+    let send = |msg| {
+        orch_tx.send(msg).expect("Failed to send message");
+        planet_rx
+            .recv_timeout(Duration::from_millis(500))
+            .expect("No message received")
+    };
+    send(OrchestratorToPlanet::StartPlanetAI);
+    send(IncomingExplorerRequest {
+        explorer_id: 1,
+        new_sender: pte_tx,
+    });
+
+    // The reply cannot be delivered, which gives the explorer away.
+    drop(pte_rx);
+    expl_tx
+        .send(ExplorerToPlanet::AvailableEnergyCellRequest { explorer_id: 1 })
+        .unwrap();
+    thread::sleep(Duration::from_millis(100));
+
+    // The departure is the relay's business: the orchestrator hears nothing.
+    assert!(matches!(
+        send(OrchestratorToPlanet::InternalStateRequest),
+        PlanetToOrchestrator::InternalStateResponse { .. }
+    ));
+    drop(orch_tx);
+    let trip = handle.join().expect("Run loop panicked");
+    assert_eq!(trip.metrics().explorers, 0, "Explorer state was not freed");
+}
+
+#[test]
+fn test_planet_info_counts_rockets_built_by_sunrays() {
+    setup_logger();
+    let (orch_tx, orch_rx) = crossbeam_channel::unbounded();
+    let (planet_tx, planet_rx) = crossbeam_channel::unbounded();
+    let (_expl_tx, expl_rx) = crossbeam_channel::unbounded();
+
+    let mut trip = TripBuilder::new(0)
+        .ai_config(AiConfig {
+            rocket_reserve: 2,
+            auto_build: true,
+            ..AiConfig::default()
+        })
+        .channels(orch_rx, planet_tx, expl_rx)
+        .build()
+        .unwrap();
+    assert_eq!(trip.planet_info().rockets, 0);
+
+    let control = trip.handle();
+    let handle = thread::spawn(move || {
+        assert_eq!(trip.run().reason, Termination::ShutdownRequested);
+        trip
+    });
+    let send = |msg| {
+        orch_tx.send(msg).expect("Failed to send message");
+        planet_rx
+            .recv_timeout(Duration::from_millis(500))
+            .expect("No message received")
+    };
+    send(OrchestratorToPlanet::StartPlanetAI);
+    for _ in 0..2 {
+        send(OrchestratorToPlanet::Sunray(Sunray::default()));
+    }
+
+    control.shutdown();
+    let info = handle.join().expect("Run loop panicked").planet_info();
+    assert_eq!(info.rockets, 2);
+}
+
+#[test]
+fn test_default_config_is_reproducible() {
+    setup_logger();
+    let run = || {
+        let (orch_tx, orch_rx) = crossbeam_channel::unbounded();
+        let (planet_tx, planet_rx) = crossbeam_channel::unbounded();
+        let (_expl_tx, expl_rx) = crossbeam_channel::unbounded();
+        let mut trip = TripBuilder::new(0)
+            .ai_config(AiConfig::default())
+            .channels(orch_rx, planet_tx, expl_rx)
+            .build()
+            .unwrap();
+        orch_tx.send(OrchestratorToPlanet::StartPlanetAI).unwrap();
+        for step in 0..10 {
+            orch_tx
+                .send(OrchestratorToPlanet::Sunray(Sunray::default()))
+                .unwrap();
+            if step % 3 == 0 {
+                orch_tx
+                    .send(OrchestratorToPlanet::Asteroid(Asteroid::default()))
+                    .unwrap();
+            }
+            orch_tx
+                .send(OrchestratorToPlanet::InternalStateRequest)
+                .unwrap();
+        }
+        drop(orch_tx);
+        let _ = trip.run();
+        planet_rx
+            .try_iter()
+            .filter_map(|msg| match msg {
+                PlanetToOrchestrator::InternalStateResponse { planet_state, .. } => {
+                    Some(format!("{planet_state:?}"))
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+    };
+
+    let states = run();
+    assert_eq!(states.len(), 10);
+    assert_eq!(states, run());
+}
+
+#[test]
+fn test_builder_threads_the_rocket_policy_through() {
+    setup_logger();
+    let after_three_sunrays = |rocket_reserve, auto_build| {
+        let (orch_tx, orch_rx) = crossbeam_channel::unbounded();
+        let (planet_tx, planet_rx) = crossbeam_channel::unbounded();
+        let (_expl_tx, expl_rx) = crossbeam_channel::unbounded();
+        let mut trip = TripBuilder::new(0)
+            .ai_config(AiConfig {
+                rocket_reserve,
+                auto_build,
+                ..AiConfig::default()
+            })
+            .channels(orch_rx, planet_tx, expl_rx)
+            .build()
+            .unwrap();
+        for msg in [
+            OrchestratorToPlanet::StartPlanetAI,
+            OrchestratorToPlanet::Sunray(Sunray::default()),
+            OrchestratorToPlanet::Sunray(Sunray::default()),
+            OrchestratorToPlanet::Sunray(Sunray::default()),
+        ] {
+            orch_tx.send(msg).expect("Failed to send message");
+        }
+        drop(orch_tx);
+        let _ = trip.run();
+        assert_eq!(planet_rx.try_iter().count(), 4);
+        (trip.planet_info().rockets, trip.snapshot().charged_cells)
+    };
+
+    assert_eq!(after_three_sunrays(2, true), (2, 1), "Reserve of two");
+    assert_eq!(after_three_sunrays(1, true), (1, 2), "Default reserve");
+    assert_eq!(after_three_sunrays(2, false), (0, 3), "No auto-build");
+}
+
+#[test]
+fn test_planet_info_matches_explorer_answers() {
+    setup_logger();
+    let (orch_tx, orch_rx) = crossbeam_channel::unbounded();
+    let (planet_tx, planet_rx) = crossbeam_channel::unbounded();
+    let (expl_tx, expl_rx) = crossbeam_channel::unbounded();
+    let (pte_tx, pte_rx) = crossbeam_channel::unbounded();
+
+    let mut trip = trip(0, orch_rx, planet_tx, expl_rx).unwrap();
+    let control = trip.handle();
+    let handle = thread::spawn(move || {
+        assert_eq!(trip.run().reason, Termination::ShutdownRequested);
+        trip
+    });
+
+    let send = |msg| {
+        orch_tx.send(msg).expect("Failed to send message");
+        planet_rx
+            .recv_timeout(Duration::from_millis(500))
+            .expect("No message received")
+    };
+    send(OrchestratorToPlanet::StartPlanetAI);
+    send(IncomingExplorerRequest {
+        explorer_id: 1,
+        new_sender: pte_tx,
+    });
+    send(OrchestratorToPlanet::Sunray(Sunray::default()));
+    send(OrchestratorToPlanet::Sunray(Sunray::default()));
+
+    let ask = |msg| {
+        expl_tx.send(msg).expect("Failed to send explorer message");
+        pte_rx
+            .recv_timeout(Duration::from_millis(500))
+            .expect("No message received")
+    };
+    let PlanetToExplorer::SupportedResourceResponse { resource_list } =
+        ask(ExplorerToPlanet::SupportedResourceRequest { explorer_id: 1 })
+    else {
+        panic!("Wrong response received");
+    };
+    let PlanetToExplorer::SupportedCombinationResponse { combination_list } =
+        ask(ExplorerToPlanet::SupportedCombinationRequest { explorer_id: 1 })
+    else {
+        panic!("Wrong response received");
+    };
+    let PlanetToExplorer::AvailableEnergyCellResponse { available_cells } =
+        ask(ExplorerToPlanet::AvailableEnergyCellRequest { explorer_id: 1 })
+    else {
+        panic!("Wrong response received");
+    };
+
+    control.shutdown();
+    let trip = handle.join().expect("Run loop panicked");
+    assert_eq!(
+        trip.planet_info(),
+        PlanetInfo {
+            supported_resources: resource_list,
+            supported_combinations: combination_list,
+            available_cells,
+            total_cells: 5,
+            rockets: 1,
+        }
+    );
+    assert_eq!(
+        available_cells, 1,
+        "One of two charged cells became a rocket"
+    );
+}
+
+#[test]
+fn test_restart_keeps_counters_and_prunes_disconnected_explorers() {
+    setup_logger();
+    let (orch_tx, orch_rx) = crossbeam_channel::unbounded();
+    let (planet_tx, planet_rx) = crossbeam_channel::unbounded();
+    let (expl_tx, expl_rx) = crossbeam_channel::unbounded();
+    let (live_tx, live_rx) = crossbeam_channel::unbounded();
+    let (dead_tx, dead_rx) = crossbeam_channel::unbounded();
+
+    let mut trip = trip(0, orch_rx, planet_tx, expl_rx).unwrap();
+    let control = trip.handle();
+    let handle = thread::spawn(move || {
+        assert_eq!(trip.run().reason, Termination::ShutdownRequested);
+        trip
+    });
+
+    let send = |msg| {
+        orch_tx.send(msg).expect("Failed to send message");
+        planet_rx
+            .recv_timeout(Duration::from_millis(500))
+            .expect("No message received")
+    };
+    send(OrchestratorToPlanet::StartPlanetAI);
+    send(IncomingExplorerRequest {
+        explorer_id: 1,
+        new_sender: live_tx,
+    });
+    send(IncomingExplorerRequest {
+        explorer_id: 2,
+        new_sender: dead_tx,
+    });
+    send(OrchestratorToPlanet::Sunray(Sunray::default()));
+    drop(dead_rx);
+    for explorer_id in [2, 1] {
+        expl_tx
+            .send(ExplorerToPlanet::SupportedResourceRequest { explorer_id })
+            .expect("Failed to send explorer message");
+    }
+    assert!(live_rx.recv_timeout(Duration::from_millis(500)).is_ok());
+    // Let the relay fail to deliver the reply to the dead explorer.
+    thread::sleep(Duration::from_millis(50));
+
+    send(OrchestratorToPlanet::StopPlanetAI);
+    send(OrchestratorToPlanet::StartPlanetAI);
+    assert!(
+        matches!(
+            send(OrchestratorToPlanet::InternalStateRequest),
+            PlanetToOrchestrator::InternalStateResponse { .. }
+        ),
+        "Pruning must not reach the orchestrator"
+    );
+
+    expl_tx
+        .send(ExplorerToPlanet::AvailableEnergyCellRequest { explorer_id: 1 })
+        .expect("Failed to send explorer message");
+    assert!(live_rx.recv_timeout(Duration::from_millis(500)).is_ok());
+
+    control.shutdown();
+    let metrics = handle.join().expect("Planet thread panicked").metrics();
+    assert_eq!(metrics.explorers, 1, "The dead explorer must be pruned");
+    assert_eq!(metrics.sunrays, 1);
+    assert_eq!(metrics.explorers_served, 3);
+    assert_eq!(metrics.rockets_built, 1);
+    assert!(metrics.has_rocket, "The reserve must survive the restart");
+}
+
+#[test]
+fn test_shutdown_stops_run_loop() {
+    setup_logger();
+    let (orch_tx, orch_rx) = crossbeam_channel::unbounded();
+    let (planet_tx, planet_rx) = crossbeam_channel::unbounded();
+    let (_expl_tx, expl_rx) = crossbeam_channel::unbounded();
+
+    let mut trip = trip(0, orch_rx, planet_tx, expl_rx).unwrap();
+    let control = trip.handle();
+    let (done_tx, done_rx) = crossbeam_channel::bounded(1);
+    let handle = thread::spawn(move || done_tx.send(trip.run()));
+
+    orch_tx.send(OrchestratorToPlanet::StartPlanetAI).unwrap();
+    planet_rx.recv_timeout(Duration::from_millis(500)).unwrap();
+    control.shutdown();
+
+    let result = done_rx
+        .recv_timeout(Duration::from_secs(1))
+        .expect("Run loop must return after shutdown");
+    assert_eq!(result.reason, Termination::ShutdownRequested);
+    assert!(!control.is_running());
+    handle.join().unwrap().unwrap();
+    // The orchestrator still holds its sender: only the request stopped it.
+    drop(orch_tx);
+}
+
+#[test]
+fn test_stopped_planet_acks_sunrays_without_charging() {
+    setup_logger();
+    let (orch_tx, orch_rx) = crossbeam_channel::unbounded();
+    let (planet_tx, planet_rx) = crossbeam_channel::unbounded();
+    let (_expl_tx, expl_rx) = crossbeam_channel::unbounded();
+
+    let mut trip = TripBuilder::new(0)
+        .ack_sunrays_while_stopped(true)
+        .ai_config(AiConfig {
+            sunray_buffer_capacity: 0,
+            ..AiConfig::default()
+        })
+        .channels(orch_rx, planet_tx, expl_rx)
+        .build()
+        .unwrap();
+    let handle = thread::spawn(move || {
+        let result = trip.run();
+        (trip, result)
+    });
+    let send = |msg| {
+        orch_tx.send(msg).expect("Failed to send message");
+        planet_rx
+            .recv_timeout(Duration::from_millis(500))
+            .expect("No message received")
+    };
+
+    match send(OrchestratorToPlanet::Sunray(Sunray::default())) {
+        PlanetToOrchestrator::SunrayAck { planet_id: 0 } => {}
+        _other => panic!("Wrong response received"),
+    }
+    assert!(
+        matches!(
+            send(OrchestratorToPlanet::Asteroid(Asteroid::default())),
+            PlanetToOrchestrator::Stopped { planet_id: 0 }
+        ),
+        "Only sunrays are acknowledged while stopped"
+    );
+    send(OrchestratorToPlanet::StartPlanetAI);
+    match send(OrchestratorToPlanet::InternalStateRequest) {
+        PlanetToOrchestrator::InternalStateResponse { planet_state, .. } => {
+            assert_eq!(
+                planet_state.charged_cells_count, 0,
+                "No cell may be charged"
+            );
+            assert!(!planet_state.has_rocket);
+        }
+        _other => panic!("Wrong response received"),
+    }
+
+    drop(orch_tx);
+    let (trip, _) = handle.join().expect("Planet thread panicked");
+    assert_eq!(trip.metrics().sunrays, 0);
+    assert_eq!(trip.metrics().dropped_while_stopped.sunrays, 1);
+}
+
+#[test]
+fn test_messages_before_start_are_counted_as_dropped() {
+    setup_logger();
+    let (orch_tx, orch_rx) = crossbeam_channel::unbounded();
+    let (planet_tx, planet_rx) = crossbeam_channel::unbounded();
+    let (_expl_tx, expl_rx) = crossbeam_channel::unbounded();
+
+    let mut trip = trip(0, orch_rx, planet_tx, expl_rx).unwrap();
+    let control = trip.handle();
+    let handle = thread::spawn(move || {
+        let result = trip.run();
+        (trip, result)
+    });
+
+    let send = |msg| {
+        orch_tx.send(msg).expect("Failed to send message");
+        planet_rx
+            .recv_timeout(Duration::from_millis(500))
+            .expect("No message received")
+    };
+    for _ in 0..2 {
+        match send(OrchestratorToPlanet::Sunray(Sunray::default())) {
+            PlanetToOrchestrator::Stopped { planet_id: 0 } => {}
+            _other => panic!("Wrong response received"),
+        }
+    }
+    send(OrchestratorToPlanet::InternalStateRequest);
+    send(OrchestratorToPlanet::StartPlanetAI);
+    // Answered normally, so not counted.
+    send(OrchestratorToPlanet::Sunray(Sunray::default()));
+    // The two stopped sunrays were replayed on start, before this one.
+    send(OrchestratorToPlanet::StopPlanetAI);
+    send(OrchestratorToPlanet::Asteroid(Asteroid::default()));
+
+    control.shutdown();
+    let (trip, _) = handle.join().expect("Planet thread panicked");
+    assert_eq!(
+        trip.metrics().dropped_while_stopped,
+        DroppedWhileStopped {
+            sunrays: 2,
+            asteroids: 1,
+            internal_state_requests: 1,
+            ..DroppedWhileStopped::default()
+        }
+    );
+    assert_eq!(trip.metrics().sunrays, 3);
+}
+
+#[test]
+fn test_sunrays_while_stopped_charge_cells_after_start() {
+    setup_logger();
+    let (orch_tx, orch_rx) = crossbeam_channel::unbounded();
+    let (planet_tx, planet_rx) = crossbeam_channel::unbounded();
+    let (_expl_tx, expl_rx) = crossbeam_channel::unbounded();
+
+    let mut trip = TripBuilder::new(0)
+        .ai_config(AiConfig {
+            auto_build: false,
+            sunray_buffer_capacity: 2,
+            ..AiConfig::default()
+        })
+        .channels(orch_rx, planet_tx, expl_rx)
+        .build()
+        .unwrap();
+    let handle = thread::spawn(move || {
+        let result = trip.run();
+        (trip, result)
+    });
+    let send = |msg| {
+        orch_tx.send(msg).expect("Failed to send message");
+        planet_rx
+            .recv_timeout(Duration::from_millis(500))
+            .expect("No message received")
+    };
+
+    for _ in 0..3 {
+        assert!(matches!(
+            send(OrchestratorToPlanet::Sunray(Sunray::default())),
+            PlanetToOrchestrator::Stopped { planet_id: 0 }
+        ));
+    }
+    assert!(matches!(
+        send(OrchestratorToPlanet::StartPlanetAI),
+        PlanetToOrchestrator::StartPlanetAIResult { planet_id: 0 }
+    ));
+    match send(OrchestratorToPlanet::InternalStateRequest) {
+        PlanetToOrchestrator::InternalStateResponse { planet_state, .. } => {
+            assert_eq!(
+                planet_state.charged_cells_count, 2,
+                "Only the buffered sunrays charge a cell"
+            );
+        }
+        _other => panic!("Wrong response received"),
+    }
+    assert!(
+        planet_rx.try_recv().is_err(),
+        "Replayed sunrays must not be acknowledged"
+    );
+
+    drop(orch_tx);
+    let (trip, _) = handle.join().expect("Planet thread panicked");
+    assert_eq!(trip.metrics().sunrays, 2);
+    assert_eq!(trip.metrics().dropped_while_stopped.sunrays, 3);
+}
@@ -0,0 +1,132 @@
+//! Checks that the log lines of the AI handlers and of the relay can be
+//! attributed to their planet.
+//!
+//! Installs its own logger, so it lives in a test binary of its own.
+
+use common_game::components::asteroid::Asteroid;
+use common_game::components::resource::BasicResourceType;
+use common_game::components::sunray::Sunray;
+use common_game::protocols::orchestrator_planet::OrchestratorToPlanet;
+use common_game::protocols::planet_explorer::ExplorerToPlanet;
+use log::{LevelFilter, Log, Metadata, Record};
+use std::sync::{Mutex, Once};
+use std::thread;
+use std::time::Duration;
+use trip::{TripBuilder, trip};
+
+/// Logger keeping the target and text of every record.
+struct Capture(Mutex<Vec<(String, String)>>);
+
+impl Log for Capture {
+    fn enabled(&self, _: &Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &Record) {
+        self.0
+            .lock()
+            .unwrap()
+            .push((record.target().to_owned(), record.args().to_string()));
+    }
+
+    fn flush(&self) {}
+}
+
+static CAPTURE: Capture = Capture(Mutex::new(Vec::new()));
+
+static INIT: Once = Once::new();
+
+/// Installs [`CAPTURE`], once for all the tests of this binary.
+fn install_capture() {
+    INIT.call_once(|| {
+        log::set_logger(&CAPTURE).expect("No other logger in this binary");
+        log::set_max_level(LevelFilter::Trace);
+    });
+}
+
+#[test]
+fn test_handler_logs_carry_planet_id() {
+    install_capture();
+
+    let (orch_tx, orch_rx) = crossbeam_channel::unbounded();
+    let (planet_tx, planet_rx) = crossbeam_channel::unbounded();
+    let (expl_tx, expl_rx) = crossbeam_channel::unbounded();
+    let (pte_tx, pte_rx) = crossbeam_channel::unbounded();
+
+    let mut trip = trip(7, orch_rx, planet_tx, expl_rx).unwrap();
+    let handle = thread::spawn(move || trip.run());
+
+    let send = |msg| {
+        orch_tx.send(msg).expect("Failed to send message");
+        planet_rx
+            .recv_timeout(Duration::from_millis(500))
+            .expect("No message received");
+    };
+    send(OrchestratorToPlanet::StartPlanetAI);
+    send(OrchestratorToPlanet::IncomingExplorerRequest {
+        explorer_id: 3,
+        new_sender: pte_tx,
+    });
+    send(OrchestratorToPlanet::Sunray(Sunray::default()));
+    send(OrchestratorToPlanet::Sunray(Sunray::default()));
+    expl_tx
+        .send(ExplorerToPlanet::GenerateResourceRequest {
+            explorer_id: 3,
+            resource: BasicResourceType::Oxygen,
+        })
+        .expect("Failed to send explorer message");
+    pte_rx
+        .recv_timeout(Duration::from_millis(500))
+        .expect("No message received");
+    send(OrchestratorToPlanet::Asteroid(Asteroid::default()));
+    send(OrchestratorToPlanet::InternalStateRequest);
+    send(OrchestratorToPlanet::StopPlanetAI);
+    drop(orch_tx);
+    assert!(handle.join().is_ok());
+
+    let records = CAPTURE.0.lock().unwrap();
+    let handler_lines: Vec<&str> = records
+        .iter()
+        .filter(|(target, _)| target.starts_with("trip::ai") || target.starts_with("trip::guard"))
+        .map(|(_, line)| line.as_str())
+        .collect();
+    assert!(!handler_lines.is_empty(), "Handlers must log");
+    for line in &handler_lines {
+        assert!(
+            line.contains("planet_id=7"),
+            "Unattributed log line: {line}"
+        );
+    }
+    assert!(
+        handler_lines
+            .iter()
+            .any(|line| line.contains("explorer_id=3")),
+        "Explorer requests must name their explorer"
+    );
+}
+
+#[test]
+fn test_idle_planet_warns_with_planet_id() {
+    install_capture();
+
+    let (orch_tx, orch_rx) = crossbeam_channel::unbounded();
+    let (planet_tx, _planet_rx) = crossbeam_channel::unbounded();
+    let (_expl_tx, expl_rx) = crossbeam_channel::unbounded();
+    let mut trip = TripBuilder::new(9)
+        .idle_warning(Duration::from_millis(20))
+        .channels(orch_rx, planet_tx, expl_rx)
+        .build()
+        .unwrap();
+    let handle = thread::spawn(move || trip.run());
+
+    thread::sleep(Duration::from_millis(200));
+    drop(orch_tx);
+    assert!(handle.join().is_ok());
+
+    let records = CAPTURE.0.lock().unwrap();
+    let warnings = records
+        .iter()
+        .filter(|(_, line)| line.starts_with("planet_id=9 no message for"))
+        .count();
+    assert_eq!(warnings, 1, "One warning per idle stretch");
+}